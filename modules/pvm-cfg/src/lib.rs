@@ -20,4 +20,5 @@ pub struct Config {
     pub db_password: String,
     pub suppress_default_views: bool,
     pub cfg_detail: Option<AdvancedConfig>,
+    pub incremental: bool,
 }
@@ -120,7 +120,7 @@ impl HasID for CtxNode {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum PVMDataType {
     Actor,
     Store,
@@ -208,13 +208,13 @@ impl DataNode {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Hash, PartialEq, Serialize)]
 pub enum Name {
     Path(String),
     Net(String, u16),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum NameNode {
     Path(ID, String),
     Net(ID, String, u16),
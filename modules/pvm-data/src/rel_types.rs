@@ -1,6 +1,6 @@
 use {Denumerate, Enumerable, HasDst, HasID, HasSrc, RelGenerable, ID};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum PVMOps {
     Source,
     Sink,
@@ -9,7 +9,7 @@ pub enum PVMOps {
     Unknown,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Inf {
     id: ID,
     src: ID,
@@ -59,7 +59,7 @@ impl RelGenerable for Inf {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Named {
     id: ID,
     src: ID,
@@ -106,7 +106,7 @@ impl RelGenerable for Named {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Rel {
     Inf(Inf),
     Named(Named),
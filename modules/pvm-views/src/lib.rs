@@ -5,8 +5,12 @@ use std::{
     any::Any,
     collections::HashMap,
     fmt::Debug,
-    sync::{mpsc, Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
     thread::{spawn, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use data::{node_types::Node, rel_types::Rel};
@@ -19,6 +23,133 @@ pub enum DBTr {
     CreateRel(Rel),
     UpdateNode(Node),
     UpdateRel(Rel),
+    // Every view sends its id back once the updates before this one are
+    // applied and stored.
+    Sync(mpsc::SyncSender<usize>),
+}
+
+#[derive(Debug, Default)]
+struct QueueCounters {
+    sent: AtomicUsize,
+    received: AtomicUsize,
+    blocked_ns: AtomicUsize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QueueStats(Arc<QueueCounters>);
+
+impl QueueStats {
+    pub fn sent(&self) -> usize {
+        self.0.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn received(&self) -> usize {
+        self.0.received.load(Ordering::Relaxed)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.sent().saturating_sub(self.received())
+    }
+
+    pub fn blocked(&self) -> Duration {
+        let ns = self.0.blocked_ns.load(Ordering::Relaxed) as u64;
+        Duration::new(ns / 1_000_000_000, (ns % 1_000_000_000) as u32)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueSender<T> {
+    send: mpsc::SyncSender<T>,
+    stats: QueueStats,
+}
+
+impl<T> QueueSender<T> {
+    pub fn send(&self, t: T) -> Result<(), mpsc::SendError<T>> {
+        let c = &self.stats.0;
+        c.sent.fetch_add(1, Ordering::Relaxed);
+        let ret = match self.send.try_send(t) {
+            Ok(()) => Ok(()),
+            Err(mpsc::TrySendError::Full(t)) => {
+                let now = Instant::now();
+                let ret = self.send.send(t);
+                let dur = now.elapsed();
+                c.blocked_ns.fetch_add(
+                    (dur.as_secs() * 1_000_000_000 + u64::from(dur.subsec_nanos())) as usize,
+                    Ordering::Relaxed,
+                );
+                ret
+            }
+            Err(mpsc::TrySendError::Disconnected(t)) => Err(mpsc::SendError(t)),
+        };
+        if ret.is_err() {
+            c.sent.fetch_sub(1, Ordering::Relaxed);
+        }
+        ret
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.stats.clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueReceiver<T> {
+    recv: mpsc::Receiver<T>,
+    stats: QueueStats,
+}
+
+impl<T> QueueReceiver<T> {
+    pub fn recv(&self) -> Result<T, mpsc::RecvError> {
+        let t = self.recv.recv()?;
+        self.stats.0.received.fetch_add(1, Ordering::Relaxed);
+        Ok(t)
+    }
+
+    pub fn try_iter<'a>(&'a self) -> impl Iterator<Item = T> + 'a {
+        self.recv.try_iter().inspect(move |_| {
+            self.stats.0.received.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.stats.clone()
+    }
+}
+
+impl<T> Iterator for QueueReceiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+pub fn queue<T>(bound: usize) -> (QueueSender<T>, QueueReceiver<T>) {
+    let (send, recv) = mpsc::sync_channel(bound);
+    let stats = QueueStats::default();
+    (
+        QueueSender {
+            send,
+            stats: stats.clone(),
+        },
+        QueueReceiver { recv, stats },
+    )
+}
+
+#[derive(Clone, Debug)]
+pub struct Queues {
+    input: QueueStats,
+    views: Arc<Mutex<Vec<(usize, QueueStats)>>>,
+}
+
+impl Queues {
+    pub fn input(&self) -> &QueueStats {
+        &self.input
+    }
+
+    pub fn views(&self) -> Vec<(usize, QueueStats)> {
+        self.views.lock().unwrap().clone()
+    }
 }
 
 pub type ViewParams = HashMap<String, Box<Any>>;
@@ -72,7 +203,7 @@ pub trait View: Debug {
         id: usize,
         params: ViewParams,
         cfg: &Config,
-        stream: mpsc::Receiver<Arc<DBTr>>,
+        stream: QueueReceiver<Arc<DBTr>>,
     ) -> ViewInst;
 }
 
@@ -80,17 +211,21 @@ pub trait View: Debug {
 pub struct ViewCoordinator {
     views: HashMap<usize, Box<View>>,
     insts: Vec<ViewInst>,
-    streams: Arc<Mutex<Vec<mpsc::SyncSender<Arc<DBTr>>>>>,
+    streams: Arc<Mutex<Vec<QueueSender<Arc<DBTr>>>>>,
+    queues: Queues,
     thread: JoinHandle<()>,
     vid_gen: usize,
     viid_gen: usize,
 }
 
 impl ViewCoordinator {
-    pub fn new(recv: mpsc::Receiver<DBTr>) -> Self {
-        let streams: Arc<Mutex<Vec<mpsc::SyncSender<Arc<DBTr>>>>> =
-            Arc::new(Mutex::new(Vec::new()));
+    pub fn new(recv: QueueReceiver<DBTr>) -> Self {
+        let streams: Arc<Mutex<Vec<QueueSender<Arc<DBTr>>>>> = Arc::new(Mutex::new(Vec::new()));
         let thread_streams = streams.clone();
+        let queues = Queues {
+            input: recv.stats(),
+            views: Arc::new(Mutex::new(Vec::new())),
+        };
         ViewCoordinator {
             thread: spawn(move || {
                 for evt in recv {
@@ -106,6 +241,7 @@ impl ViewCoordinator {
             views: HashMap::new(),
             insts: Vec::new(),
             streams,
+            queues,
             vid_gen: 0,
             viid_gen: 0,
         }
@@ -130,13 +266,18 @@ impl ViewCoordinator {
     pub fn create_view_inst(&mut self, id: usize, params: ViewParams, cfg: &Config) -> usize {
         let iid = self.viid_gen;
         self.viid_gen += 1;
-        let (w, r) = mpsc::sync_channel(1000);
+        let (w, r) = queue(1000);
+        self.queues.views.lock().unwrap().push((iid, r.stats()));
         let view = self.views[&id].create(iid, params, cfg, r);
         self.insts.push(view);
         self.streams.lock().unwrap().push(w);
         iid
     }
 
+    pub fn queues(&self) -> Queues {
+        self.queues.clone()
+    }
+
     pub fn shutdown(self) {
         self.thread.join().unwrap();
         self.streams.lock().unwrap().clear();
@@ -0,0 +1,359 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str,
+    sync::{Arc, Mutex},
+};
+
+use bzip2::read::BzDecoder;
+use flate2::read::DeflateDecoder;
+use zip::ZipArchive;
+
+use iostream::IOStream;
+
+const BLOCK: usize = 512;
+
+pub type Member = (String, IOStream);
+
+fn invalid<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn fill<R: Read>(src: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match src.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    start: u64,
+    size: u64,
+}
+
+struct TarState {
+    src: Box<Read + Send>,
+    remaining: u64,
+    pad: u64,
+    done: bool,
+}
+
+struct TarMember(Arc<Mutex<TarState>>);
+
+impl Read for TarMember {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut st = self.0.lock().unwrap();
+        if st.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = st.remaining.min(buf.len() as u64) as usize;
+        let n = st.src.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated tar member",
+            ));
+        }
+        st.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+fn tar_size(field: &[u8]) -> io::Result<u64> {
+    // GNU tar stores sizes that do not fit in octal as base-256.
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |acc, b| {
+                (acc << 8) | u64::from(*b)
+            }));
+    }
+    let s = str::from_utf8(field).map_err(invalid)?;
+    let s = s.trim_matches(|c| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(s, 8).map_err(invalid)
+}
+
+fn tar_str(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn skip<R: Read>(src: &mut R, n: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut src.take(n), &mut io::sink())?;
+    if skipped < n {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Truncated tar archive",
+        ));
+    }
+    Ok(())
+}
+
+fn next_tar(state: &Arc<Mutex<TarState>>) -> io::Result<Option<Member>> {
+    let mut st = state.lock().unwrap();
+    let st = &mut *st;
+    if st.done {
+        return Ok(None);
+    }
+    skip(&mut st.src, st.remaining + st.pad)?;
+    st.remaining = 0;
+    st.pad = 0;
+    let mut long_name = None;
+    loop {
+        let mut hdr = [0; BLOCK];
+        let len = fill(&mut st.src, &mut hdr)?;
+        if len == 0 || hdr.iter().all(|b| *b == 0) {
+            st.done = true;
+            return Ok(None);
+        }
+        if len < BLOCK {
+            return Err(invalid("Truncated tar header"));
+        }
+        let size = tar_size(&hdr[124..136])?;
+        let pad = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+        match hdr[156] {
+            b'0' | b'7' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let prefix = tar_str(&hdr[345..500]);
+                    if &hdr[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, tar_str(&hdr[..100]))
+                    } else {
+                        tar_str(&hdr[..100])
+                    }
+                });
+                st.remaining = size;
+                st.pad = pad;
+                let member = TarMember(state.clone());
+                return Ok(Some((name, IOStream::from_read(member))));
+            }
+            b'L' => {
+                let mut name = Vec::new();
+                (&mut st.src).take(size).read_to_end(&mut name)?;
+                skip(&mut st.src, pad)?;
+                long_name = Some(tar_str(&name));
+            }
+            _ => skip(&mut st.src, size + pad)?,
+        }
+    }
+}
+
+enum Kind {
+    Single(Option<Member>),
+    Zip {
+        path: PathBuf,
+        entries: Vec<ZipEntry>,
+        next: usize,
+    },
+    Tar(Arc<Mutex<TarState>>),
+}
+
+pub struct Archive {
+    kind: Kind,
+}
+
+impl Archive {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Archive> {
+        let path = path.as_ref();
+        let mut magic = [0; 4];
+        let len = fill(&mut File::open(path)?, &mut magic)?;
+        if len == 4 && magic == *b"PK\x03\x04" {
+            return Archive::zip(path);
+        }
+        Archive::from_stream(path.display().to_string(), IOStream::open(path)?)
+    }
+
+    pub fn from_stream(name: String, mut stream: IOStream) -> io::Result<Archive> {
+        let mut block = vec![0; BLOCK];
+        let len = fill(&mut stream, &mut block)?;
+        block.truncate(len);
+        if block.starts_with(b"PK\x03\x04") {
+            return Err(invalid("Zip archives can only be read from a file"));
+        }
+        let tar = len == BLOCK && &block[257..262] == b"ustar";
+        let src = Cursor::new(block).chain(stream);
+        let kind = if tar {
+            Kind::Tar(Arc::new(Mutex::new(TarState {
+                src: Box::new(src),
+                remaining: 0,
+                pad: 0,
+                done: false,
+            })))
+        } else {
+            Kind::Single(Some((name, IOStream::from_read_raw(src))))
+        };
+        Ok(Archive { kind })
+    }
+
+    // Tar members share one underlying stream, so each has to be read to the
+    // end before the next is taken.
+    pub fn is_sequential(&self) -> bool {
+        match self.kind {
+            Kind::Tar(_) => true,
+            _ => false,
+        }
+    }
+
+    fn zip(path: &Path) -> io::Result<Archive> {
+        let mut zip = ZipArchive::new(File::open(path)?).map_err(invalid)?;
+        let mut entries = Vec::new();
+        for i in 0..zip.len() {
+            let f = zip.by_index(i).map_err(invalid)?;
+            if f.name().ends_with('/') {
+                continue;
+            }
+            entries.push(ZipEntry {
+                name: f.name().to_string(),
+                method: f.compression().to_u16(),
+                start: f.data_start(),
+                size: f.compressed_size(),
+            });
+        }
+        Ok(Archive {
+            kind: Kind::Zip {
+                path: path.to_path_buf(),
+                entries,
+                next: 0,
+            },
+        })
+    }
+
+    fn zip_member(path: &Path, entry: &ZipEntry) -> io::Result<Member> {
+        let mut f = File::open(path)?;
+        f.seek(SeekFrom::Start(entry.start))?;
+        let data = f.take(entry.size);
+        let src: Box<Read + Send> = match entry.method {
+            0 => Box::new(data),
+            8 => Box::new(DeflateDecoder::new(data)),
+            12 => Box::new(BzDecoder::new(data)),
+            m => return Err(invalid(format!("Unsupported zip compression method {}", m))),
+        };
+        Ok((entry.name.clone(), IOStream::from_read(src)))
+    }
+}
+
+impl Iterator for Archive {
+    type Item = io::Result<Member>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.kind {
+            Kind::Single(ref mut member) => member.take().map(Ok),
+            Kind::Zip {
+                ref path,
+                ref entries,
+                ref mut next,
+            } => {
+                let entry = entries.get(*next)?;
+                *next += 1;
+                Some(Archive::zip_member(path, entry))
+            }
+            Kind::Tar(ref state) => match next_tar(state) {
+                Ok(member) => member.map(Ok),
+                Err(e) => {
+                    state.lock().unwrap().done = true;
+                    Some(Err(e))
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs, io::Write};
+
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn tar_entry(out: &mut Vec<u8>, ty: u8, name: &str, data: &[u8]) {
+        let mut hdr = [0; BLOCK];
+        hdr[..name.len()].copy_from_slice(name.as_bytes());
+        hdr[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        hdr[156] = ty;
+        hdr[257..263].copy_from_slice(b"ustar\0");
+        out.extend_from_slice(&hdr);
+        out.extend_from_slice(data);
+        let pad = (BLOCK - data.len() % BLOCK) % BLOCK;
+        out.extend(vec![0; pad]);
+    }
+
+    fn contents(archive: Archive) -> Vec<(String, String)> {
+        archive
+            .map(|m| {
+                let (name, mut stream) = m.unwrap();
+                let mut s = String::new();
+                stream.read_to_string(&mut s).unwrap();
+                (name, s)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tar_members() {
+        let mut tar = Vec::new();
+        tar_entry(&mut tar, b'5', "traces/", b"");
+        tar_entry(&mut tar, b'0', "traces/a.json", b"a\n");
+        tar_entry(&mut tar, b'L', "././@LongLink", b"traces/long-name.json\0");
+        tar_entry(&mut tar, b'0', "traces/long-na", b"bb\n");
+        tar.extend(vec![0; BLOCK * 2]);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&tar).unwrap();
+        let archive = Archive::from_stream(
+            "t".to_string(),
+            IOStream::from_read(Cursor::new(gz.finish().unwrap())),
+        ).unwrap();
+        assert_eq!(
+            contents(archive),
+            vec![
+                ("traces/a.json".to_string(), "a\n".to_string()),
+                ("traces/long-name.json".to_string(), "bb\n".to_string()),
+            ]
+        );
+
+        let plain =
+            Archive::from_stream("plain".to_string(), IOStream::from_read(Cursor::new("x\n")))
+                .unwrap();
+        assert_eq!(
+            contents(plain),
+            vec![("plain".to_string(), "x\n".to_string())]
+        );
+    }
+
+    #[test]
+    fn zip_members() {
+        let path = env::temp_dir().join(format!("opus-archive-{}.zip", ::std::process::id()));
+        {
+            let mut zip = ZipWriter::new(File::create(&path).unwrap());
+            zip.start_file("a.json", FileOptions::default()).unwrap();
+            zip.write_all(b"a\n").unwrap();
+            zip.start_file(
+                "b.json",
+                FileOptions::default().compression_method(::zip::CompressionMethod::Stored),
+            ).unwrap();
+            zip.write_all(b"b\n").unwrap();
+            zip.finish().unwrap();
+        }
+        let members = contents(Archive::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            members,
+            vec![
+                ("a.json".to_string(), "a\n".to_string()),
+                ("b.json".to_string(), "b\n".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+extern crate clap;
+extern crate libc;
+extern crate opus;
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fs::File,
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use opus::{
+    archive::Archive,
+    cfg::{CfgMode, Config},
+    engine::Engine,
+    ingest::{ErrorPolicy, IngestReport},
+    iostream::IOStream,
+    views::ViewParams,
+};
+
+fn db_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("db-server")
+            .long("db-server")
+            .takes_value(true)
+            .default_value("localhost:7687")
+            .help("The Neo4j server address to connect to"),
+    ).arg(
+        Arg::with_name("db-user")
+            .long("db-user")
+            .takes_value(true)
+            .default_value("neo4j")
+            .help("The username to auth with"),
+    ).arg(
+        Arg::with_name("db-password")
+            .long("db-password")
+            .takes_value(true)
+            .default_value("opus")
+            .help("The password to auth with"),
+    )
+}
+
+fn ingest_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("view")
+            .long("view")
+            .short("v")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("NAME[:KEY=VAL,...]")
+            .help("Create an instance of the named view with the given parameters"),
+    ).arg(
+        Arg::with_name("max-errors")
+            .long("max-errors")
+            .takes_value(true)
+            .value_name("N")
+            .help("Abort the ingest after N malformed records"),
+    ).arg(
+        Arg::with_name("format")
+            .long("format")
+            .short("f")
+            .takes_value(true)
+            .help("The trace format to parse, detected from the input by default"),
+    ).arg(
+        Arg::with_name("metrics")
+            .long("metrics")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Write pipeline metrics to FILE as JSON once the ingest finishes"),
+    ).arg(
+        Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Save the PVM state and input position to FILE when the ingest stops"),
+    ).arg(
+        Arg::with_name("resume")
+            .long("resume")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Restore a checkpoint and skip the records of the first trace it already covers"),
+    ).arg(
+        Arg::with_name("merge")
+            .long("merge")
+            .help("Ingest all traces at once, interleaving their records by event time"),
+    ).arg(
+        Arg::with_name("reorder-window")
+            .long("reorder-window")
+            .takes_value(true)
+            .value_name("RECORDS")
+            .requires("merge")
+            .help("Records buffered per trace to sort them back into order when merging"),
+    ).arg(
+        Arg::with_name("listen")
+            .long("listen")
+            .takes_value(true)
+            .value_name("ADDR")
+            .conflicts_with_all(&["trace", "merge"])
+            .help("Ingest records sent to a tcp://, udp://, unix:// or unixgram:// address until interrupted"),
+    ).arg(
+        Arg::with_name("trace")
+            .required_unless("listen")
+            .multiple(true)
+            .help("Trace files or zip/tar archives of them to ingest, use - for stdin"),
+    )
+}
+
+fn cli<'a, 'b>() -> App<'a, 'b> {
+    App::new("pvm")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Provenance Virtual Machine trace ingestion")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(ingest_args(db_args(
+            SubCommand::with_name("ingest")
+                .about("Ingest traces into a Neo4j database")
+                .arg(
+                    Arg::with_name("no-default-views")
+                        .long("no-default-views")
+                        .help("Do not start the default Neo4j view"),
+                ).arg(
+                    Arg::with_name("incremental")
+                        .long("incremental")
+                        .help("Append to the existing graph, continuing its IDs and objects"),
+                ),
+        )))
+        .subcommand(ingest_args(
+            SubCommand::with_name("csv")
+                .about("Ingest traces into a zip of CSV files for neo4j-admin import")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .default_value("./prov_csv.zip")
+                        .help("The zip file to write the csv data to"),
+                ),
+        ))
+        .subcommand(SubCommand::with_name("views").about("List the available view types"))
+        .subcommand(SubCommand::with_name("formats").about("List the supported trace formats"))
+        .subcommand(db_args(
+            SubCommand::with_name("count").about("Count the processes stored in a Neo4j database"),
+        ))
+}
+
+fn config(args: &ArgMatches, suppress_default_views: bool) -> Config {
+    Config {
+        cfg_mode: CfgMode::Auto,
+        db_server: args.value_of("db-server").unwrap_or("").to_string(),
+        db_user: args.value_of("db-user").unwrap_or("").to_string(),
+        db_password: args.value_of("db-password").unwrap_or("").to_string(),
+        suppress_default_views,
+        cfg_detail: None,
+        incremental: args.is_present("incremental"),
+    }
+}
+
+fn parse_view_spec(spec: &str) -> Result<(&str, ViewParams), String> {
+    let mut parts = spec.splitn(2, ':');
+    let name = parts.next().unwrap();
+    let mut params = HashMap::new();
+    if let Some(plist) = parts.next() {
+        for p in plist.split(',').filter(|p| !p.is_empty()) {
+            let mut kv = p.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => {
+                    params.insert(k.to_string(), Box::new(v.to_string()) as Box<Any>);
+                }
+                _ => return Err(format!("Malformed view parameter: {}", p)),
+            }
+        }
+    }
+    Ok((name, params))
+}
+
+fn create_view(engine: &mut Engine, name: &str, params: ViewParams) -> Result<usize, String> {
+    let ids: Vec<usize> = engine
+        .list_view_types()?
+        .into_iter()
+        .filter(|v| v.name() == name)
+        .map(|v| v.id())
+        .collect();
+    match ids.len() {
+        0 => Err(format!("Unknown view: {}", name)),
+        1 => engine
+            .create_view_by_id(ids[0], params)
+            .map_err(|e| e.into_owned()),
+        _ => Err(format!("Ambiguous view name: {}", name)),
+    }
+}
+
+fn open_trace(path: &str) -> Result<Archive, String> {
+    if path == "-" {
+        Archive::from_stream(path.to_string(), IOStream::stdin())
+    } else {
+        Archive::open(path)
+    }.map_err(|e| format!("Cannot open {}: {}", path, e))
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+fn listen(engine: &mut Engine, addr: &str, fmt: Option<&str>) -> Result<IngestReport, String> {
+    let job = engine.ingest_listen(addr, fmt)?;
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+    eprintln!("Listening on {}, interrupt to stop", addr);
+    while !job.is_finished() {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            job.cancel();
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(job.wait()?)
+}
+
+fn finish(engine: &mut Engine, args: &ArgMatches) -> Result<(), String> {
+    if let Some(path) = args.value_of("checkpoint") {
+        engine
+            .checkpoint(path)
+            .map_err(|e| format!("Cannot write checkpoint {}: {}", path, e))?;
+    }
+    engine.shutdown_pipeline()?;
+    if let Some(path) = args.value_of("metrics") {
+        let f = File::create(path).map_err(|e| format!("Cannot create {}: {}", path, e))?;
+        engine
+            .metrics()
+            .dump(f)
+            .map_err(|e| format!("Cannot write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn ingest(engine: &mut Engine, args: &ArgMatches) -> Result<(), String> {
+    if let Some(n) = args.value_of("max-errors") {
+        let n = n
+            .parse()
+            .map_err(|_| format!("Invalid error limit: {}", n))?;
+        engine.set_error_policy(ErrorPolicy::AbortAfter(n));
+    }
+    if let Some(specs) = args.values_of("view") {
+        for spec in specs {
+            let (name, params) = parse_view_spec(spec)?;
+            create_view(engine, name, params)?;
+        }
+    }
+    if let Some(path) = args.value_of("resume") {
+        let offset = engine
+            .restore(path)
+            .map_err(|e| format!("Cannot restore checkpoint {}: {}", path, e))?;
+        eprintln!(
+            "Resuming after record {} of input {}",
+            offset,
+            engine.resume_input() + 1
+        );
+    }
+    if let Some(addr) = args.value_of("listen") {
+        let report = listen(engine, addr, args.value_of("format"))?;
+        eprintln!("{}", report);
+        if report.aborted {
+            finish(engine, args)?;
+            return Err(format!("Ingest from {} aborted", addr));
+        }
+    }
+    if args.is_present("merge") {
+        if let Some(n) = args.value_of("reorder-window") {
+            let n = n
+                .parse()
+                .map_err(|_| format!("Invalid reorder window: {}", n))?;
+            engine.set_reorder_window(n);
+        }
+        let mut streams = Vec::new();
+        for path in args.values_of("trace").unwrap() {
+            let archive = open_trace(path)?;
+            if archive.is_sequential() {
+                return Err(format!("Cannot merge the members of tar archive {}", path));
+            }
+            for member in archive {
+                let (name, stream) = member.map_err(|e| format!("Cannot read {}: {}", path, e))?;
+                eprintln!("Merging {}", name);
+                streams.push(stream);
+            }
+        }
+        let report = engine.ingest_streams(streams, args.value_of("format"))?;
+        eprintln!("{}", report);
+        if report.aborted {
+            finish(engine, args)?;
+            return Err("Merged ingest aborted".to_string());
+        }
+        return finish(engine, args);
+    }
+    for path in args.values_of("trace").into_iter().flatten() {
+        for member in open_trace(path)? {
+            let (name, stream) = member.map_err(|e| format!("Cannot read {}: {}", path, e))?;
+            if name != path {
+                eprintln!("Ingesting {} from {}", name, path);
+            }
+            let report = engine.ingest_stream_fmt(stream, args.value_of("format"))?;
+            eprintln!("{}", report);
+            if report.aborted {
+                finish(engine, args)?;
+                return Err(format!("Ingest of {} aborted", name));
+            }
+        }
+    }
+    finish(engine, args)
+}
+
+fn run(args: &ArgMatches) -> Result<(), String> {
+    match args.subcommand() {
+        ("ingest", Some(sub)) => {
+            let mut engine = Engine::new(config(sub, sub.is_present("no-default-views")));
+            engine.init_pipeline()?;
+            ingest(&mut engine, sub)
+        }
+        ("csv", Some(sub)) => {
+            let mut engine = Engine::new(config(sub, true));
+            engine.init_pipeline()?;
+            let mut params: ViewParams = HashMap::new();
+            params.insert(
+                "path".to_string(),
+                Box::new(sub.value_of("output").unwrap().to_string()) as Box<Any>,
+            );
+            create_view(&mut engine, "CSVView", params)?;
+            ingest(&mut engine, sub)
+        }
+        ("views", Some(sub)) => {
+            let mut engine = Engine::new(config(sub, true));
+            engine.init_pipeline()?;
+            for view in engine.list_view_types()? {
+                println!("{}: {}", view.name(), view.desc());
+                for (k, v) in view.params() {
+                    println!("    {}: {}", k, v);
+                }
+            }
+            engine.shutdown_pipeline()?;
+            Ok(())
+        }
+        ("formats", Some(sub)) => {
+            let engine = Engine::new(config(sub, true));
+            for fmt in engine.list_formats() {
+                println!("{}: {}", fmt.name(), fmt.desc());
+            }
+            Ok(())
+        }
+        ("count", Some(sub)) => {
+            let engine = Engine::new(config(sub, true));
+            println!("Number of processes: {}", engine.count_processes()?);
+            Ok(())
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn main() {
+    let args = cli().get_matches();
+    if let Err(e) = run(&args) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
@@ -1,17 +1,33 @@
-use iostream::IOStream;
-use libc::{c_char, malloc};
+use graph::{Direction, Graph, GraphRef};
+use ingest::{
+    self, ErrorSink, IngestError, IngestErrorKind, IngestHandle, IngestReport, StderrSink,
+};
+use iostream::{IOStream, ListenAddr};
+use libc::{c_char, c_void, free, malloc};
 
 use std::{
     any::Any,
+    borrow::Cow,
+    cell::RefCell,
     collections::HashMap,
-    ffi::CStr,
+    ffi::{CStr, CString},
     mem::size_of,
     os::unix::io::{FromRawFd, RawFd},
+    panic::{self, AssertUnwindSafe},
     ptr, slice,
+    sync::{Mutex, MutexGuard, PoisonError, RwLockReadGuard},
 };
 
 use cfg::{self, AdvancedConfig, CfgMode};
+use chrono::{DateTime, TimeZone, Utc};
+use data::{node_types::Name, ID};
 use engine;
+use query::{
+    lineage::{Lineage, LineageQuery},
+    record::{NodeKind, NodeRecord, RelKind, RelRecord},
+    scope::TimeWindow,
+};
+use uuid::Uuid;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -20,12 +36,89 @@ pub enum OpusErr {
     EAMBIGUOUSVIEWNAME = 2,
     ENOVIEWWITHNAME = 3,
     EINVALIDARG = 4,
+    EINGESTABORTED = 5,
+    EUNKNOWNFORMAT = 6,
+    EPANIC = 7,
+    EDATABASE = 8,
 }
 
 fn ret(err: OpusErr) -> isize {
     -(err as isize)
 }
 
+type FfiResult<T> = Result<T, (OpusErr, Cow<'static, str>)>;
+
+fn fail<T, M: Into<Cow<'static, str>>>(code: OpusErr, msg: M) -> FfiResult<T> {
+    Err((code, msg.into()))
+}
+
+trait FfiRet {
+    fn from_err(err: OpusErr) -> Self;
+}
+
+impl FfiRet for isize {
+    fn from_err(err: OpusErr) -> Self {
+        ret(err)
+    }
+}
+
+impl FfiRet for i64 {
+    fn from_err(err: OpusErr) -> Self {
+        -(err as i64)
+    }
+}
+
+impl<T> FfiRet for *mut T {
+    fn from_err(_: OpusErr) -> Self {
+        ptr::null_mut()
+    }
+}
+
+impl FfiRet for () {
+    fn from_err(_: OpusErr) -> Self {}
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn panic_msg(payload: &(Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        format!("panic: {}", s)
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        format!("panic: {}", s)
+    } else {
+        "panic: unknown cause".to_string()
+    }
+}
+
+fn guard<T: FfiRet, F: FnOnce() -> FfiResult<T>>(f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => v,
+        Ok(Err((code, msg))) => {
+            set_last_error(&msg);
+            T::from_err(code)
+        }
+        Err(payload) => {
+            set_last_error(&panic_msg(&*payload));
+            T::from_err(OpusErr::EPANIC)
+        }
+    }
+}
+
+fn unknown(e: Cow<'static, str>) -> (OpusErr, Cow<'static, str>) {
+    (OpusErr::EUNKNOWN, e)
+}
+
+fn database(e: Cow<'static, str>) -> (OpusErr, Cow<'static, str>) {
+    (OpusErr::EDATABASE, e)
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct KeyVal {
@@ -60,22 +153,193 @@ pub struct Config {
     db_password: *mut c_char,
     suppress_default_views: bool,
     cfg_detail: *const AdvancedConfig,
+    incremental: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub enum ErrorPolicy {
+    Skip,
+    AbortAfter,
+    AbortOnFirst,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct IngestStats {
+    lines: usize,
+    records: usize,
+    io_errors: usize,
+    json_errors: usize,
+    syntax_errors: usize,
+    missing_field_errors: usize,
+    pvm_errors: usize,
+    unhandled_events: usize,
+    aborted: bool,
+}
+
+impl<'a> From<&'a IngestReport> for IngestStats {
+    fn from(r: &'a IngestReport) -> Self {
+        IngestStats {
+            lines: r.lines,
+            records: r.records,
+            io_errors: r.io.count,
+            json_errors: r.json.count,
+            syntax_errors: r.syntax.count,
+            missing_field_errors: r.missing_field.count,
+            pvm_errors: r.pvm.count,
+            unhandled_events: r.unhandled.values().sum(),
+            aborted: r.aborted,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct IngestProgress {
+    bytes: usize,
+    lines: usize,
+    records: usize,
+    errors: usize,
+    pvm_queue: usize,
+    cancelled: bool,
+    finished: bool,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct QueueDepth {
+    view_inst: usize,
+    depth: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct GraphNode {
+    id: u64,
+    kind: NodeKind,
+    uuid: *mut c_char,
+    ty: *mut c_char,
+    num_props: usize,
+    props: *mut KeyVal,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct GraphRel {
+    id: u64,
+    kind: RelKind,
+    src: u64,
+    dst: u64,
+    num_props: usize,
+    props: *mut KeyVal,
+    node: GraphNode,
+}
+
+pub type ErrorCallback =
+    unsafe extern "C" fn(kind: IngestErrorKind, line: usize, msg: *const c_char, data: *mut c_void);
+
+struct CallbackSink {
+    cb: ErrorCallback,
+    data: *mut c_void,
+}
+
+unsafe impl Send for CallbackSink {}
+
+impl ErrorSink for CallbackSink {
+    fn report(&mut self, err: &IngestError) {
+        let msg = CString::new(err.msg.replace('\0', "")).unwrap_or_default();
+        unsafe { (self.cb)(err.kind, err.line, msg.as_ptr(), self.data) }
+    }
+}
+
+const HDL_MAGIC: u32 = 0x4f50_5553;
+
+pub struct OpusHdl {
+    magic: u32,
+    engine: Mutex<engine::Engine>,
+}
+
+pub struct GraphHdl {
+    magic: u32,
+    graph: GraphRef,
+}
+
+pub struct IngestHdl {
+    magic: u32,
+    job: IngestHandle,
+}
+
+unsafe fn engine<'a>(hdl: *const OpusHdl) -> FfiResult<MutexGuard<'a, engine::Engine>> {
+    if hdl.is_null() || (*hdl).magic != HDL_MAGIC {
+        return fail(OpusErr::EINVALIDARG, "Invalid libpvm handle");
+    }
+    Ok((*hdl)
+        .engine
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner))
+}
+
+unsafe fn graph<'a>(hdl: *const GraphHdl) -> FfiResult<RwLockReadGuard<'a, Graph>> {
+    if hdl.is_null() || (*hdl).magic != HDL_MAGIC {
+        return fail(OpusErr::EINVALIDARG, "Invalid graph handle");
+    }
+    Ok((*hdl)
+        .graph
+        .read()
+        .unwrap_or_else(PoisonError::into_inner))
+}
+
+unsafe fn job<'a>(hdl: *const IngestHdl) -> FfiResult<&'a IngestHandle> {
+    if hdl.is_null() || (*hdl).magic != HDL_MAGIC {
+        return fail(OpusErr::EINVALIDARG, "Invalid ingest handle");
+    }
+    Ok(&(*hdl).job)
 }
 
-pub struct OpusHdl(engine::Engine);
+unsafe fn out_ptr<'a, T>(out: *mut T) -> FfiResult<&'a mut T> {
+    if out.is_null() {
+        fail(OpusErr::EINVALIDARG, "Null output pointer")
+    } else {
+        Ok(&mut *out)
+    }
+}
+
+fn c_str(str_p: *const c_char, what: &str) -> FfiResult<String> {
+    match string_from_c_char(str_p) {
+        Some(s) => Ok(s),
+        None => fail(
+            OpusErr::EINVALIDARG,
+            format!("Invalid string argument: {}", what),
+        ),
+    }
+}
+
+// Enums come in from C as plain integers, since any value outside a Rust
+// enum's variants is undefined behaviour. `variants` lists them in order.
+fn c_enum<T: Copy>(val: u32, what: &str, variants: &[T]) -> FfiResult<T> {
+    match variants.get(val as usize) {
+        Some(v) => Ok(*v),
+        None => fail(OpusErr::EINVALIDARG, format!("Invalid {}: {}", what, val)),
+    }
+}
 
-fn keyval_arr_to_hashmap(ptr: *const KeyVal, n: usize) -> HashMap<String, Box<Any>> {
+fn c_direction(dir: u32) -> FfiResult<Direction> {
+    c_enum(dir, "direction", &[Direction::Out, Direction::In, Direction::Both])
+}
+
+fn keyval_arr_to_hashmap(ptr: *const KeyVal, n: usize) -> FfiResult<HashMap<String, Box<Any>>> {
     let mut ret = HashMap::with_capacity(n);
     if !ptr.is_null() {
         let s = unsafe { slice::from_raw_parts(ptr, n) };
         for kv in s {
             ret.insert(
-                string_from_c_char(kv.key).unwrap(),
-                Box::new(string_from_c_char(kv.val).unwrap()) as Box<Any>,
+                c_str(kv.key, "parameter key")?,
+                Box::new(c_str(kv.val, "parameter value")?) as Box<Any>,
             );
         }
     }
-    ret
+    Ok(ret)
 }
 
 fn view_params_to_keyval_arr(h: &HashMap<&'static str, &'static str>) -> (*mut KeyVal, usize) {
@@ -105,10 +369,81 @@ fn iter_to_keyval_arr<'a, 'b, T: IntoIterator<Item = (&'a str, &'b str)>>(
     (data, len)
 }
 
-fn string_to_c_char(val: &str) -> *mut c_char {
-    if val.contains('\0') {
-        panic!("Trying to convert a string containing nulls to a C-string");
+fn props_to_keyval_arr(props: &[(String, String)]) -> (*mut KeyVal, usize) {
+    iter_to_keyval_arr(props.iter().map(|(k, v)| (&k[..], &v[..])), props.len())
+}
+
+fn node_to_c(node: &NodeRecord) -> GraphNode {
+    let (props, num_props) = props_to_keyval_arr(&node.props);
+    GraphNode {
+        id: node.id.inner(),
+        kind: node.kind,
+        uuid: match node.uuid {
+            Some(u) => string_to_c_char(&u.hyphenated().to_string()),
+            None => ptr::null_mut(),
+        },
+        ty: string_to_c_char(&node.ty),
+        num_props,
+        props,
+    }
+}
+
+fn rel_to_c(rel: &RelRecord) -> GraphRel {
+    let (props, num_props) = props_to_keyval_arr(&rel.props);
+    GraphRel {
+        id: rel.id.inner(),
+        kind: rel.kind,
+        src: rel.src.inner(),
+        dst: rel.dst.inner(),
+        num_props,
+        props,
+        node: node_to_c(&rel.node),
+    }
+}
+
+unsafe fn nodes_to_c<T: Into<NodeRecord>>(
+    nodes: Vec<T>,
+    out: *mut *mut GraphNode,
+) -> FfiResult<isize> {
+    let out = out_ptr(out)?;
+    let len = nodes.len();
+    *out = malloc(len * size_of::<GraphNode>()) as *mut GraphNode;
+    for (i, node) in nodes.into_iter().enumerate() {
+        ptr::write((*out).add(i), node_to_c(&node.into()));
+    }
+    Ok(len as isize)
+}
+
+unsafe fn rels_to_c(rels: Vec<RelRecord>, out: *mut *mut GraphRel) -> FfiResult<isize> {
+    let out = out_ptr(out)?;
+    let len = rels.len();
+    *out = malloc(len * size_of::<GraphRel>()) as *mut GraphRel;
+    for (i, rel) in rels.iter().enumerate() {
+        ptr::write((*out).add(i), rel_to_c(rel));
+    }
+    Ok(len as isize)
+}
+
+unsafe fn free_keyvals(kvs: *mut KeyVal, n: usize) {
+    if kvs.is_null() {
+        return;
     }
+    for kv in slice::from_raw_parts(kvs, n) {
+        free(kv.key as *mut c_void);
+        free(kv.val as *mut c_void);
+    }
+    free(kvs as *mut c_void);
+}
+
+unsafe fn free_node(node: &GraphNode) {
+    free(node.uuid as *mut c_void);
+    free(node.ty as *mut c_void);
+    free_keyvals(node.props, node.num_props);
+}
+
+// Trace data can contain NULs, which a C string cannot, so they are dropped.
+fn string_to_c_char(val: &str) -> *mut c_char {
+    let val: Vec<u8> = val.bytes().filter(|b| *b != 0).collect();
     unsafe {
         let data = malloc((val.len() + 1) * size_of::<c_char>()) as *mut c_char;
         ptr::copy(val.as_ptr() as *const c_char, data, val.len());
@@ -118,84 +453,93 @@ fn string_to_c_char(val: &str) -> *mut c_char {
 }
 
 fn string_from_c_char(str_p: *const c_char) -> Option<String> {
+    if str_p.is_null() {
+        return None;
+    }
     unsafe { CStr::from_ptr(str_p) }
         .to_str()
         .ok()
         .map(|s| s.to_string())
 }
 
+#[no_mangle]
+pub extern "C" fn opus_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match *e.borrow() {
+        Some(ref msg) => msg.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn opus_init(cfg: Config) -> *mut OpusHdl {
-    let r_cfg = cfg::Config {
-        cfg_mode: cfg.cfg_mode,
-        db_server: string_from_c_char(cfg.db_server)
-            .unwrap_or_else(|| "localhost:7687".to_string()),
-        db_user: string_from_c_char(cfg.db_user).unwrap_or_else(|| "neo4j".to_string()),
-        db_password: string_from_c_char(cfg.db_password).unwrap_or_else(|| "opus".to_string()),
-        suppress_default_views: cfg.suppress_default_views,
-        cfg_detail: if cfg.cfg_detail.is_null() {
-            Option::None
-        } else {
-            Option::Some(ptr::read(cfg.cfg_detail))
-        },
-    };
-    let e = engine::Engine::new(r_cfg);
-    let hdl = Box::new(OpusHdl(e));
-    Box::into_raw(hdl)
+    guard(|| {
+        let r_cfg = cfg::Config {
+            cfg_mode: cfg.cfg_mode,
+            db_server: string_from_c_char(cfg.db_server)
+                .unwrap_or_else(|| "localhost:7687".to_string()),
+            db_user: string_from_c_char(cfg.db_user).unwrap_or_else(|| "neo4j".to_string()),
+            db_password: string_from_c_char(cfg.db_password)
+                .unwrap_or_else(|| "opus".to_string()),
+            suppress_default_views: cfg.suppress_default_views,
+            cfg_detail: if cfg.cfg_detail.is_null() {
+                Option::None
+            } else {
+                Option::Some(ptr::read(cfg.cfg_detail))
+            },
+            incremental: cfg.incremental,
+        };
+        let e = engine::Engine::new(r_cfg);
+        let hdl = Box::new(OpusHdl {
+            magic: HDL_MAGIC,
+            engine: Mutex::new(e),
+        });
+        Ok(Box::into_raw(hdl))
+    })
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn opus_start_pipeline(hdl: *mut OpusHdl) -> isize {
-    let engine = &mut (*hdl).0;
-    match engine.init_pipeline() {
-        Ok(_) => 0,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            ret(OpusErr::EUNKNOWN)
-        }
-    }
+    guard(|| {
+        engine(hdl)?.init_pipeline().map_err(unknown)?;
+        Ok(0)
+    })
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn opus_shutdown_pipeline(hdl: *mut OpusHdl) -> isize {
-    let engine = &mut (*hdl).0;
-    match engine.shutdown_pipeline() {
-        Ok(_) => 0,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            ret(OpusErr::EUNKNOWN)
-        }
-    }
+    guard(|| {
+        engine(hdl)?.shutdown_pipeline().map_err(unknown)?;
+        Ok(0)
+    })
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn opus_print_cfg(hdl: *const OpusHdl) {
-    let engine = &(*hdl).0;
-    engine.print_cfg();
+    guard(|| {
+        engine(hdl)?.print_cfg();
+        Ok(())
+    })
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn opus_list_view_types(hdl: *const OpusHdl, out: *mut *mut View) -> isize {
-    let engine = &(*hdl).0;
-    let views = match engine.list_view_types() {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return ret(OpusErr::EUNKNOWN);
+    guard(|| {
+        let engine = engine(hdl)?;
+        let out = out_ptr(out)?;
+        let views = engine.list_view_types().map_err(unknown)?;
+        let len = views.len();
+        *out = malloc(len * size_of::<View>()) as *mut View;
+        let s = slice::from_raw_parts_mut(*out, len);
+        for (view, c_view) in views.into_iter().zip(s) {
+            c_view.id = view.id();
+            c_view.name = string_to_c_char(view.name());
+            c_view.desc = string_to_c_char(view.desc());
+            let (params, num) = view_params_to_keyval_arr(&view.params());
+            c_view.num_parameters = num;
+            c_view.parameters = params;
         }
-    };
-    let len = views.len();
-    *out = malloc(len * size_of::<View>()) as *mut View;
-    let s = slice::from_raw_parts_mut(*out, len);
-    for (view, c_view) in views.into_iter().zip(s) {
-        c_view.id = view.id();
-        c_view.name = string_to_c_char(view.name());
-        c_view.desc = string_to_c_char(view.desc());
-        let (params, num) = view_params_to_keyval_arr(&view.params());
-        c_view.num_parameters = num;
-        c_view.parameters = params;
-    }
-    len as isize
+        Ok(len as isize)
+    })
 }
 
 #[no_mangle]
@@ -205,15 +549,14 @@ pub unsafe extern "C" fn opus_create_view_by_id(
     params: *const KeyVal,
     n_params: usize,
 ) -> isize {
-    let engine = &mut (*hdl).0;
-    let rparams = keyval_arr_to_hashmap(params, n_params);
-    match engine.create_view_by_id(view_id, rparams) {
-        Ok(vid) => vid as isize,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            ret(OpusErr::EUNKNOWN)
-        }
-    }
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        let rparams = keyval_arr_to_hashmap(params, n_params)?;
+        let vid = engine
+            .create_view_by_id(view_id, rparams)
+            .map_err(unknown)?;
+        Ok(vid as isize)
+    })
 }
 
 #[no_mangle]
@@ -223,39 +566,32 @@ pub unsafe extern "C" fn opus_create_view_by_name(
     params: *const KeyVal,
     n_params: usize,
 ) -> isize {
-    let engine = &mut (*hdl).0;
-    let rparams = keyval_arr_to_hashmap(params, n_params);
-    let name = match string_from_c_char(name) {
-        Some(s) => s,
-        None => {
-            return ret(OpusErr::EINVALIDARG);
-        }
-    };
-    let views_with_name = match engine.list_view_types() {
-        Ok(vtypes) => vtypes
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        let rparams = keyval_arr_to_hashmap(params, n_params)?;
+        let name = c_str(name, "view name")?;
+        let views_with_name = engine
+            .list_view_types()
+            .map_err(unknown)?
             .into_iter()
             .filter(|v| v.name() == name)
             .map(|v| v.id())
-            .collect::<Vec<usize>>(),
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return ret(OpusErr::EUNKNOWN);
-        }
-    };
+            .collect::<Vec<usize>>();
 
-    if views_with_name.is_empty() {
-        ret(OpusErr::ENOVIEWWITHNAME)
-    } else if views_with_name.len() > 1 {
-        ret(OpusErr::EAMBIGUOUSVIEWNAME)
-    } else {
-        match engine.create_view_by_id(views_with_name[0], rparams) {
-            Ok(vid) => vid as isize,
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                ret(OpusErr::EUNKNOWN)
-            }
+        if views_with_name.is_empty() {
+            fail(OpusErr::ENOVIEWWITHNAME, format!("Unknown view: {}", name))
+        } else if views_with_name.len() > 1 {
+            fail(
+                OpusErr::EAMBIGUOUSVIEWNAME,
+                format!("Ambiguous view name: {}", name),
+            )
+        } else {
+            let vid = engine
+                .create_view_by_id(views_with_name[0], rparams)
+                .map_err(unknown)?;
+            Ok(vid as isize)
         }
-    }
+    })
 }
 
 #[no_mangle]
@@ -263,48 +599,568 @@ pub unsafe extern "C" fn opus_list_view_inst(
     hdl: *const OpusHdl,
     out: *mut *mut ViewInst,
 ) -> isize {
-    let engine = &(*hdl).0;
-    let views = match engine.list_running_views() {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return ret(OpusErr::EUNKNOWN);
+    guard(|| {
+        let engine = engine(hdl)?;
+        let out = out_ptr(out)?;
+        let views = engine.list_running_views().map_err(unknown)?;
+        let len = views.len();
+        *out = malloc(len * size_of::<ViewInst>()) as *mut ViewInst;
+        let s = slice::from_raw_parts_mut(*out, len);
+        for (view, c_view) in views.into_iter().zip(s) {
+            c_view.id = view.id();
+            c_view.vtype = view.vtype();
+            let (params, num) = view_inst_params_to_keyval_arr(view.params());
+            c_view.num_parameters = num;
+            c_view.parameters = params;
         }
-    };
-    let len = views.len();
-    *out = malloc(len * size_of::<ViewInst>()) as *mut ViewInst;
-    let s = slice::from_raw_parts_mut(*out, len);
-    for (view, c_view) in views.into_iter().zip(s) {
-        c_view.id = view.id();
-        c_view.vtype = view.vtype();
-        let (params, num) = view_inst_params_to_keyval_arr(view.params());
-        c_view.num_parameters = num;
-        c_view.parameters = params;
+        Ok(len as isize)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_free_keyvals(kvs: *mut KeyVal, n: usize) {
+    free_keyvals(kvs, n);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_free_views(views: *mut View, n: usize) {
+    if views.is_null() {
+        return;
+    }
+    for view in slice::from_raw_parts(views, n) {
+        free(view.name as *mut c_void);
+        free(view.desc as *mut c_void);
+        free_keyvals(view.parameters, view.num_parameters);
     }
-    len as isize
+    free(views as *mut c_void);
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn opus_ingest_fd(hdl: *mut OpusHdl, fd: i32) -> isize {
-    let engine = &mut (*hdl).0;
-    let stream = IOStream::from_raw_fd(fd as RawFd);
-    match timeit!(engine.ingest_stream(stream)) {
-        Ok(_) => 0,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            ret(OpusErr::EUNKNOWN)
+pub unsafe extern "C" fn opus_free_view_insts(insts: *mut ViewInst, n: usize) {
+    if insts.is_null() {
+        return;
+    }
+    for inst in slice::from_raw_parts(insts, n) {
+        free_keyvals(inst.parameters, inst.num_parameters);
+    }
+    free(insts as *mut c_void);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_set_error_policy(
+    hdl: *mut OpusHdl,
+    policy: u32,
+    max_errors: usize,
+) -> isize {
+    guard(|| {
+        let policy = c_enum(
+            policy,
+            "error policy",
+            &[
+                ErrorPolicy::Skip,
+                ErrorPolicy::AbortAfter,
+                ErrorPolicy::AbortOnFirst,
+            ],
+        )?;
+        engine(hdl)?.set_error_policy(match policy {
+            ErrorPolicy::Skip => ingest::ErrorPolicy::Skip,
+            ErrorPolicy::AbortAfter => ingest::ErrorPolicy::AbortAfter(max_errors),
+            ErrorPolicy::AbortOnFirst => ingest::ErrorPolicy::AbortOnFirst,
+        });
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_set_error_callback(
+    hdl: *mut OpusHdl,
+    cb: Option<ErrorCallback>,
+    data: *mut c_void,
+) -> isize {
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        match cb {
+            Some(cb) => engine.set_error_sink(CallbackSink { cb, data }),
+            None => engine.set_error_sink(StderrSink),
         }
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_fd(hdl: *mut OpusHdl, fd: i32) -> isize {
+    opus_ingest_fd_report(hdl, fd, ptr::null_mut())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_fd_report(
+    hdl: *mut OpusHdl,
+    fd: i32,
+    out: *mut IngestStats,
+) -> isize {
+    opus_ingest_fd_fmt(hdl, fd, ptr::null(), out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_list_formats(hdl: *const OpusHdl, out: *mut *mut KeyVal) -> isize {
+    guard(|| {
+        let engine = engine(hdl)?;
+        let out = out_ptr(out)?;
+        let fmts = engine.list_formats();
+        let (arr, len) =
+            iter_to_keyval_arr(fmts.iter().map(|f| (f.name(), f.desc())), fmts.len());
+        *out = arr;
+        Ok(len as isize)
+    })
+}
+
+fn c_fmt(engine: &engine::Engine, fmt: *const c_char) -> FfiResult<Option<String>> {
+    if fmt.is_null() {
+        return Ok(None);
+    }
+    let name = c_str(fmt, "format name")?;
+    if engine.list_formats().iter().all(|f| f.name() != name) {
+        return fail(
+            OpusErr::EUNKNOWNFORMAT,
+            format!("Unknown trace format: {}", name),
+        );
     }
+    Ok(Some(name))
+}
+
+fn report_to_c(report: &IngestReport, out: *mut IngestStats) -> FfiResult<isize> {
+    if !out.is_null() {
+        unsafe { *out = IngestStats::from(report) };
+    }
+    if report.aborted {
+        fail(OpusErr::EINGESTABORTED, "Ingest aborted")
+    } else {
+        Ok(0)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_fd_fmt(
+    hdl: *mut OpusHdl,
+    fd: i32,
+    fmt: *const c_char,
+    out: *mut IngestStats,
+) -> isize {
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        let fmt = c_fmt(&engine, fmt)?;
+        if fd < 0 {
+            return fail(OpusErr::EINVALIDARG, "Invalid file descriptor");
+        }
+        let stream = IOStream::from_raw_fd(fd as RawFd);
+        let report = timeit!(engine.ingest_stream_fmt(stream, fmt.as_ref().map(|s| &s[..])))
+            .map_err(unknown)?;
+        report_to_c(&report, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_fds(
+    hdl: *mut OpusHdl,
+    fds: *const i32,
+    n: usize,
+    fmt: *const c_char,
+    out: *mut IngestStats,
+) -> isize {
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        let fmt = c_fmt(&engine, fmt)?;
+        if fds.is_null() || n == 0 {
+            return fail(OpusErr::EINVALIDARG, "No file descriptors given");
+        }
+        let fds = slice::from_raw_parts(fds, n);
+        if fds.iter().any(|fd| *fd < 0) {
+            return fail(OpusErr::EINVALIDARG, "Invalid file descriptor");
+        }
+        let streams = fds
+            .iter()
+            .map(|fd| IOStream::from_raw_fd(*fd as RawFd))
+            .collect();
+        let report = timeit!(engine.ingest_streams(streams, fmt.as_ref().map(|s| &s[..])))
+            .map_err(unknown)?;
+        report_to_c(&report, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_fd_async(
+    hdl: *mut OpusHdl,
+    fd: i32,
+    fmt: *const c_char,
+) -> *mut IngestHdl {
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        let fmt = c_fmt(&engine, fmt)?;
+        if fd < 0 {
+            return fail(OpusErr::EINVALIDARG, "Invalid file descriptor");
+        }
+        let stream = IOStream::from_raw_fd(fd as RawFd);
+        let job = engine
+            .ingest_stream_async(stream, fmt.as_ref().map(|s| &s[..]))
+            .map_err(unknown)?;
+        Ok(Box::into_raw(Box::new(IngestHdl {
+            magic: HDL_MAGIC,
+            job,
+        })))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_listen(
+    hdl: *mut OpusHdl,
+    addr: *const c_char,
+    fmt: *const c_char,
+) -> *mut IngestHdl {
+    guard(|| {
+        let mut engine = engine(hdl)?;
+        let fmt = c_fmt(&engine, fmt)?;
+        let addr = c_str(addr, "listen address")?;
+        let addr = match addr.parse::<ListenAddr>() {
+            Ok(addr) => addr,
+            Err(e) => return fail(OpusErr::EINVALIDARG, e),
+        };
+        let stream = IOStream::listen(&addr).map_err(|e| unknown(e.to_string().into()))?;
+        let job = engine
+            .ingest_stream_async(stream, fmt.as_ref().map(|s| &s[..]))
+            .map_err(unknown)?;
+        Ok(Box::into_raw(Box::new(IngestHdl {
+            magic: HDL_MAGIC,
+            job,
+        })))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_progress(
+    hdl: *const IngestHdl,
+    out: *mut IngestProgress,
+) -> isize {
+    guard(|| {
+        let p = job(hdl)?.progress();
+        *out_ptr(out)? = IngestProgress {
+            bytes: p.bytes,
+            lines: p.lines,
+            records: p.records,
+            errors: p.errors,
+            pvm_queue: p.pvm_queue,
+            cancelled: p.cancelled,
+            finished: p.finished,
+        };
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_queue_depths(
+    hdl: *const IngestHdl,
+    out: *mut *mut QueueDepth,
+) -> isize {
+    guard(|| {
+        let views = job(hdl)?.progress().view_queues;
+        let out = out_ptr(out)?;
+        let len = views.len();
+        *out = malloc(len * size_of::<QueueDepth>()) as *mut QueueDepth;
+        for (i, (view_inst, depth)) in views.into_iter().enumerate() {
+            ptr::write((*out).add(i), QueueDepth { view_inst, depth });
+        }
+        Ok(len as isize)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_cancel(hdl: *const IngestHdl) -> isize {
+    guard(|| {
+        job(hdl)?.cancel();
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_wait(hdl: *const IngestHdl, out: *mut IngestStats) -> isize {
+    guard(|| {
+        let report = job(hdl)?.wait().map_err(unknown)?;
+        report_to_c(&report, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_ingest_free(hdl: *mut IngestHdl) {
+    guard(|| {
+        job(hdl)?;
+        (*hdl).magic = 0;
+        drop(Box::from_raw(hdl));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_free_queue_depths(depths: *mut QueueDepth) {
+    free(depths as *mut c_void);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_checkpoint(hdl: *mut OpusHdl, path: *const c_char) -> isize {
+    guard(|| {
+        let path = c_str(path, "checkpoint path")?;
+        engine(hdl)?.checkpoint(&path).map_err(unknown)?;
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_restore(hdl: *mut OpusHdl, path: *const c_char) -> isize {
+    guard(|| {
+        let path = c_str(path, "checkpoint path")?;
+        let offset = engine(hdl)?.restore(&path).map_err(unknown)?;
+        Ok(offset as isize)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_metrics(hdl: *const OpusHdl, out: *mut *mut KeyVal) -> isize {
+    guard(|| {
+        let snap = engine(hdl)?.metrics().snapshot();
+        let out = out_ptr(out)?;
+        let flat: Vec<(String, String)> = snap
+            .iter()
+            .flat_map(|(stage, keys)| {
+                keys.iter()
+                    .map(move |(k, v)| (format!("{}.{}", stage, k), v.to_string()))
+            })
+            .collect();
+        let (arr, len) = props_to_keyval_arr(&flat);
+        *out = arr;
+        Ok(len as isize)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_metrics_json(hdl: *const OpusHdl) -> *mut c_char {
+    guard(|| Ok(string_to_c_char(&engine(hdl)?.metrics().to_json())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_free_string(s: *mut c_char) {
+    free(s as *mut c_void);
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn opus_cleanup(hdl: *mut OpusHdl) {
-    drop(Box::from_raw(hdl));
-    println!("Cleaning up..");
+    guard(|| {
+        engine(hdl)?;
+        (*hdl).magic = 0;
+        drop(Box::from_raw(hdl));
+        println!("Cleaning up..");
+        Ok(())
+    })
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn opus_count_processes(hdl: *const OpusHdl) -> i64 {
-    let engine = &(*hdl).0;
-    engine.count_processes()
+    guard(|| engine(hdl)?.count_processes().map_err(database))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_view(hdl: *const OpusHdl, view_inst: usize) -> *mut GraphHdl {
+    guard(|| {
+        let graph = engine(hdl)?.graph(view_inst).map_err(unknown)?;
+        Ok(Box::into_raw(Box::new(GraphHdl {
+            magic: HDL_MAGIC,
+            graph,
+        })))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_free(hdl: *mut GraphHdl) {
+    guard(|| {
+        graph(hdl)?;
+        (*hdl).magic = 0;
+        drop(Box::from_raw(hdl));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_node(
+    hdl: *const GraphHdl,
+    id: u64,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let g = graph(hdl)?;
+        nodes_to_c(g.node(ID::new(id)).into_iter().collect(), out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_by_uuid(
+    hdl: *const GraphHdl,
+    uuid: *const c_char,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let uuid = c_uuid(uuid)?;
+        nodes_to_c(graph(hdl)?.by_uuid(uuid), out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_by_path(
+    hdl: *const GraphHdl,
+    path: *const c_char,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let path = c_str(path, "path")?;
+        nodes_to_c(graph(hdl)?.by_name(&Name::Path(path)), out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_by_net(
+    hdl: *const GraphHdl,
+    addr: *const c_char,
+    port: u16,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let addr = c_str(addr, "address")?;
+        nodes_to_c(graph(hdl)?.by_name(&Name::Net(addr, port)), out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_graph_neighbours(
+    hdl: *const GraphHdl,
+    id: u64,
+    dir: u32,
+    out: *mut *mut GraphRel,
+) -> isize {
+    guard(|| {
+        let dir = c_direction(dir)?;
+        let rels = graph(hdl)?
+            .neighbours(ID::new(id), dir)
+            .into_iter()
+            .map(|(r, n)| RelRecord::new(r, n))
+            .collect();
+        rels_to_c(rels, out)
+    })
+}
+
+fn nanos_to_time(ns: i64) -> Option<DateTime<Utc>> {
+    if ns <= 0 {
+        None
+    } else {
+        Some(Utc.timestamp(ns / 1_000_000_000, (ns % 1_000_000_000) as u32))
+    }
+}
+
+fn c_uuid(uuid: *const c_char) -> FfiResult<Uuid> {
+    let s = c_str(uuid, "uuid")?;
+    Uuid::parse_str(&s).or_else(|_| fail(OpusErr::EINVALIDARG, format!("Invalid uuid: {}", s)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_find_by_uuid(
+    hdl: *const OpusHdl,
+    uuid: *const c_char,
+    start_ns: i64,
+    end_ns: i64,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let uuid = c_uuid(uuid)?;
+        let w = TimeWindow::new(nanos_to_time(start_ns), nanos_to_time(end_ns));
+        nodes_to_c(engine(hdl)?.find_by_uuid(uuid, &w).map_err(database)?, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_find_by_path(
+    hdl: *const OpusHdl,
+    path: *const c_char,
+    start_ns: i64,
+    end_ns: i64,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let path = c_str(path, "path")?;
+        let w = TimeWindow::new(nanos_to_time(start_ns), nanos_to_time(end_ns));
+        nodes_to_c(engine(hdl)?.find_by_path(&path, &w).map_err(database)?, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_lineage(
+    hdl: *const OpusHdl,
+    id: u64,
+    dir: u32,
+    max_depth: usize,
+    start_ns: i64,
+    end_ns: i64,
+    out: *mut *mut GraphNode,
+) -> isize {
+    guard(|| {
+        let dir = c_enum(dir, "lineage", &[Lineage::Ancestors, Lineage::Descendants])?;
+        let q = LineageQuery {
+            max_depth: if max_depth == 0 {
+                None
+            } else {
+                Some(max_depth)
+            },
+            window: TimeWindow::new(nanos_to_time(start_ns), nanos_to_time(end_ns)),
+        };
+        let nodes = engine(hdl)?
+            .lineage(ID::new(id), dir, &q)
+            .map_err(database)?;
+        nodes_to_c(nodes, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_neighbours(
+    hdl: *const OpusHdl,
+    id: u64,
+    dir: u32,
+    start_ns: i64,
+    end_ns: i64,
+    out: *mut *mut GraphRel,
+) -> isize {
+    guard(|| {
+        let dir = c_direction(dir)?;
+        let w = TimeWindow::new(nanos_to_time(start_ns), nanos_to_time(end_ns));
+        let rels = engine(hdl)?
+            .neighbours(ID::new(id), dir, &w)
+            .map_err(database)?;
+        rels_to_c(rels, out)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_free_nodes(nodes: *mut GraphNode, n: usize) {
+    if nodes.is_null() {
+        return;
+    }
+    for node in slice::from_raw_parts(nodes, n) {
+        free_node(node);
+    }
+    free(nodes as *mut c_void);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opus_free_rels(rels: *mut GraphRel, n: usize) {
+    if rels.is_null() {
+        return;
+    }
+    for rel in slice::from_raw_parts(rels, n) {
+        free_keyvals(rel.props, rel.num_props);
+        free_node(&rel.node);
+    }
+    free(rels as *mut c_void);
 }
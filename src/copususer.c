@@ -37,12 +37,7 @@ int main(int argc, char** argv) {
     }
   }
 
-  for (int i=0; i<num_views; i++) {
-    free((void*)views[i].name);
-    free((void*)views[i].desc);
-    free((void*)views[i].parameters);
-  }
-  free(views);
+  opus_free_views(views, num_views);
 
   printf("File fd: %d\n", in);
   opus_ingest_fd(hdl, in);
@@ -1,26 +1,124 @@
-use ingest::{ingest_stream, pvm::PVM, Parseable};
-use iostream::IOStream;
-use neo4j_glue::{CSVView, Neo4JView};
-use query::low::count_processes;
-use std::{borrow::Cow, sync::mpsc};
+use graph::{Direction, GraphRef, GraphView};
+use ingest::{
+    format, pvm::PVM, Checkpoint, ErrorPolicy, ErrorSink, FormatRegistry, IngestHandle,
+    IngestReport, JsonFormat, MergeReader, Parseable, ProgressReader, ProgressSink, StderrSink,
+    TraceFormat,
+};
+use iostream::{IOStream, ListenAddr};
+use metrics::Metrics;
+use neo4j_glue::{db_checkpoint, CSVView, Neo4JView};
+use query::{
+    lineage::{Lineage, LineageQuery, LineageSource},
+    low::{self, count_processes, ids_by_path, ids_by_uuid, nodes_by_ids},
+    record::{NodeRecord, RelRecord},
+    scope::{self, TimeWindow},
+};
+use std::{
+    any::Any,
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, BufWriter, Cursor, Read},
+    mem,
+    sync::{mpsc, Arc, Mutex},
+};
 
 use cfg::Config;
-use views::{View, ViewCoordinator, ViewInst, ViewParams};
+use data::{node_types::Name, ID};
+use uuid::Uuid;
+use views::{queue, Queues, View, ViewCoordinator, ViewInst, ViewParams};
 
 use neo4j::Neo4jDB;
 
-use trace::cadets::TraceEvent;
+#[cfg(feature = "avro")]
+use trace::cdm::avro::CdmAvroFormat;
+use trace::{cadets::TraceEvent, cdm::CdmRecord, linux_audit::LinuxAuditFormat};
 
 type EngineResult<T> = Result<T, Cow<'static, str>>;
 
+const REORDER_WINDOW: usize = 1024;
+
 pub struct Pipeline {
-    pvm: PVM,
+    pvm: Arc<Mutex<PVM>>,
     view_ctrl: ViewCoordinator,
 }
 
 pub struct Engine {
     cfg: Config,
     pipeline: Option<Pipeline>,
+    err_policy: ErrorPolicy,
+    err_sink: Arc<Mutex<Box<ErrorSink>>>,
+    formats: FormatRegistry,
+    graph: Option<GraphRef>,
+    jobs: Vec<IngestHandle>,
+    metrics: Metrics,
+    inputs: usize,
+    resume: (usize, usize),
+    window: usize,
+}
+
+fn record_queues(metrics: &Metrics, queues: &Queues) {
+    let views = queues.views();
+    let stages = views
+        .iter()
+        .map(|(id, q)| (format!("queue.view.{}", id), q))
+        .chain(Some(("queue.pvm".to_string(), queues.input())));
+    for (stage, q) in stages {
+        let blocked = q.blocked();
+        metrics.set(&stage, "sent", q.sent() as u64);
+        metrics.set(&stage, "received", q.received() as u64);
+        metrics.set(&stage, "depth", q.depth() as u64);
+        metrics.set(
+            &stage,
+            "blocked_us",
+            blocked.as_secs() * 1_000_000 + u64::from(blocked.subsec_micros()),
+        );
+    }
+}
+
+// Numbers the inputs in the order they are ingested. Inputs before the one
+// a restored checkpoint stopped in were read in full and are skipped, the
+// rest get their index and the records to skip.
+fn next_input(inputs: &mut usize, resume: &mut (usize, usize)) -> Option<(usize, usize)> {
+    let input = *inputs;
+    *inputs += 1;
+    if input < resume.0 {
+        None
+    } else if input == resume.0 {
+        Some((input, mem::replace(&mut resume.1, 0)))
+    } else {
+        Some((input, 0))
+    }
+}
+
+fn run_ingest<R: Read + 'static>(
+    formats: &FormatRegistry,
+    stream: R,
+    fmt: Option<&str>,
+    (input, skip): (usize, usize),
+    pvm: &mut PVM,
+    policy: ErrorPolicy,
+    sink: &mut ErrorSink,
+) -> EngineResult<IngestReport> {
+    let (fmt, stream) = match fmt {
+        Some(name) => match formats.get(name) {
+            Some(f) => (f, Box::new(stream) as Box<Read>),
+            None => return Err(format!("Unknown trace format: {}", name).into()),
+        },
+        None => {
+            let (sample, stream) = format::peek(stream).map_err(|e| e.to_string())?;
+            match formats.detect(&sample) {
+                Some(f) => (f, stream),
+                None => return Err("Unable to detect trace format".into()),
+            }
+        }
+    };
+    if skip > 0 && !fmt.resumable() {
+        return Err(format!("{} traces cannot be resumed", fmt.name()).into());
+    }
+    let stream = fmt.framing().skip(stream, skip);
+    let report = fmt.ingest(stream, pvm, policy, sink);
+    pvm.set_offset(input, skip + report.offset);
+    Ok(report)
 }
 
 impl Drop for Engine {
@@ -31,34 +129,95 @@ impl Drop for Engine {
 
 impl Engine {
     pub fn new(cfg: Config) -> Engine {
+        let mut formats = FormatRegistry::new();
+        formats.register(JsonFormat::<TraceEvent>::new(
+            "cadets",
+            "CADETS FreeBSD audit and FBT events in JSON.",
+        ));
+        formats.register(LinuxAuditFormat::new());
+        formats.register(JsonFormat::<CdmRecord>::new(
+            "cdm",
+            "TC Common Data Model records in JSON.",
+        ));
+        #[cfg(feature = "avro")]
+        formats.register(CdmAvroFormat);
         Engine {
             cfg,
             pipeline: None,
+            err_policy: ErrorPolicy::default(),
+            err_sink: Arc::new(Mutex::new(Box::new(StderrSink))),
+            formats,
+            graph: None,
+            jobs: Vec::new(),
+            inputs: 0,
+            resume: (0, 0),
+            metrics: Metrics::new(),
+            window: REORDER_WINDOW,
         }
     }
 
+    pub fn register_format<F: TraceFormat + 'static>(&mut self, fmt: F) {
+        self.formats.register(fmt);
+    }
+
+    pub fn list_formats(&self) -> Vec<&TraceFormat> {
+        self.formats.list()
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.err_policy = policy;
+    }
+
+    pub fn set_error_sink<S: ErrorSink + 'static>(&mut self, sink: S) {
+        self.err_sink = Arc::new(Mutex::new(Box::new(sink)));
+    }
+
+    pub fn set_reorder_window(&mut self, records: usize) {
+        self.window = records;
+    }
+
     pub fn init_pipeline(&mut self) -> EngineResult<()> {
         if self.pipeline.is_some() {
             return Err("Pipeline already running".into());
         }
-        let (send, recv) = mpsc::sync_channel(100_000);
+        let (send, recv) = queue(100_000);
+        let mut pvm = PVM::new(send);
+        self.inputs = 0;
+        self.resume = (0, 0);
+        if self.cfg.incremental {
+            pvm.restore(db_checkpoint(&mut self.connect_db()?)?)?;
+        }
+        self.metrics = pvm.metrics();
         let mut view_ctrl = ViewCoordinator::new(recv);
         let neo4j_view_id = view_ctrl.register_view_type::<Neo4JView>();
         if !self.cfg.suppress_default_views {
-            view_ctrl.create_view_inst(neo4j_view_id, hashmap!(), &self.cfg);
+            let params = self.view_params(hashmap!());
+            view_ctrl.create_view_inst(neo4j_view_id, params, &self.cfg);
         }
         view_ctrl.register_view_type::<CSVView>();
+        view_ctrl.register_view_type::<GraphView>();
         self.pipeline = Some(Pipeline {
-            pvm: PVM::new(send),
+            pvm: Arc::new(Mutex::new(pvm)),
             view_ctrl,
         });
         Ok(())
     }
 
     pub fn shutdown_pipeline(&mut self) -> EngineResult<()> {
+        for job in self.jobs.drain(..) {
+            job.cancel();
+            job.wait().ok();
+        }
+        if let Some(graph) = self.running_graph() {
+            self.graph = Some(graph);
+        }
         if let Some(pipeline) = self.pipeline.take() {
-            pipeline.pvm.shutdown();
+            if let Ok(pvm) = Arc::try_unwrap(pipeline.pvm) {
+                pvm.into_inner().unwrap().shutdown();
+            }
+            let queues = pipeline.view_ctrl.queues();
             pipeline.view_ctrl.shutdown();
+            record_queues(&self.metrics, &queues);
             Ok(())
         } else {
             Err("Pipeline not running".into())
@@ -85,7 +244,15 @@ impl Engine {
         }
     }
 
+    fn view_params(&self, mut params: ViewParams) -> ViewParams {
+        params
+            .entry("metrics".to_string())
+            .or_insert_with(|| Box::new(self.metrics.clone()) as Box<Any>);
+        params
+    }
+
     pub fn create_view_by_id(&mut self, view_id: usize, params: ViewParams) -> EngineResult<usize> {
+        let params = self.view_params(params);
         if let Some(ref mut pipeline) = self.pipeline {
             Ok(pipeline
                 .view_ctrl
@@ -103,18 +270,168 @@ impl Engine {
         }
     }
 
-    pub fn ingest_stream(&mut self, stream: IOStream) -> EngineResult<()> {
-        if let Some(ref mut pipeline) = self.pipeline {
-            ingest_stream::<_, TraceEvent>(stream, &mut pipeline.pvm);
-            Ok(())
+    pub fn graph(&self, view_inst: usize) -> EngineResult<GraphRef> {
+        self.list_running_views()?
+            .into_iter()
+            .find(|v| v.id() == view_inst)
+            .and_then(|v| v.params().get("graph"))
+            .and_then(|g| g.downcast_ref::<GraphRef>())
+            .cloned()
+            .ok_or_else(|| format!("View {} is not a graph view", view_inst).into())
+    }
+
+    fn running_graph(&self) -> Option<GraphRef> {
+        self.list_running_views()
+            .ok()?
+            .into_iter()
+            .filter_map(|v| v.params().get("graph"))
+            .filter_map(|g| g.downcast_ref::<GraphRef>())
+            .cloned()
+            .next()
+    }
+
+    fn query_graph(&self) -> Option<GraphRef> {
+        self.running_graph().or_else(|| self.graph.clone())
+    }
+
+    pub fn ingest_stream(&mut self, stream: IOStream) -> EngineResult<IngestReport> {
+        self.ingest_stream_fmt(stream, None)
+    }
+
+    pub fn ingest_stream_fmt(
+        &mut self,
+        stream: IOStream,
+        fmt: Option<&str>,
+    ) -> EngineResult<IngestReport> {
+        self.ingest_read(stream, fmt)
+    }
+
+    pub fn ingest_streams(
+        &mut self,
+        streams: Vec<IOStream>,
+        fmt: Option<&str>,
+    ) -> EngineResult<IngestReport> {
+        if self.pipeline.is_none() {
+            return Err("Pipeline not running".into());
+        }
+        let mut sampled = Vec::new();
+        for mut stream in streams {
+            let sample = format::read_sample(&mut stream).map_err(|e| e.to_string())?;
+            sampled.push((sample, stream));
+        }
+        if sampled.is_empty() {
+            return Err("No input streams".into());
+        }
+        let fmt = match fmt {
+            Some(name) => match self.formats.get(name) {
+                Some(f) => f,
+                None => return Err(format!("Unknown trace format: {}", name).into()),
+            },
+            None => match self.formats.detect(&sampled[0].0) {
+                Some(f) => f,
+                None => return Err("Unable to detect trace format".into()),
+            },
+        };
+        let time = fmt
+            .line_time()
+            .ok_or_else(|| format!("{} traces cannot be merged", fmt.name()))?;
+        if sampled.len() > 1 {
+            for (sample, _) in &sampled {
+                fmt.check_merge(sample)?;
+            }
+        }
+        let name = fmt.name();
+        let sources = sampled
+            .into_iter()
+            .map(|(sample, s)| Box::new(Cursor::new(sample).chain(s)) as Box<Read + Send>)
+            .collect();
+        let merged = MergeReader::new(sources, fmt.framing(), time, self.window);
+        self.ingest_read(merged, Some(name))
+    }
+
+    fn ingest_read<R: Read + 'static>(
+        &mut self,
+        stream: R,
+        fmt: Option<&str>,
+    ) -> EngineResult<IngestReport> {
+        if let Some(ref pipeline) = self.pipeline {
+            let pos = match next_input(&mut self.inputs, &mut self.resume) {
+                Some(pos) => pos,
+                None => return Ok(IngestReport::default()),
+            };
+            let mut pvm = pipeline.pvm.lock().unwrap();
+            let report = run_ingest(
+                &self.formats,
+                stream,
+                fmt,
+                pos,
+                &mut pvm,
+                self.err_policy,
+                &mut **self.err_sink.lock().unwrap(),
+            );
+            pvm.flush_metrics();
+            report
         } else {
             Err("Pipeline not running".into())
         }
     }
 
+    pub fn ingest_stream_async(
+        &mut self,
+        stream: IOStream,
+        fmt: Option<&str>,
+    ) -> EngineResult<IngestHandle> {
+        if let Some(ref pipeline) = self.pipeline {
+            if let Some(name) = fmt {
+                if self.formats.get(name).is_none() {
+                    return Err(format!("Unknown trace format: {}", name).into());
+                }
+            }
+            let formats = self.formats.clone();
+            let fmt = fmt.map(|s| s.to_string());
+            let pvm = pipeline.pvm.clone();
+            let policy = self.err_policy;
+            let sink = self.err_sink.clone();
+            let pos = next_input(&mut self.inputs, &mut self.resume);
+            let job = IngestHandle::spawn(pipeline.view_ctrl.queues(), move |progress| {
+                let pos = match pos {
+                    Some(pos) => pos,
+                    None => return Ok(IngestReport::default()),
+                };
+                let mut sink = sink.lock().unwrap();
+                let mut pvm = pvm.lock().unwrap();
+                let report = run_ingest(
+                    &formats,
+                    ProgressReader::new(stream, progress.clone()),
+                    fmt.as_ref().map(|s| &s[..]),
+                    pos,
+                    &mut pvm,
+                    policy,
+                    &mut ProgressSink::new(&mut **sink, progress),
+                );
+                pvm.flush_metrics();
+                report
+            });
+            self.jobs.retain(|j| !j.is_finished());
+            self.jobs.push(job.clone());
+            Ok(job)
+        } else {
+            Err("Pipeline not running".into())
+        }
+    }
+
+    pub fn ingest_listen(&mut self, addr: &str, fmt: Option<&str>) -> EngineResult<IngestHandle> {
+        if self.pipeline.is_none() {
+            return Err("Pipeline not running".into());
+        }
+        let stream = IOStream::listen(&addr.parse::<ListenAddr>()?)
+            .map_err(|e| format!("Cannot listen on {}: {}", addr, e))?;
+        self.ingest_stream_async(stream, fmt)
+    }
+
     pub fn init_record<T: Parseable>(&mut self) -> EngineResult<()> {
-        if let Some(ref mut pipeline) = self.pipeline {
-            T::init(&mut pipeline.pvm);
+        if let Some(ref pipeline) = self.pipeline {
+            T::init(&mut pipeline.pvm.lock().unwrap());
             Ok(())
         } else {
             Err("Pipeline not running".into())
@@ -122,20 +439,140 @@ impl Engine {
     }
 
     pub fn ingest_record<T: Parseable>(&mut self, rec: &T) -> EngineResult<()> {
-        if let Some(ref mut pipeline) = self.pipeline {
-            rec.parse(&mut pipeline.pvm)
+        if let Some(ref pipeline) = self.pipeline {
+            rec.parse(&mut pipeline.pvm.lock().unwrap())
                 .map_err(|e| e.to_string().into())
         } else {
             Err("Pipeline not running".into())
         }
     }
 
-    pub fn count_processes(&self) -> i64 {
-        let mut db = Neo4jDB::connect(
+    pub fn checkpoint(&mut self, path: &str) -> EngineResult<()> {
+        if let Some(ref pipeline) = self.pipeline {
+            if self.jobs.iter().any(|j| !j.is_finished()) {
+                return Err("Ingest job still running".into());
+            }
+            let mut pvm = pipeline.pvm.lock().unwrap();
+            // The views store everything the PVM sent them first, so the
+            // checkpoint never refers to nodes the database lacks.
+            let views = pipeline.view_ctrl.list_view_insts().len();
+            let (done, synced) = mpsc::sync_channel(views);
+            pvm.sync_views(done);
+            for _ in 0..views {
+                synced
+                    .recv()
+                    .map_err(|_| "A view stopped before storing its updates")?;
+            }
+            let cp = pvm.checkpoint();
+            let file = File::create(path).map_err(|e| e.to_string())?;
+            cp.save(BufWriter::new(file)).map_err(|e| e.to_string().into())
+        } else {
+            Err("Pipeline not running".into())
+        }
+    }
+
+    pub fn restore(&mut self, path: &str) -> EngineResult<usize> {
+        if let Some(ref pipeline) = self.pipeline {
+            let file = File::open(path).map_err(|e| e.to_string())?;
+            let cp = Checkpoint::load(BufReader::new(file)).map_err(|e| e.to_string())?;
+            let mut pvm = pipeline.pvm.lock().unwrap();
+            let offset = pvm.restore(cp)?;
+            self.resume = (pvm.input(), offset);
+            Ok(offset)
+        } else {
+            Err("Pipeline not running".into())
+        }
+    }
+
+    pub fn resume_input(&self) -> usize {
+        self.resume.0
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        if let Some(ref pipeline) = self.pipeline {
+            record_queues(&self.metrics, &pipeline.view_ctrl.queues());
+        }
+        self.metrics.clone()
+    }
+
+    pub fn count_processes(&self) -> EngineResult<i64> {
+        count_processes(&mut self.connect_db()?)
+    }
+
+    fn connect_db(&self) -> EngineResult<Neo4jDB> {
+        Neo4jDB::connect(
             &self.cfg.db_server,
             &self.cfg.db_user,
             &self.cfg.db_password,
-        ).unwrap();
-        count_processes(&mut db)
+        ).map_err(|e| format!("Cannot connect to {}: {:?}", self.cfg.db_server, e).into())
+    }
+
+    pub fn find_by_uuid(&self, uuid: Uuid, w: &TimeWindow) -> EngineResult<Vec<NodeRecord>> {
+        match self.query_graph() {
+            Some(g) => Ok(scope::by_uuid(&g.read().unwrap(), uuid, w)
+                .into_iter()
+                .map(NodeRecord::from)
+                .collect()),
+            None => {
+                let mut db = self.connect_db()?;
+                let ids = ids_by_uuid(&mut db, uuid, w)?;
+                nodes_by_ids(&mut db, &ids)
+            }
+        }
+    }
+
+    pub fn find_by_path(&self, path: &str, w: &TimeWindow) -> EngineResult<Vec<NodeRecord>> {
+        match self.query_graph() {
+            Some(g) => Ok(
+                scope::by_name(&g.read().unwrap(), &Name::Path(path.to_string()), w)
+                    .into_iter()
+                    .map(NodeRecord::from)
+                    .collect(),
+            ),
+            None => {
+                let mut db = self.connect_db()?;
+                let ids = ids_by_path(&mut db, path, w)?;
+                nodes_by_ids(&mut db, &ids)
+            }
+        }
+    }
+
+    pub fn lineage(
+        &self,
+        start: ID,
+        dir: Lineage,
+        q: &LineageQuery,
+    ) -> EngineResult<Vec<NodeRecord>> {
+        match self.query_graph() {
+            Some(mut g) => {
+                let ids = g.lineage(start, dir, q)?;
+                let g = g.read().unwrap();
+                Ok(ids
+                    .into_iter()
+                    .filter_map(|id| g.node(id))
+                    .map(NodeRecord::from)
+                    .collect())
+            }
+            None => {
+                let mut db = self.connect_db()?;
+                let ids = db.lineage(start, dir, q)?;
+                nodes_by_ids(&mut db, &ids)
+            }
+        }
+    }
+
+    pub fn neighbours(
+        &self,
+        id: ID,
+        dir: Direction,
+        w: &TimeWindow,
+    ) -> EngineResult<Vec<RelRecord>> {
+        match self.query_graph() {
+            Some(g) => Ok(scope::neighbours(&g.read().unwrap(), id, dir, w)
+                .into_iter()
+                .map(|(r, n)| RelRecord::new(r, n))
+                .collect()),
+            None => low::neighbours(&mut self.connect_db()?, id, dir, w),
+        }
     }
 }
@@ -0,0 +1,287 @@
+mod view;
+
+pub use self::view::GraphView;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+
+use data::{
+    node_types::{Name, NameNode, Node},
+    rel_types::Rel,
+    HasDst, HasID, HasSrc, ID,
+};
+use uuid::Uuid;
+use views::DBTr;
+
+pub type GraphRef = Arc<RwLock<Graph>>;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Out,
+    In,
+    Both,
+}
+
+#[derive(Debug, Default)]
+pub struct Graph {
+    nodes: HashMap<ID, Node>,
+    rels: HashMap<ID, Rel>,
+    out_rels: HashMap<ID, Vec<ID>>,
+    in_rels: HashMap<ID, Vec<ID>>,
+    uuids: HashMap<Uuid, Vec<ID>>,
+    paths: HashMap<String, ID>,
+    nets: HashMap<(String, u16), ID>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Graph::default()
+    }
+
+    pub fn apply(&mut self, tr: &DBTr) {
+        match tr {
+            DBTr::CreateNode(node) | DBTr::UpdateNode(node) => self.add_node(node.clone()),
+            DBTr::CreateRel(rel) | DBTr::UpdateRel(rel) => self.add_rel(rel.clone()),
+            DBTr::Sync(_) => {}
+        }
+    }
+
+    fn add_node(&mut self, node: Node) {
+        let id = node.get_db_id();
+        if !self.nodes.contains_key(&id) {
+            match node {
+                Node::Data(ref d) => self.uuids.entry(d.uuid()).or_insert_with(Vec::new).push(id),
+                Node::Name(NameNode::Path(_, ref path)) => {
+                    self.paths.insert(path.clone(), id);
+                }
+                Node::Name(NameNode::Net(_, ref addr, port)) => {
+                    self.nets.insert((addr.clone(), port), id);
+                }
+                _ => {}
+            }
+        }
+        self.nodes.insert(id, node);
+    }
+
+    fn add_rel(&mut self, rel: Rel) {
+        let id = rel.get_db_id();
+        if !self.rels.contains_key(&id) {
+            self.out_rels
+                .entry(rel.get_src())
+                .or_insert_with(Vec::new)
+                .push(id);
+            self.in_rels
+                .entry(rel.get_dst())
+                .or_insert_with(Vec::new)
+                .push(id);
+        }
+        self.rels.insert(id, rel);
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn rel_count(&self) -> usize {
+        self.rels.len()
+    }
+
+    pub fn node(&self, id: ID) -> Option<&Node> {
+        self.nodes.get(&id)
+    }
+
+    pub fn rel(&self, id: ID) -> Option<&Rel> {
+        self.rels.get(&id)
+    }
+
+    pub fn ctx_time(&self, ctx: ID) -> Option<DateTime<Utc>> {
+        match self.nodes.get(&ctx) {
+            Some(Node::Ctx(c)) => c
+                .cont
+                .get("time")
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.with_timezone(&Utc)),
+            _ => None,
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.values()
+    }
+
+    pub fn by_uuid(&self, uuid: Uuid) -> Vec<&Node> {
+        self.uuids
+            .get(&uuid)
+            .map(|ids| ids.iter().filter_map(|id| self.nodes.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn latest(&self, uuid: Uuid) -> Option<&Node> {
+        self.uuids
+            .get(&uuid)
+            .and_then(|ids| ids.last())
+            .and_then(|id| self.nodes.get(id))
+    }
+
+    pub fn name_node(&self, name: &Name) -> Option<ID> {
+        match name {
+            Name::Path(path) => self.paths.get(path).cloned(),
+            Name::Net(addr, port) => self.nets.get(&(addr.clone(), *port)).cloned(),
+        }
+    }
+
+    pub fn by_name(&self, name: &Name) -> Vec<&Node> {
+        let mut ret: Vec<&Node> = Vec::new();
+        if let Some(id) = self.name_node(name) {
+            for (_, node) in self.neighbours(id, Direction::In) {
+                if ret.iter().all(|n| n.get_db_id() != node.get_db_id()) {
+                    ret.push(node);
+                }
+            }
+        }
+        ret
+    }
+
+    pub fn neighbours(&self, id: ID, dir: Direction) -> Vec<(&Rel, &Node)> {
+        let mut ret = Vec::new();
+        if dir != Direction::In {
+            for rel in self.rels_of(&self.out_rels, id) {
+                if let Some(n) = self.nodes.get(&rel.get_dst()) {
+                    ret.push((rel, n));
+                }
+            }
+        }
+        if dir != Direction::Out {
+            for rel in self.rels_of(&self.in_rels, id) {
+                if let Some(n) = self.nodes.get(&rel.get_src()) {
+                    ret.push((rel, n));
+                }
+            }
+        }
+        ret
+    }
+
+    fn rels_of<'a>(
+        &'a self,
+        adj: &'a HashMap<ID, Vec<ID>>,
+        id: ID,
+    ) -> impl Iterator<Item = &'a Rel> + 'a {
+        adj.get(&id)
+            .into_iter()
+            .flat_map(|ids| ids.iter())
+            .filter_map(move |r| self.rels.get(r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use data::{
+        node_types::{ConcreteType, DataNode, PVMDataType},
+        rel_types::{Inf, InfInit, Named, NamedInit, PVMOps},
+        RelGenerable,
+    };
+
+    lazy_static! {
+        static ref FILE: ConcreteType = ConcreteType {
+            pvm_ty: PVMDataType::Store,
+            name: "file",
+            props: HashMap::new(),
+        };
+    }
+
+    fn uuid(n: u8) -> Uuid {
+        Uuid::from_bytes(&[n; 16]).unwrap()
+    }
+
+    fn data(id: u64, u: u8) -> DBTr {
+        DBTr::CreateNode(Node::Data(DataNode::new(
+            PVMDataType::Store,
+            &FILE,
+            ID::new(id),
+            uuid(u),
+            ID::new(0),
+            None,
+        )))
+    }
+
+    fn inf(id: u64, src: u64, dst: u64) -> DBTr {
+        DBTr::CreateRel(Rel::Inf(Inf::new(
+            ID::new(id),
+            ID::new(src),
+            ID::new(dst),
+            InfInit {
+                pvm_op: PVMOps::Version,
+                ctx: ID::new(0),
+                byte_count: 0,
+            },
+        )))
+    }
+
+    fn named(id: u64, src: u64, dst: u64) -> DBTr {
+        DBTr::CreateRel(Rel::Named(Named::new(
+            ID::new(id),
+            ID::new(src),
+            ID::new(dst),
+            NamedInit {
+                start: ID::new(0),
+                end: ID::new(0),
+            },
+        )))
+    }
+
+    fn graph(trs: &[DBTr]) -> Graph {
+        let mut g = Graph::new();
+        for tr in trs {
+            g.apply(tr);
+        }
+        g
+    }
+
+    fn ids(nodes: Vec<&Node>) -> Vec<u64> {
+        nodes.iter().map(|n| n.get_db_id().inner()).collect()
+    }
+
+    #[test]
+    fn uuid_versions() {
+        let g = graph(&[data(1, 1), data(2, 1), inf(3, 1, 2), data(4, 2), data(2, 1)]);
+        assert_eq!(g.node_count(), 3);
+        assert_eq!(ids(g.by_uuid(uuid(1))), vec![1, 2]);
+        assert_eq!(g.latest(uuid(1)).map(|n| n.get_db_id()), Some(ID::new(2)));
+        assert!(g.by_uuid(uuid(9)).is_empty());
+    }
+
+    #[test]
+    fn names() {
+        let g = graph(&[
+            data(1, 1),
+            data(2, 2),
+            DBTr::CreateNode(Node::Name(NameNode::Path(ID::new(3), "/etc".into()))),
+            named(4, 1, 3),
+            named(5, 2, 3),
+            named(6, 1, 3),
+        ]);
+        assert_eq!(ids(g.by_name(&Name::Path("/etc".into()))), vec![1, 2]);
+        assert!(g.by_name(&Name::Path("/tmp".into())).is_empty());
+    }
+
+    #[test]
+    fn neighbours() {
+        let g = graph(&[data(1, 1), data(2, 2), data(3, 3), inf(4, 1, 2), inf(5, 2, 3)]);
+        let out: Vec<u64> = g
+            .neighbours(ID::new(2), Direction::Out)
+            .iter()
+            .map(|(_, n)| n.get_db_id().inner())
+            .collect();
+        assert_eq!(out, vec![3]);
+        assert_eq!(g.neighbours(ID::new(2), Direction::In).len(), 1);
+        assert_eq!(g.neighbours(ID::new(2), Direction::Both).len(), 2);
+        assert_eq!(g.rel_count(), 2);
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    thread,
+};
+
+use cfg::Config;
+use views::*;
+
+use super::{Graph, GraphRef};
+
+#[derive(Debug)]
+pub struct GraphView {
+    id: usize,
+}
+
+impl View for GraphView {
+    fn new(id: usize) -> GraphView {
+        GraphView { id }
+    }
+    fn id(&self) -> usize {
+        self.id
+    }
+    fn name(&self) -> &'static str {
+        "GraphView"
+    }
+    fn desc(&self) -> &'static str {
+        "View for materialising the provenance graph in memory for local queries."
+    }
+    fn params(&self) -> HashMap<&'static str, &'static str> {
+        hashmap!()
+    }
+    fn create(
+        &self,
+        id: usize,
+        mut params: ViewParams,
+        _cfg: &Config,
+        stream: QueueReceiver<Arc<DBTr>>,
+    ) -> ViewInst {
+        let graph: GraphRef = Arc::new(RwLock::new(Graph::new()));
+        params.insert("graph".to_string(), Box::new(graph.clone()) as Box<Any>);
+        let thr = thread::spawn(move || {
+            for evt in stream {
+                if let DBTr::Sync(ref done) = *evt {
+                    done.send(id).ok();
+                    continue;
+                }
+                graph.write().unwrap().apply(&evt);
+            }
+        });
+        ViewInst {
+            id,
+            vtype: self.id,
+            params,
+            handle: thr,
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::{self, BufReader, Read, Write};
+
+use serde_json;
+use uuid::Uuid;
+
+use data::{
+    node_types::{NameNode, PVMDataType},
+    rel_types::Rel,
+    MetaStore, ID,
+};
+
+use super::{net::ConnState, JsonRecords};
+
+const CHECKPOINT_VERSION: u32 = 2;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct NodeState {
+    pub pvm_ty: PVMDataType,
+    pub ty: String,
+    pub id: ID,
+    pub uuid: Uuid,
+    pub ctx: ID,
+    pub meta: MetaStore,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Checkpoint {
+    version: u32,
+    // Index of the input, in ingest order, that `offset` is a count of
+    // records in.
+    #[serde(default)]
+    pub input: usize,
+    pub offset: usize,
+    pub(crate) next_id: u64,
+    pub(crate) types: Vec<String>,
+    pub(crate) ctx_types: Vec<String>,
+    pub(crate) ctx: ID,
+    pub(crate) uuids: Vec<(Uuid, ID)>,
+    pub(crate) nodes: Vec<NodeState>,
+    pub(crate) rels: Vec<Rel>,
+    pub(crate) rel_src_dst: Vec<((ID, ID), ID)>,
+    pub(crate) open: Vec<(Uuid, Vec<Uuid>)>,
+    pub(crate) names: Vec<NameNode>,
+    pub(crate) pids: Vec<((Uuid, i32), Uuid)>,
+    pub(crate) conns: ConnState,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(offset: usize, next_id: u64) -> Self {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            input: 0,
+            offset,
+            next_id,
+            types: Vec::new(),
+            ctx_types: Vec::new(),
+            ctx: ID::new(0),
+            uuids: Vec::new(),
+            nodes: Vec::new(),
+            rels: Vec::new(),
+            rel_src_dst: Vec::new(),
+            open: Vec::new(),
+            names: Vec::new(),
+            pids: Vec::new(),
+            conns: ConnState::default(),
+        }
+    }
+
+    pub fn save<W: Write>(&self, w: W) -> io::Result<()> {
+        serde_json::to_writer(w, self).map_err(io::Error::from)
+    }
+
+    pub fn load<R: Read>(r: R) -> io::Result<Self> {
+        let cp: Checkpoint = serde_json::from_reader(r).map_err(io::Error::from)?;
+        if cp.version != CHECKPOINT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported checkpoint version: {}", cp.version),
+            ));
+        }
+        Ok(cp)
+    }
+}
+
+pub struct SkipLines<R: Read> {
+    inner: R,
+    remaining: usize,
+}
+
+impl<R: Read> SkipLines<R> {
+    pub fn new(inner: R, lines: usize) -> Self {
+        SkipLines {
+            inner,
+            remaining: lines,
+        }
+    }
+}
+
+impl<R: Read> Read for SkipLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining > 0 {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut i = 0;
+            while self.remaining > 0 {
+                match buf[i..n].iter().position(|b| *b == b'\n') {
+                    Some(p) => {
+                        i += p + 1;
+                        self.remaining -= 1;
+                    }
+                    None => {
+                        i = n;
+                        break;
+                    }
+                }
+            }
+            if i < n {
+                for j in i..n {
+                    buf[j - i] = buf[j];
+                }
+                return Ok(n - i);
+            }
+        }
+        self.inner.read(buf)
+    }
+}
+
+// Skips the first `records` JSON values as JsonRecords frames them, leaving
+// the stream at the end of the last one.
+pub struct SkipRecords<R: Read> {
+    inner: JsonRecords<BufReader<R>>,
+    remaining: usize,
+}
+
+impl<R: Read> SkipRecords<R> {
+    pub fn new(inner: R, records: usize) -> Self {
+        SkipRecords {
+            inner: JsonRecords::new(BufReader::new(inner)),
+            remaining: records,
+        }
+    }
+}
+
+impl<R: Read> Read for SkipRecords<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining > 0 {
+            match self.inner.next() {
+                Some(Ok(_)) => self.remaining -= 1,
+                Some(Err(e)) => return Err(e),
+                None => self.remaining = 0,
+            }
+        }
+        self.inner.get_mut().read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use data::node_types::{ConcreteType, Name};
+    use ingest::pvm::PVM;
+    use views::queue;
+
+    lazy_static! {
+        static ref FILE: ConcreteType = ConcreteType {
+            pvm_ty: PVMDataType::Store,
+            name: "file",
+            props: hashmap!("path" => true),
+        };
+    }
+
+    #[test]
+    fn skip_lines() {
+        let mut s = String::new();
+        SkipLines::new(Cursor::new("a\nbb\nccc\nd"), 2)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "ccc\nd");
+        s.clear();
+        SkipLines::new(Cursor::new("a\n"), 3)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "");
+    }
+
+    #[test]
+    fn skip_records() {
+        let mut s = String::new();
+        SkipRecords::new(Cursor::new("[{\"a\": 1}, {\"b\":\n2}, {\"c\": 3}]"), 2)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, ", {\"c\": 3}]");
+        s.clear();
+        SkipRecords::new(Cursor::new("1 2\n"), 3)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "");
+    }
+
+    #[test]
+    fn round_trip() {
+        let (send, _recv) = queue(0x100);
+        let mut pvm = PVM::new(send);
+        pvm.register_data_type(&FILE);
+        let uuid = Uuid::from_bytes(&[1; 16]).unwrap();
+        let id = pvm.declare(&FILE, uuid, None).unwrap();
+        pvm.meta(id, "path", "/tmp/a").unwrap();
+        pvm.name(id, Name::Path("/tmp/a".to_string())).unwrap();
+        pvm.set_offset(2, 7);
+        let mut buf = Vec::new();
+        pvm.checkpoint().save(&mut buf).unwrap();
+
+        let (send, recv) = queue(0x100);
+        let mut resumed = PVM::new(send);
+        assert_eq!(resumed.restore(Checkpoint::load(&buf[..]).unwrap()), Ok(7));
+        assert_eq!(resumed.input(), 2);
+        assert!(resumed.restore(Checkpoint::load(&buf[..]).unwrap()).is_err());
+        resumed.register_data_type(&FILE);
+        assert_eq!(resumed.declare(&FILE, uuid, None).unwrap(), id);
+        resumed.meta(id, "path", "/tmp/b").unwrap();
+        let name = resumed.name(id, Name::Path("/tmp/a".to_string())).unwrap();
+        let next = resumed.declare(&FILE, Uuid::from_bytes(&[2; 16]).unwrap(), None).unwrap();
+        assert!(next.inner() > name.inner());
+        drop(resumed);
+        let created = recv
+            .filter(|tr| match *tr {
+                ::views::DBTr::CreateNode(_) | ::views::DBTr::CreateRel(_) => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(created, 1);
+    }
+}
@@ -1,14 +1,14 @@
-use std::sync::mpsc::SyncSender;
+use std::sync::mpsc;
 
 use data::{node_types::Node, rel_types::Rel, Enumerable};
-use views::DBTr;
+use views::{DBTr, QueueSender};
 
 pub struct DB {
-    persist_pipe: SyncSender<DBTr>,
+    persist_pipe: QueueSender<DBTr>,
 }
 
 impl DB {
-    pub fn create(pipe: SyncSender<DBTr>) -> DB {
+    pub fn create(pipe: QueueSender<DBTr>) -> DB {
         DB { persist_pipe: pipe }
     }
 
@@ -30,6 +30,12 @@ impl DB {
             .expect("Database worker closed queue unexpectadly")
     }
 
+    pub fn sync(&mut self, done: mpsc::SyncSender<usize>) {
+        self.persist_pipe
+            .send(DBTr::Sync(done))
+            .expect("Database worker closed queue unexpectadly")
+    }
+
     pub fn update_rel<R: Enumerable<Target = Rel>>(&mut self, rel: R) {
         self.persist_pipe
             .send(DBTr::UpdateRel(rel.enumerate()))
@@ -0,0 +1,192 @@
+use std::{
+    io::{self, Cursor, Read},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use serde_json;
+
+use super::{
+    ingest_stream, pvm::PVM, ErrorPolicy, ErrorSink, IngestReport, JsonRecords, Parseable,
+    SkipLines, SkipRecords,
+};
+
+const PROBE_SIZE: usize = 0x10_000;
+const PROBE_RECORDS: usize = 4;
+
+// Event time of one input record in nanoseconds, the key streams are merged
+// on.
+pub type LineTime = fn(&[u8]) -> Option<i64>;
+
+// How a format splits its input into records. Merged streams are interleaved
+// a record at a time and checkpoints count their offset in records.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+    Lines,
+    Json,
+}
+
+impl Framing {
+    pub fn skip(self, stream: Box<Read>, records: usize) -> Box<Read> {
+        match self {
+            Framing::Lines => Box::new(SkipLines::new(stream, records)),
+            Framing::Json => Box::new(SkipRecords::new(stream, records)),
+        }
+    }
+}
+
+pub trait TraceFormat: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn desc(&self) -> &'static str;
+    fn probe(&self, sample: &[u8]) -> bool;
+    fn ingest(
+        &self,
+        stream: Box<Read>,
+        pvm: &mut PVM,
+        policy: ErrorPolicy,
+        sink: &mut ErrorSink,
+    ) -> IngestReport;
+    fn framing(&self) -> Framing {
+        Framing::Lines
+    }
+    // Formats that keep state of their own outside the PVM, or whose records
+    // cannot be skipped by their framing, cannot resume from a checkpoint.
+    fn resumable(&self) -> bool {
+        true
+    }
+    // Only formats with a record time can be merged with MergeReader.
+    fn line_time(&self) -> Option<LineTime> {
+        None
+    }
+    // Rejects a stream, going by a sample of it, whose records could not be
+    // told apart from those of the other streams once merged.
+    fn check_merge(&self, _sample: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub struct JsonFormat<T: Parseable> {
+    name: &'static str,
+    desc: &'static str,
+    _rec: PhantomData<fn() -> T>,
+}
+
+impl<T: Parseable> JsonFormat<T> {
+    pub fn new(name: &'static str, desc: &'static str) -> Self {
+        JsonFormat {
+            name,
+            desc,
+            _rec: PhantomData,
+        }
+    }
+}
+
+impl<T: Parseable> TraceFormat for JsonFormat<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn desc(&self) -> &'static str {
+        self.desc
+    }
+
+    fn probe(&self, sample: &[u8]) -> bool {
+        // The sample is cut at an arbitrary byte, so ignore its last record if
+        // it was left open.
+        let mut recs = JsonRecords::new(sample)
+            .filter_map(|r| r.ok())
+            .filter(|r| r.complete)
+            .take(PROBE_RECORDS)
+            .peekable();
+        recs.peek().is_some() && recs.all(|r| serde_json::from_slice::<T>(&r.data).is_ok())
+    }
+
+    fn ingest(
+        &self,
+        stream: Box<Read>,
+        pvm: &mut PVM,
+        policy: ErrorPolicy,
+        sink: &mut ErrorSink,
+    ) -> IngestReport {
+        ingest_stream::<_, T>(stream, pvm, policy, sink)
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Json
+    }
+
+    fn line_time(&self) -> Option<LineTime> {
+        Some(T::line_time)
+    }
+}
+
+pub fn sample_lines(sample: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let complete = match sample.iter().rposition(|b| *b == b'\n') {
+        Some(n) => &sample[..n],
+        None => sample,
+    };
+    complete
+        .split(|b| *b == b'\n')
+        .map(|l| if l.ends_with(b"\r") { &l[..l.len() - 1] } else { l })
+        .filter(|l| !l.is_empty())
+}
+
+#[derive(Clone, Default)]
+pub struct FormatRegistry {
+    formats: Vec<Arc<TraceFormat>>,
+}
+
+impl FormatRegistry {
+    pub fn new() -> Self {
+        FormatRegistry::default()
+    }
+
+    pub fn register<F: TraceFormat + 'static>(&mut self, fmt: F) {
+        self.formats.retain(|f| f.name() != fmt.name());
+        self.formats.push(Arc::new(fmt));
+    }
+
+    pub fn list(&self) -> Vec<&TraceFormat> {
+        self.formats.iter().map(|f| f.as_ref()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TraceFormat> {
+        self.formats
+            .iter()
+            .find(|f| f.name() == name)
+            .map(|f| f.as_ref())
+    }
+
+    pub fn detect(&self, sample: &[u8]) -> Option<&TraceFormat> {
+        self.formats
+            .iter()
+            .find(|f| f.probe(sample))
+            .map(|f| f.as_ref())
+    }
+}
+
+pub fn peek<R: Read + 'static>(mut stream: R) -> io::Result<(Vec<u8>, Box<Read>)> {
+    let sample = read_sample(&mut stream)?;
+    let replay = Cursor::new(sample.clone()).chain(stream);
+    Ok((sample, Box::new(replay)))
+}
+
+pub fn read_sample<R: Read>(stream: &mut R) -> io::Result<Vec<u8>> {
+    let mut sample = vec![0; PROBE_SIZE];
+    let mut len = 0;
+    while len < PROBE_SIZE {
+        match stream.read(&mut sample[len..]) {
+            Ok(0) => break,
+            Ok(n) => {
+                len += n;
+                if sample[..len].iter().filter(|b| **b == b'\n').count() > PROBE_RECORDS + 1 {
+                    break;
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    sample.truncate(len);
+    Ok(sample)
+}
@@ -0,0 +1,308 @@
+use std::io::{self, BufRead};
+
+const MAX_RECORD: usize = 0x400_0000;
+// Record separator of RFC 7464 JSON text sequences. Datagram streams start
+// every datagram with one.
+pub const RS: u8 = 0x1e;
+
+pub struct JsonRecord {
+    pub line: usize,
+    pub end: usize,
+    pub data: Vec<u8>,
+    pub complete: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Value,
+    Scalar,
+}
+
+// Splits a stream into top level JSON values without parsing them. Handles
+// JSON Lines, values concatenated on one line, values spread over several
+// lines, and top level arrays, whose elements become the records. Strings
+// cannot contain raw newlines, so a record that hits one inside a string is
+// cut there and scanning resumes on the next line. An unclosed record is
+// also cut when a later line starts with `{` or at a record separator.
+pub struct JsonRecords<R: BufRead> {
+    src: R,
+    pos: Position,
+}
+
+#[derive(Default)]
+struct Position {
+    lines: usize,
+    partial: bool,
+    array: bool,
+    skip: bool,
+}
+
+fn delimiter(b: u8) -> bool {
+    match b {
+        b' ' | b'\t' | b'\r' | b'\n' | b',' | b'[' | b']' | b'{' | b'}' | b'"' | RS => true,
+        _ => false,
+    }
+}
+
+impl<R: BufRead> JsonRecords<R> {
+    pub fn new(src: R) -> Self {
+        JsonRecords {
+            src,
+            pos: Position::default(),
+        }
+    }
+
+    // Lines consumed so far, counting a partly read one.
+    pub fn lines(&self) -> usize {
+        self.pos.lines()
+    }
+
+    // The source, positioned right after the last record returned.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.src
+    }
+}
+
+impl Position {
+    fn lines(&self) -> usize {
+        self.lines + self.partial as usize
+    }
+
+    fn advance(&mut self, b: u8) {
+        if b == b'\n' {
+            self.lines += 1;
+            self.partial = false;
+        } else {
+            self.partial = true;
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonRecords<R> {
+    type Item = io::Result<JsonRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut state = State::Idle;
+        let mut data = Vec::new();
+        let mut line = 0;
+        let mut depth = 0usize;
+        let mut in_str = false;
+        let mut esc = false;
+        let mut line_start = false;
+        let pos = &mut self.pos;
+        loop {
+            let (used, complete) = {
+                let buf = match self.src.fill_buf() {
+                    Ok(buf) => buf,
+                    // Between records an idle source is reported, so the
+                    // caller can act on what it has read so far.
+                    Err(e) => {
+                        if e.kind() == io::ErrorKind::Interrupted && state != State::Idle {
+                            continue;
+                        }
+                        return Some(Err(e));
+                    }
+                };
+                if buf.is_empty() {
+                    if state == State::Idle {
+                        return None;
+                    }
+                    return Some(Ok(JsonRecord {
+                        line,
+                        end: pos.lines(),
+                        data,
+                        complete: state == State::Scalar,
+                    }));
+                }
+                let mut start = 0;
+                let mut i = 0;
+                let mut complete = None;
+                while i < buf.len() {
+                    let b = buf[i];
+                    match state {
+                        State::Idle if pos.skip => pos.skip = b != b'\n' && b != RS,
+                        // Separators are skipped even outside an array, so a
+                        // stream resumed past its opening bracket still reads.
+                        State::Idle => match b {
+                            b' ' | b'\t' | b'\r' | b'\n' | b',' | RS => {}
+                            b'[' if !pos.array => pos.array = true,
+                            b']' => pos.array = false,
+                            _ => {
+                                line = pos.lines + 1;
+                                start = i;
+                                state = match b {
+                                    b'{' | b'[' | b'"' => State::Value,
+                                    _ => State::Scalar,
+                                };
+                                if state == State::Scalar {
+                                    pos.advance(b);
+                                    i += 1;
+                                    continue;
+                                }
+                            }
+                        },
+                        State::Scalar => {
+                            if delimiter(b) {
+                                complete = Some(true);
+                                break;
+                            }
+                        }
+                        State::Value => {}
+                    }
+                    if state == State::Value {
+                        // A top level object at the start of a line or a new
+                        // datagram means the open record was cut short.
+                        if b == RS || (line_start && b == b'{') {
+                            complete = Some(false);
+                            break;
+                        }
+                        if b != b'\r' && b != b'\n' {
+                            line_start = false;
+                        }
+                        if in_str {
+                            if esc {
+                                esc = false;
+                            } else if b == b'\\' {
+                                esc = true;
+                            } else if b == b'\n' {
+                                complete = Some(false);
+                                break;
+                            } else if b == b'"' {
+                                in_str = false;
+                            }
+                        } else {
+                            match b {
+                                b'\n' => line_start = true,
+                                b'"' => in_str = true,
+                                b'{' | b'[' => depth += 1,
+                                b'}' | b']' => depth = depth.saturating_sub(1),
+                                _ => {}
+                            }
+                        }
+                        if depth == 0 && !in_str {
+                            pos.advance(b);
+                            i += 1;
+                            complete = Some(true);
+                            break;
+                        }
+                        if data.len() + i - start >= MAX_RECORD {
+                            pos.skip = b != b'\n';
+                            pos.advance(b);
+                            i += 1;
+                            complete = Some(false);
+                            break;
+                        }
+                    }
+                    pos.advance(b);
+                    i += 1;
+                }
+                if state != State::Idle {
+                    data.extend_from_slice(&buf[start..i]);
+                }
+                if complete == Some(false) {
+                    while data.last().map_or(false, |b| b.is_ascii_whitespace()) {
+                        data.pop();
+                    }
+                }
+                (i, complete)
+            };
+            self.src.consume(used);
+            if let Some(complete) = complete {
+                return Some(Ok(JsonRecord {
+                    line,
+                    end: pos.lines(),
+                    data,
+                    complete,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(input: &str) -> Vec<(usize, String, bool)> {
+        JsonRecords::new(input.as_bytes())
+            .map(|r| {
+                let r = r.unwrap();
+                (r.line, String::from_utf8(r.data).unwrap(), r.complete)
+            })
+            .collect()
+    }
+
+    fn values(input: &str) -> Vec<String> {
+        records(input).into_iter().map(|(_, v, _)| v).collect()
+    }
+
+    #[test]
+    fn split_records() {
+        let lines = "{\"a\": 1}\n\n{\"b\": [1, {\"c\": \"}\"}]}\n";
+        assert_eq!(values(lines), vec!["{\"a\": 1}", "{\"b\": [1, {\"c\": \"}\"}]}"]);
+        assert_eq!(values("[\n{\"a\": 1}\n, {\"b\": 2}\n]\n"), vec!["{\"a\": 1}", "{\"b\": 2}"]);
+        assert_eq!(values("[{\"a\": 1},{\"b\": 2}]"), vec!["{\"a\": 1}", "{\"b\": 2}"]);
+        assert_eq!(values("{\"a\": \"\\\"{\"}{\"b\": 2} 3"), vec!["{\"a\": \"\\\"{\"}", "{\"b\": 2}", "3"]);
+        assert_eq!(values("[[1, 2], [3]]"), vec!["[1, 2]", "[3]"]);
+        assert_eq!(
+            records("{\n  \"a\": 1,\n  \"b\": [\n    2\n  ]\n}\n{\"c\": 3}"),
+            vec![
+                (1, "{\n  \"a\": 1,\n  \"b\": [\n    2\n  ]\n}".to_string(), true),
+                (7, "{\"c\": 3}".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn resync_on_bad_records() {
+        assert_eq!(
+            records("{\"a\": \"cut\n{\"b\": 2}\n{\"c\": ["),
+            vec![
+                (1, "{\"a\": \"cut".to_string(), false),
+                (2, "{\"b\": 2}".to_string(), true),
+                (3, "{\"c\": [".to_string(), false),
+            ]
+        );
+        let mut recs = JsonRecords::new(&b"{\"a\": 1}\n, {\"b\": 2} ]\n\n"[..]);
+        assert_eq!(recs.next().unwrap().unwrap().data, b"{\"a\": 1}");
+        assert_eq!(recs.lines(), 1);
+        assert_eq!(recs.next().unwrap().unwrap().data, b"{\"b\": 2}");
+        assert!(recs.next().is_none());
+        assert_eq!(recs.lines(), 3);
+    }
+
+    #[test]
+    fn cut_unclosed_records() {
+        assert_eq!(
+            records("{\"a\": [1,\n\n{\"b\": 2}\n"),
+            vec![
+                (1, "{\"a\": [1,".to_string(), false),
+                (3, "{\"b\": 2}".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            records("{\"a\": [\n  {\"b\": 2}\n]}"),
+            vec![(1, "{\"a\": [\n  {\"b\": 2}\n]}".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn cut_at_datagrams() {
+        assert_eq!(
+            records("\x1e{\"a\": [1,\n\x1e  {\"b\": 2}\n\x1e3\n"),
+            vec![
+                (1, "{\"a\": [1,".to_string(), false),
+                (2, "{\"b\": 2}".to_string(), true),
+                (3, "3".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            records("\x1e{\"a\": \"x\x1e{\"b\": 1}\n"),
+            vec![
+                (1, "{\"a\": \"x".to_string(), false),
+                (1, "{\"b\": 1}".to_string(), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    borrow::Cow,
+    io::{self, Read},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use views::Queues;
+
+use super::{ErrorSink, IngestError, IngestReport};
+
+pub type JobResult = Result<IngestReport, Cow<'static, str>>;
+
+#[derive(Debug, Default)]
+pub struct Progress {
+    bytes: AtomicUsize,
+    lines: AtomicUsize,
+    records: AtomicUsize,
+    errors: AtomicUsize,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl Progress {
+    pub fn set_lines(&self, n: usize) {
+        self.lines.store(n, Ordering::Relaxed);
+    }
+
+    pub fn add_record(&self) {
+        self.records.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_errors(&self, n: usize) {
+        self.errors.store(n, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub struct ProgressReader<R: Read> {
+    inner: R,
+    progress: Arc<Progress>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, progress: Arc<Progress>) -> Self {
+        ProgressReader { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Formats stop a cancelled job between records. A listener with no
+        // input pending ends here instead, or the job would wait forever.
+        let n = match self.inner.read(buf) {
+            Ok(n) => n,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::Interrupted && self.progress.is_cancelled() {
+                    return Ok(0);
+                }
+                return Err(e);
+            }
+        };
+        self.progress.bytes.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+pub struct ProgressSink<'a> {
+    inner: &'a mut ErrorSink,
+    progress: Arc<Progress>,
+}
+
+impl<'a> ProgressSink<'a> {
+    pub fn new(inner: &'a mut ErrorSink, progress: Arc<Progress>) -> Self {
+        ProgressSink { inner, progress }
+    }
+}
+
+impl<'a> ErrorSink for ProgressSink<'a> {
+    fn report(&mut self, err: &IngestError) {
+        self.inner.report(err)
+    }
+
+    fn progress(&self) -> Option<Arc<Progress>> {
+        Some(self.progress.clone())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IngestProgress {
+    pub bytes: usize,
+    pub lines: usize,
+    pub records: usize,
+    pub errors: usize,
+    pub pvm_queue: usize,
+    pub view_queues: Vec<(usize, usize)>,
+    pub cancelled: bool,
+    pub finished: bool,
+}
+
+struct JobState {
+    thread: Option<JoinHandle<JobResult>>,
+    result: Option<JobResult>,
+}
+
+#[derive(Clone)]
+pub struct IngestHandle {
+    progress: Arc<Progress>,
+    queues: Queues,
+    state: Arc<Mutex<JobState>>,
+}
+
+impl IngestHandle {
+    pub fn spawn<F>(queues: Queues, f: F) -> Self
+    where
+        F: FnOnce(Arc<Progress>) -> JobResult + Send + 'static,
+    {
+        let progress = Arc::new(Progress::default());
+        let thread_progress = progress.clone();
+        let thread = thread::spawn(move || {
+            let p = thread_progress.clone();
+            let ret = panic::catch_unwind(AssertUnwindSafe(|| f(p)))
+                .unwrap_or_else(|_| Err("Ingest thread panicked".into()));
+            thread_progress.finished.store(true, Ordering::Release);
+            ret
+        });
+        IngestHandle {
+            progress,
+            queues,
+            state: Arc::new(Mutex::new(JobState {
+                thread: Some(thread),
+                result: None,
+            })),
+        }
+    }
+
+    pub fn progress(&self) -> IngestProgress {
+        let p = &self.progress;
+        IngestProgress {
+            bytes: p.bytes.load(Ordering::Relaxed),
+            lines: p.lines.load(Ordering::Relaxed),
+            records: p.records.load(Ordering::Relaxed),
+            errors: p.errors.load(Ordering::Relaxed),
+            pvm_queue: self.queues.input().depth(),
+            view_queues: self.queues
+                .views()
+                .iter()
+                .map(|(id, q)| (*id, q.depth()))
+                .collect(),
+            cancelled: p.is_cancelled(),
+            finished: self.is_finished(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress.finished.load(Ordering::Acquire)
+    }
+
+    pub fn cancel(&self) {
+        self.progress.cancel();
+    }
+
+    pub fn wait(&self) -> JobResult {
+        let mut state = self.state.lock().unwrap();
+        if let Some(thread) = state.thread.take() {
+            state.result = Some(
+                thread
+                    .join()
+                    .unwrap_or_else(|_| Err("Ingest thread panicked".into())),
+            );
+        }
+        state.result.clone().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use views::{queue, ViewCoordinator};
+
+    struct Idle;
+
+    impl Read for Idle {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::Interrupted.into())
+        }
+    }
+
+    #[test]
+    fn reader_stops_idle_on_cancel() {
+        let progress = Arc::new(Progress::default());
+        let mut r = ProgressReader::new(Cursor::new("abc\ndef\n"), progress.clone());
+        let mut buf = [0; 6];
+        progress.cancel();
+        assert_eq!(r.read(&mut buf).unwrap(), 6);
+        assert_eq!(progress.bytes.load(Ordering::Relaxed), 6);
+        let mut r = ProgressReader::new(Idle, progress.clone());
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn wait_and_progress() {
+        let (_send, recv) = queue(1);
+        let coord = ViewCoordinator::new(recv);
+        let job = IngestHandle::spawn(coord.queues(), |p| {
+            p.set_lines(3);
+            Ok(IngestReport {
+                lines: 3,
+                ..Default::default()
+            })
+        });
+        assert_eq!(job.wait().unwrap().lines, 3);
+        assert_eq!(job.clone().wait().unwrap().lines, 3);
+        let p = job.progress();
+        assert!(p.finished);
+        assert_eq!((p.lines, p.pvm_queue), (3, 0));
+    }
+}
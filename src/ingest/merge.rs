@@ -0,0 +1,231 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use super::{
+    format::{Framing, LineTime},
+    JsonRecords,
+};
+
+type Key = Reverse<(i64, usize, u64)>;
+
+type Sender = mpsc::SyncSender<io::Result<Vec<u8>>>;
+
+fn read_lines<R: Read>(src: R, tx: &Sender) {
+    let mut src = BufReader::new(src);
+    loop {
+        let mut line = Vec::new();
+        let ret = match src.read_until(b'\n', &mut line) {
+            Ok(0) => return,
+            Ok(_) => {
+                if line.last() != Some(&b'\n') {
+                    line.push(b'\n');
+                }
+                tx.send(Ok(line))
+            }
+            Err(e) => {
+                tx.send(Err(e)).ok();
+                return;
+            }
+        };
+        if ret.is_err() {
+            return;
+        }
+    }
+}
+
+// Each JSON record goes out on a line of its own, so the merged stream is
+// JSON Lines whatever the layout of the inputs.
+fn read_json<R: Read>(src: R, tx: &Sender) {
+    for rec in JsonRecords::new(BufReader::new(src)) {
+        let ret = match rec {
+            Ok(rec) => {
+                let mut data = rec.data;
+                data.push(b'\n');
+                tx.send(Ok(data))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                tx.send(Err(e)).ok();
+                return;
+            }
+        };
+        if ret.is_err() {
+            return;
+        }
+    }
+}
+
+fn record(line: &[u8]) -> &[u8] {
+    match line.iter().rposition(|b| *b != b'\n' && *b != b'\r') {
+        Some(n) => &line[..n + 1],
+        None => &line[..0],
+    }
+}
+
+// K-way merge of streams by event time, a record at a time. Every stream
+// keeps up to `window` records buffered, so records a stream has slightly out
+// of order are still sorted, and each stream is read on its own thread.
+// Records without a time keep the time of the record before them in the same
+// stream. Equal times are ordered by stream and then by position, so the
+// output is deterministic.
+pub struct MergeReader {
+    sources: Vec<Option<Receiver<io::Result<Vec<u8>>>>>,
+    last: Vec<i64>,
+    heap: BinaryHeap<(Key, Vec<u8>)>,
+    seq: u64,
+    time: LineTime,
+    window: usize,
+    started: bool,
+    cur: Cursor<Vec<u8>>,
+}
+
+impl MergeReader {
+    pub fn new(
+        streams: Vec<Box<Read + Send>>,
+        framing: Framing,
+        time: LineTime,
+        window: usize,
+    ) -> Self {
+        let window = window.max(1);
+        let sources: Vec<_> = streams
+            .into_iter()
+            .map(|src| {
+                let (tx, rx) = mpsc::sync_channel(window);
+                thread::spawn(move || match framing {
+                    Framing::Lines => read_lines(src, &tx),
+                    Framing::Json => read_json(src, &tx),
+                });
+                Some(rx)
+            })
+            .collect();
+        MergeReader {
+            last: vec![i64::min_value(); sources.len()],
+            sources,
+            heap: BinaryHeap::new(),
+            seq: 0,
+            time,
+            window,
+            started: false,
+            cur: Cursor::new(Vec::new()),
+        }
+    }
+
+    fn pull(&mut self, src: usize) -> io::Result<()> {
+        let line = match self.sources[src].as_ref().map(|rx| rx.recv()) {
+            Some(Ok(Ok(line))) => line,
+            Some(Ok(Err(e))) => {
+                self.sources[src] = None;
+                return Err(e);
+            }
+            Some(Err(_)) | None => {
+                self.sources[src] = None;
+                return Ok(());
+            }
+        };
+        let t = (self.time)(record(&line)).unwrap_or(self.last[src]);
+        self.last[src] = t;
+        self.heap.push((Reverse((t, src, self.seq)), line));
+        self.seq += 1;
+        Ok(())
+    }
+
+    // Every live stream needs a record in the heap before anything is emitted,
+    // so fill all of them even if one fails.
+    fn start(&mut self) -> io::Result<()> {
+        self.started = true;
+        let mut ret = Ok(());
+        for src in 0..self.sources.len() {
+            for _ in 0..self.window {
+                if self.sources[src].is_none() {
+                    break;
+                }
+                if let Err(e) = self.pull(src) {
+                    ret = ret.and(Err(e));
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl Read for MergeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.cur.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            if !self.started {
+                self.start()?;
+            }
+            let (Reverse((_, src, _)), line) = match self.heap.pop() {
+                Some(next) => next,
+                None => return Ok(0),
+            };
+            self.cur = Cursor::new(line);
+            self.pull(src)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num_time(line: &[u8]) -> Option<i64> {
+        ::std::str::from_utf8(line).ok()?.parse().ok()
+    }
+
+    fn json_time(rec: &[u8]) -> Option<i64> {
+        ::serde_json::from_slice::<::serde_json::Value>(rec).ok()?["t"].as_i64()
+    }
+
+    fn merge_as(
+        framing: Framing,
+        time: LineTime,
+        streams: &[&'static str],
+        window: usize,
+    ) -> String {
+        let streams = streams
+            .iter()
+            .map(|s| Box::new(Cursor::new(*s)) as Box<Read + Send>)
+            .collect();
+        let mut out = String::new();
+        MergeReader::new(streams, framing, time, window)
+            .read_to_string(&mut out)
+            .unwrap();
+        out
+    }
+
+    fn merge(streams: &[&'static str], window: usize) -> String {
+        merge_as(Framing::Lines, num_time, streams, window)
+    }
+
+    #[test]
+    fn merge_by_time() {
+        assert_eq!(merge(&["1\n4\n5\n", "2\n3\n6"], 1), "1\n2\n3\n4\n5\n6\n");
+        assert_eq!(merge(&["x\n1\n3\n", "2\n"], 1), "x\n1\n2\n3\n");
+        assert_eq!(merge(&["2\n1\n4\n", "3\n"], 1), "2\n1\n3\n4\n");
+        assert_eq!(merge(&["2\n1\n4\n", "3\n"], 2), "1\n2\n3\n4\n");
+        assert_eq!(merge(&[], 4), "");
+    }
+
+    #[test]
+    fn merge_json_records() {
+        let streams = [
+            "[\n{\"t\": 1}\n, {\"t\": 4}\n]\n",
+            "{\"t\": 2} {\"t\":\n 3}",
+            "[{\"t\": 5}, {\"t\": 0, \"x\": \"cut\n",
+        ];
+        assert_eq!(
+            merge_as(Framing::Json, json_time, &streams, 1),
+            "{\"t\": 1}\n{\"t\": 2}\n{\"t\":\n 3}\n{\"t\": 4}\n{\"t\": 5}\n\
+             {\"t\": 0, \"x\": \"cut\n"
+        );
+    }
+}
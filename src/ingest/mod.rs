@@ -1,9 +1,29 @@
+mod checkpoint;
 mod db;
+pub mod format;
+mod framing;
+mod job;
+mod merge;
+mod net;
 pub mod pvm;
+mod report;
+
+pub(crate) use self::checkpoint::NodeState;
+pub use self::checkpoint::{Checkpoint, SkipLines, SkipRecords};
+pub use self::format::{FormatRegistry, Framing, JsonFormat, LineTime, TraceFormat};
+pub use self::framing::{JsonRecord, JsonRecords, RS};
+pub use self::job::{
+    IngestHandle, IngestProgress, JobResult, Progress, ProgressReader, ProgressSink,
+};
+pub use self::merge::MergeReader;
+pub use self::report::{
+    ErrorClass, ErrorPolicy, ErrorSink, ErrorTracker, IngestError, IngestErrorKind, IngestReport,
+    StderrSink,
+};
 
 use std::{
     fmt::Display,
-    io::{BufRead, BufReader, Read},
+    io::{self, BufReader, Read},
 };
 
 use rayon::prelude::*;
@@ -18,71 +38,238 @@ const BATCH_SIZE: usize = 0x80_000;
 pub trait Parseable: DeserializeOwned + Display + Send + Sized {
     fn init(pvm: &mut PVM);
     fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError>;
+    fn line_time(_line: &[u8]) -> Option<i64> {
+        None
+    }
 }
 
-pub fn ingest_stream<R: Read, T: Parseable>(stream: R, pvm: &mut PVM) {
-    let mut pre_vec: Vec<(usize, String)> = Vec::with_capacity(BATCH_SIZE);
-    let mut post_vec: Vec<(usize, Option<T>)> = Vec::with_capacity(BATCH_SIZE);
-    let mut lines = BufReader::new(stream).lines().enumerate();
+pub fn ingest_stream<R: Read, T: Parseable>(
+    stream: R,
+    pvm: &mut PVM,
+    policy: ErrorPolicy,
+    sink: &mut ErrorSink,
+) -> IngestReport {
+    let mut pre_vec: Vec<JsonRecord> = Vec::with_capacity(BATCH_SIZE);
+    let mut post_vec: Vec<Result<T, serde_json::Error>> = Vec::with_capacity(BATCH_SIZE);
+    let mut records = JsonRecords::new(BufReader::new(stream));
+    let mut tracker = ErrorTracker::new(policy, sink);
+    let mut consumed = 0;
 
     T::init(pvm);
 
-    loop {
+    // Lines read when the last I/O error was reported.
+    let mut io_err = None;
+    let mut done = false;
+    while !done {
         pre_vec.clear();
         while pre_vec.len() < BATCH_SIZE {
-            let (n, mut l) = match lines.next() {
-                Some((n, l)) => match l {
-                    Ok(l) => (n, l),
-                    Err(perr) => {
-                        eprintln!("Line: {}", n + 1);
-                        eprintln!("File Reading error: {}", perr);
-                        continue;
+            if tracker.cancelled() {
+                done = true;
+                break;
+            }
+            match records.next() {
+                Some(Ok(rec)) => {
+                    tracker.line(rec.end);
+                    pre_vec.push(rec);
+                }
+                // The source is idle, a listener between events say, so the
+                // records it sent so far are applied now.
+                Some(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {
+                    if !pre_vec.is_empty() {
+                        break;
                     }
-                },
+                }
+                Some(Err(perr)) => {
+                    let line = records.lines();
+                    tracker.line(line);
+                    let abort = tracker.error(IngestError {
+                        kind: IngestErrorKind::Io,
+                        line,
+                        msg: perr.to_string(),
+                        record: None,
+                    });
+                    // An error that repeats without the input moving on would
+                    // otherwise be skipped forever.
+                    if abort || io_err == Some(line) {
+                        done = true;
+                        break;
+                    }
+                    io_err = Some(line);
+                }
                 None => {
+                    tracker.line(records.lines());
+                    done = true;
                     break;
                 }
-            };
-            if l.is_empty() {
-                continue;
-            }
-            if l == "[" || l == "]" {
-                continue;
             }
-            if l.starts_with(", ") {
-                l.drain(0..2);
-            }
-            pre_vec.push((n, l));
         }
 
+        // Records read before the input stopped are still applied.
         pre_vec
             .par_iter()
-            .map(|(n, s)| match serde_json::from_slice(s.as_bytes()) {
-                Ok(evt) => (*n, Some(evt)),
-                Err(perr) => {
-                    eprintln!("Line: {}", n + 1);
-                    eprintln!("JSON Parsing error: {}", perr);
-                    eprintln!("{}", s);
-                    (*n, None)
-                }
-            })
+            .map(|rec| serde_json::from_slice(&rec.data))
             .collect_into(&mut post_vec);
 
-        for (n, tr) in post_vec.drain(..) {
-            if let Some(tr) = tr {
-                if let Err(e) = tr.parse(pvm) {
-                    eprintln!("Line: {}", n + 1);
-                    eprintln!("PVM Parsing error: {}", e);
-                    eprintln!("{}", tr);
+        for (rec, tr) in pre_vec.drain(..).zip(post_vec.drain(..)) {
+            consumed += 1;
+            let err = match tr {
+                Ok(tr) => {
+                    tracker.record();
+                    match tr.parse(pvm) {
+                        Ok(_) => continue,
+                        Err(e) => IngestError::from_pvm(rec.line, &e, tr.to_string()),
+                    }
                 }
+                Err(perr) => IngestError {
+                    kind: IngestErrorKind::Json,
+                    line: rec.line,
+                    msg: perr.to_string(),
+                    record: Some(String::from_utf8_lossy(&rec.data).into_owned()),
+                },
+            };
+            if tracker.error(err) {
+                tracker.line(rec.end);
+                done = true;
+                break;
+            }
+        }
+    }
+    let mut report = tracker.report;
+    report.offset = consumed;
+    report.unhandled.extend(pvm.unparsed_events.drain());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::Cell, fmt, io::Cursor, sync::Arc};
+
+    use views::queue;
+
+    #[derive(Deserialize)]
+    struct Rec {
+        n: u64,
+    }
+
+    impl Display for Rec {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.n)
+        }
+    }
+
+    thread_local! {
+        static PARSED: Cell<usize> = Cell::new(0);
+    }
+
+    impl Parseable for Rec {
+        fn init(_pvm: &mut PVM) {}
+
+        fn parse(&self, _pvm: &mut PVM) -> Result<(), PVMError> {
+            PARSED.with(|p| p.set(p.get() + 1));
+            Ok(())
+        }
+    }
+
+    // Reads a few bytes at a time and cancels the job once `at` bytes are
+    // read.
+    struct CancelAt {
+        data: Cursor<Vec<u8>>,
+        at: u64,
+        progress: Arc<Progress>,
+    }
+
+    impl Read for CancelAt {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(4);
+            let n = self.data.read(&mut buf[..len])?;
+            if self.data.position() >= self.at {
+                self.progress.cancel();
             }
+            Ok(n)
         }
-        if pre_vec.len() < BATCH_SIZE {
-            break;
+    }
+
+    fn run<R: Read>(r: R, progress: Arc<Progress>, policy: ErrorPolicy) -> IngestReport {
+        let (send, _recv) = queue(16);
+        let mut pvm = PVM::new(send);
+        let mut errs = |_: &IngestError| {};
+        ingest_stream::<_, Rec>(r, &mut pvm, policy, &mut ProgressSink::new(&mut errs, progress))
+    }
+
+    #[test]
+    fn cancel_between_records() {
+        let progress = Arc::new(Progress::default());
+        let r = CancelAt {
+            data: Cursor::new(b"{\n  \"n\": 1\n}\n{\n  \"n\": 2\n}\n".to_vec()),
+            at: 12,
+            progress: progress.clone(),
+        };
+        let report = run(r, progress, ErrorPolicy::Skip);
+        assert_eq!((report.records, report.offset, report.lines), (1, 1, 3));
+    }
+
+    // Yields its data, then fails on every read.
+    struct Failing(Cursor<Vec<u8>>);
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(io::Error::new(io::ErrorKind::Other, "bad sector")),
+                n => Ok(n),
+            }
+        }
+    }
+
+    #[test]
+    fn io_error_keeps_batch() {
+        let r = Failing(Cursor::new(b"{\"n\": 1}\n{\"n\": 2}\n".to_vec()));
+        let report = run(r, Arc::new(Progress::default()), ErrorPolicy::AbortOnFirst);
+        assert!(report.aborted);
+        assert_eq!((report.records, report.offset), (2, 2));
+    }
+
+    #[test]
+    fn io_error_repeating() {
+        let r = Failing(Cursor::new(b"{\"n\": 1}\n".to_vec()));
+        let report = run(r, Arc::new(Progress::default()), ErrorPolicy::Skip);
+        assert_eq!((report.records, report.errors()), (1, 2));
+    }
+
+    // Goes idle once its data is read, then ends noting how many records
+    // had been applied by then.
+    struct Idle {
+        data: Cursor<Vec<u8>>,
+        idle: bool,
+        parsed: usize,
+    }
+
+    impl Read for Idle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.data.read(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            if !self.idle {
+                self.idle = true;
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            self.parsed = PARSED.with(|p| p.get());
+            Ok(0)
         }
     }
-    println!("Missing Events:");
-    for evt in pvm.unparsed_events.drain() {
-        println!("{}", evt);
+
+    #[test]
+    fn idle_flushes_batch() {
+        let mut r = Idle {
+            data: Cursor::new(b"{\"n\": 1}\n{\"n\": 2}\n".to_vec()),
+            idle: false,
+            parsed: 0,
+        };
+        let before = PARSED.with(|p| p.get());
+        let report = run(&mut r, Arc::new(Progress::default()), ErrorPolicy::AbortOnFirst);
+        assert_eq!((report.records, report.errors()), (2, 0));
+        assert_eq!(r.parsed - before, 2);
     }
 }
@@ -0,0 +1,327 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+};
+
+use uuid::Uuid;
+
+const MAX_PENDING: usize = 1024;
+const MAX_CONNECTING: usize = 0x10000;
+
+pub type Endpoint = (String, u16);
+
+fn is_loopback(addr: &str) -> bool {
+    match addr.parse::<IpAddr>() {
+        Ok(a) => a.is_loopback(),
+        Err(_) => addr == "localhost",
+    }
+}
+
+fn is_unspecified(addr: &str) -> bool {
+    match addr.parse::<IpAddr>() {
+        Ok(a) => a.is_unspecified(),
+        Err(_) => addr == "*",
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct PendingConn {
+    host: Uuid,
+    addr: String,
+    sock: Uuid,
+}
+
+type Flow = (Option<Uuid>, Endpoint, Endpoint);
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct ConnState {
+    flows: Vec<(Flow, Uuid)>,
+    bound: Vec<(Uuid, Endpoint)>,
+    pending: Vec<(u16, VecDeque<PendingConn>)>,
+    #[serde(default)]
+    addrs: Vec<(Uuid, String)>,
+}
+
+#[derive(Default)]
+pub struct ConnTracker {
+    flows: HashMap<Flow, Uuid>,
+    bound: HashMap<Uuid, Endpoint>,
+    pending: HashMap<u16, VecDeque<PendingConn>>,
+    addrs: HashSet<(Uuid, String)>,
+    // Indexes into flows and pending by socket, rebuilt on restore.
+    socks: HashMap<Uuid, Vec<Flow>>,
+    connecting: HashMap<Uuid, u16>,
+    order: VecDeque<Uuid>,
+}
+
+impl ConnTracker {
+    pub fn new() -> Self {
+        ConnTracker::default()
+    }
+
+    fn scope(host: Uuid, a: &str, b: &str) -> Option<Uuid> {
+        if is_loopback(a) || is_loopback(b) {
+            Some(host)
+        } else {
+            None
+        }
+    }
+
+    // Remembers which host an address belongs to, so connects can be matched
+    // to listeners bound to a wildcard address.
+    fn learn(&mut self, host: Uuid, addr: &str) {
+        if !is_loopback(addr) && !is_unspecified(addr) {
+            self.addrs.insert((host, addr.to_string()));
+        }
+    }
+
+    pub fn flow(
+        &mut self,
+        sock: Uuid,
+        host: Uuid,
+        local: Endpoint,
+        foreign: Endpoint,
+    ) -> Option<Uuid> {
+        self.learn(host, &local.0);
+        let scope = ConnTracker::scope(host, &local.0, &foreign.0);
+        let peer = self
+            .flows
+            .get(&(scope, foreign.clone(), local.clone()))
+            .cloned();
+        let key = (scope, local, foreign);
+        self.socks.entry(sock).or_insert_with(Vec::new).push(key.clone());
+        self.flows.insert(key, sock);
+        peer.filter(|p| *p != sock)
+    }
+
+    pub fn bind(&mut self, sock: Uuid, host: Uuid, local: Endpoint) {
+        self.learn(host, &local.0);
+        self.bound.insert(sock, local);
+    }
+
+    pub fn connect(&mut self, sock: Uuid, host: Uuid, remote: Endpoint) {
+        self.unconnect(&sock);
+        let port = remote.1;
+        let dropped = {
+            let queue = self.pending.entry(port).or_insert_with(VecDeque::new);
+            queue.push_back(PendingConn {
+                host,
+                addr: remote.0,
+                sock,
+            });
+            if queue.len() > MAX_PENDING {
+                queue.pop_front()
+            } else {
+                None
+            }
+        };
+        if let Some(c) = dropped {
+            self.connecting.remove(&c.sock);
+        }
+        self.connecting.insert(sock, port);
+        self.order.push_back(sock);
+        // Connects that are never accepted expire oldest first.
+        while self.connecting.len() > MAX_CONNECTING {
+            match self.order.pop_front() {
+                Some(old) => self.unconnect(&old),
+                None => break,
+            }
+        }
+        if self.order.len() > 2 * MAX_CONNECTING {
+            let connecting = &self.connecting;
+            self.order.retain(|s| connecting.contains_key(s));
+        }
+    }
+
+    fn unconnect(&mut self, sock: &Uuid) {
+        if let Some(port) = self.connecting.remove(sock) {
+            let empty = match self.pending.get_mut(&port) {
+                Some(queue) => {
+                    queue.retain(|c| c.sock != *sock);
+                    queue.is_empty()
+                }
+                None => false,
+            };
+            if empty {
+                self.pending.remove(&port);
+            }
+        }
+    }
+
+    pub fn accept(&mut self, listener: Uuid, host: Uuid) -> Option<Uuid> {
+        let (laddr, lport) = self.bound.get(&listener)?.clone();
+        let sock = {
+            let queue = self.pending.get(&lport)?;
+            let addrs = &self.addrs;
+            queue
+                .iter()
+                .find(|c| {
+                    (c.addr == laddr || is_unspecified(&laddr))
+                        && if is_loopback(&c.addr) {
+                            c.host == host
+                        } else {
+                            addrs.contains(&(host, c.addr.clone()))
+                        }
+                })?
+                .sock
+        };
+        self.unconnect(&sock);
+        Some(sock)
+    }
+
+    pub fn release(&mut self, sock: &Uuid) {
+        self.bound.remove(sock);
+        self.unconnect(sock);
+        if let Some(keys) = self.socks.remove(sock) {
+            for key in keys {
+                if self.flows.get(&key) == Some(sock) {
+                    self.flows.remove(&key);
+                }
+            }
+        }
+    }
+
+    pub fn save(&self) -> ConnState {
+        ConnState {
+            flows: self.flows.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+            bound: self.bound.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            pending: self.pending.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            addrs: self.addrs.iter().cloned().collect(),
+        }
+    }
+
+    pub fn restore(state: ConnState) -> Self {
+        let mut socks = HashMap::new();
+        for &(ref key, sock) in &state.flows {
+            socks.entry(sock).or_insert_with(Vec::new).push(key.clone());
+        }
+        let mut connecting = HashMap::new();
+        let mut order = VecDeque::new();
+        for &(port, ref queue) in &state.pending {
+            for c in queue {
+                connecting.insert(c.sock, port);
+                order.push_back(c.sock);
+            }
+        }
+        ConnTracker {
+            flows: state.flows.into_iter().collect(),
+            bound: state.bound.into_iter().collect(),
+            pending: state.pending.into_iter().collect(),
+            addrs: state.addrs.into_iter().collect(),
+            socks,
+            connecting,
+            order,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uuid(n: u8) -> Uuid {
+        Uuid::from_bytes(&[n; 16]).unwrap()
+    }
+
+    fn ep(addr: &str, port: u16) -> Endpoint {
+        (addr.to_string(), port)
+    }
+
+    #[test]
+    fn flow_same_host() {
+        let mut ct = ConnTracker::new();
+        let h = uuid(1);
+        assert_eq!(ct.flow(uuid(2), h, ep("127.0.0.1", 80), ep("127.0.0.1", 5000)), None);
+        assert_eq!(
+            ct.flow(uuid(3), h, ep("127.0.0.1", 5000), ep("127.0.0.1", 80)),
+            Some(uuid(2))
+        );
+    }
+
+    #[test]
+    fn flow_loopback_is_host_local() {
+        let mut ct = ConnTracker::new();
+        ct.flow(uuid(2), uuid(1), ep("127.0.0.1", 80), ep("127.0.0.1", 5000));
+        assert_eq!(
+            ct.flow(uuid(3), uuid(9), ep("127.0.0.1", 5000), ep("127.0.0.1", 80)),
+            None
+        );
+    }
+
+    #[test]
+    fn flow_cross_host() {
+        let mut ct = ConnTracker::new();
+        ct.flow(uuid(2), uuid(1), ep("10.0.0.1", 80), ep("10.0.0.2", 5000));
+        assert_eq!(
+            ct.flow(uuid(3), uuid(9), ep("10.0.0.2", 5000), ep("10.0.0.1", 80)),
+            Some(uuid(2))
+        );
+    }
+
+    #[test]
+    fn accept_matches_connect() {
+        let mut ct = ConnTracker::new();
+        ct.bind(uuid(2), uuid(1), ep("0.0.0.0", 22));
+        ct.connect(uuid(3), uuid(9), ep("10.0.0.1", 22));
+        assert_eq!(ct.accept(uuid(2), uuid(1)), None);
+        ct.bind(uuid(5), uuid(1), ep("10.0.0.1", 8080));
+        ct.connect(uuid(4), uuid(9), ep("10.0.0.1", 23));
+        assert_eq!(ct.accept(uuid(2), uuid(1)), Some(uuid(3)));
+        assert_eq!(ct.accept(uuid(2), uuid(1)), None);
+    }
+
+    #[test]
+    fn accept_ignores_remote_loopback() {
+        let mut ct = ConnTracker::new();
+        ct.bind(uuid(2), uuid(1), ep("127.0.0.1", 22));
+        ct.connect(uuid(3), uuid(9), ep("127.0.0.1", 22));
+        assert_eq!(ct.accept(uuid(2), uuid(1)), None);
+    }
+
+    #[test]
+    fn accept_same_port_on_two_hosts() {
+        let mut ct = ConnTracker::new();
+        ct.bind(uuid(2), uuid(1), ep("0.0.0.0", 80));
+        ct.bind(uuid(3), uuid(7), ep("::", 80));
+        ct.flow(uuid(4), uuid(1), ep("10.0.0.1", 5000), ep("10.0.0.9", 443));
+        ct.flow(uuid(5), uuid(7), ep("10.0.0.5", 5000), ep("10.0.0.9", 443));
+        ct.connect(uuid(6), uuid(9), ep("10.0.0.5", 80));
+        assert_eq!(ct.accept(uuid(2), uuid(1)), None);
+        assert_eq!(ct.accept(uuid(3), uuid(7)), Some(uuid(6)));
+        ct.bind(uuid(8), uuid(7), ep("10.0.0.6", 80));
+        ct.connect(uuid(10), uuid(9), ep("10.0.0.6", 80));
+        assert_eq!(ct.accept(uuid(2), uuid(1)), None);
+        assert_eq!(ct.accept(uuid(8), uuid(7)), Some(uuid(10)));
+    }
+
+    #[test]
+    fn release_forgets_socket() {
+        let mut ct = ConnTracker::new();
+        ct.flow(uuid(2), uuid(1), ep("10.0.0.1", 80), ep("10.0.0.2", 5000));
+        ct.connect(uuid(3), uuid(9), ep("10.0.0.1", 22));
+        ct.release(&uuid(2));
+        ct.release(&uuid(3));
+        assert!(ct.flows.is_empty());
+        assert!(ct.pending.is_empty());
+        assert_eq!(
+            ct.flow(uuid(4), uuid(9), ep("10.0.0.2", 5000), ep("10.0.0.1", 80)),
+            None
+        );
+    }
+
+    #[test]
+    fn pending_expires() {
+        let mut ct = ConnTracker::new();
+        for i in 0..MAX_CONNECTING + 1 {
+            let mut bytes = [1u8; 16];
+            bytes[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            let sock = Uuid::from_bytes(&bytes).unwrap();
+            ct.connect(sock, uuid(9), ep("10.0.0.1", (i % 4000) as u16));
+        }
+        assert_eq!(ct.connecting.len(), MAX_CONNECTING);
+        assert_eq!(
+            ct.pending.values().map(VecDeque::len).sum::<usize>(),
+            MAX_CONNECTING
+        );
+    }
+}
@@ -4,7 +4,7 @@ use std::{
     mem,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        mpsc::SyncSender,
+        mpsc,
     },
 };
 
@@ -14,18 +14,43 @@ use data::{
         SchemaNode,
     },
     rel_types::{Inf, InfInit, Named, NamedInit, PVMOps, Rel},
-    Denumerate, Enumerable, HasID, MetaStore, RelGenerable, ID,
+    Enumerable, HasID, MetaStore, RelGenerable, ID,
 };
-use views::DBTr;
+use metrics::{Counters, Metrics};
+use views::{DBTr, QueueSender};
 
 use either::Either;
 use lending_library::{LendingLibrary, Loan};
 use uuid::Uuid;
 
-use super::db::DB;
+pub use super::net::Endpoint;
 
+use super::{
+    checkpoint::{Checkpoint, NodeState},
+    db::DB,
+    net::ConnTracker,
+};
+
+const METRICS_FLUSH: usize = 0x1000;
+
+#[derive(Debug)]
 pub enum PVMError {
     MissingField { evt: String, field: &'static str },
+    InvalidField {
+        evt: String,
+        field: &'static str,
+        value: String,
+    },
+    WrongNodeType {
+        id: ID,
+        expected: PVMDataType,
+        found: PVMDataType,
+    },
+    UnknownProperty { ty: &'static str, prop: String },
+    UnknownNode(ID),
+    UnknownRel(ID),
+    UnregisteredType(&'static str),
+    SessionNotOpen(Uuid),
 }
 
 impl Display for PVMError {
@@ -34,6 +59,33 @@ impl Display for PVMError {
             PVMError::MissingField { evt, field } => {
                 write!(f, "Event {} missing needed field {}", evt, field)
             }
+            PVMError::InvalidField { evt, field, value } => write!(
+                f,
+                "Event {} has invalid value {} for field {}",
+                evt, value, field
+            ),
+            PVMError::WrongNodeType {
+                id,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Node {} is a {} but was used as a {}",
+                id.inner(),
+                found,
+                expected
+            ),
+            PVMError::UnknownProperty { ty, prop } => {
+                write!(f, "Type {} does not have a property named {}", ty, prop)
+            }
+            PVMError::UnknownNode(id) => write!(f, "Node {} is not in the cache", id.inner()),
+            PVMError::UnknownRel(id) => write!(f, "Relation {} is not in the cache", id.inner()),
+            PVMError::UnregisteredType(ty) => write!(f, "Type {} has not been registered", ty),
+            PVMError::SessionNotOpen(uuid) => write!(
+                f,
+                "Object {} does not have an open edit session",
+                uuid.hyphenated()
+            ),
         }
     }
 }
@@ -55,17 +107,27 @@ pub struct PVM {
     ctx_type_cache: HashSet<&'static ContextType>,
     uuid_cache: HashMap<Uuid, ID>,
     node_cache: LendingLibrary<ID, DataNode>,
-    rel_src_dst_cache: HashMap<(&'static str, ID, ID), ID>,
+    rel_src_dst_cache: HashMap<(ID, ID), ID>,
     rel_cache: LendingLibrary<ID, Rel>,
     id_counter: AtomicUsize,
     open_cache: HashMap<Uuid, HashSet<Uuid>>,
     name_cache: LendingLibrary<Name, NameNode>,
+    names: Vec<Name>,
+    conn_tracker: ConnTracker,
+    pid_cache: HashMap<(Uuid, i32), Uuid>,
     cur_ctx: CtxStore,
-    pub unparsed_events: HashSet<String>,
+    pub unparsed_events: HashMap<String, usize>,
+    metrics: Metrics,
+    counters: Counters,
+    events: usize,
+    input: usize,
+    offset: usize,
+    restored: HashSet<String>,
+    pending: HashMap<String, Vec<NodeState>>,
 }
 
 impl PVM {
-    pub fn new(db: SyncSender<DBTr>) -> Self {
+    pub fn new(db: QueueSender<DBTr>) -> Self {
         PVM {
             db: DB::create(db),
             type_cache: HashSet::new(),
@@ -77,14 +139,62 @@ impl PVM {
             id_counter: AtomicUsize::new(1),
             open_cache: HashMap::new(),
             name_cache: LendingLibrary::new(),
+            names: Vec::new(),
+            conn_tracker: ConnTracker::new(),
+            pid_cache: HashMap::new(),
             cur_ctx: CtxStore::Node(ID::new(0)),
-            unparsed_events: HashSet::new(),
+            unparsed_events: HashMap::new(),
+            metrics: Metrics::new(),
+            counters: Counters::new(),
+            events: 0,
+            input: 0,
+            offset: 0,
+            restored: HashSet::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    pub fn flush_metrics(&mut self) {
+        self.metrics.flush(&mut self.counters);
+        for (name, len) in &[
+            ("uuid_cache", self.uuid_cache.len()),
+            ("node_cache", self.node_cache.len()),
+            ("rel_cache", self.rel_cache.len()),
+            ("name_cache", self.name_cache.len()),
+        ] {
+            self.metrics.set("pvm.caches", name, *len as u64);
         }
     }
 
-    pub fn new_ctx(&mut self, ty: &'static ContextType, cont: HashMap<&'static str, String>) {
-        assert!(self.ctx_type_cache.contains(ty));
+    pub fn new_ctx(
+        &mut self,
+        ty: &'static ContextType,
+        cont: HashMap<&'static str, String>,
+    ) -> Result<(), PVMError> {
+        if !self.ctx_type_cache.contains(ty) {
+            return Err(PVMError::UnregisteredType(ty.name));
+        }
+        if let Some(k) = cont.keys().find(|k| !ty.props.contains(k)) {
+            return Err(PVMError::UnknownProperty {
+                ty: ty.name,
+                prop: k.to_string(),
+            });
+        }
+        self.counters.add(
+            "pvm.events",
+            cont.get("event").map(|e| &e[..]).unwrap_or(ty.name),
+            1,
+        );
+        self.events += 1;
+        if self.events % METRICS_FLUSH == 0 {
+            self.flush_metrics();
+        }
         self.cur_ctx = CtxStore::Lazy(ty, cont);
+        Ok(())
     }
 
     pub fn ctx(&mut self) -> ID {
@@ -102,7 +212,34 @@ impl PVM {
         }
     }
 
+    pub fn unparsed_event(&mut self, evt: &str) {
+        *self.unparsed_events.entry(evt.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn known(&self, uuid: &Uuid) -> bool {
+        self.uuid_cache.contains_key(uuid)
+    }
+
+    pub fn lookup(&self, uuid: &Uuid) -> Option<ID> {
+        self.uuid_cache.get(uuid).cloned()
+    }
+
+    pub fn register_pid(&mut self, host: Uuid, pid: i32, uuid: Uuid) {
+        self.pid_cache.insert((host, pid), uuid);
+    }
+
+    pub fn pid_owner(&self, host: Uuid, pid: i32) -> Option<Uuid> {
+        self.pid_cache.get(&(host, pid)).cloned()
+    }
+
+    pub fn release_pid(&mut self, host: Uuid, pid: i32, uuid: &Uuid) {
+        if self.pid_cache.get(&(host, pid)) == Some(uuid) {
+            self.pid_cache.remove(&(host, pid));
+        }
+    }
+
     pub fn release(&mut self, uuid: &Uuid) {
+        self.conn_tracker.release(uuid);
         if let Some(nid) = self.uuid_cache.remove(uuid) {
             self.node_cache.remove(&nid);
         }
@@ -112,12 +249,30 @@ impl PVM {
         ID::new(self.id_counter.fetch_add(1, Ordering::Relaxed) as u64)
     }
 
-    fn _node(&mut self, id: ID) -> Loan<ID, DataNode> {
-        self.node_cache.lend(&id).unwrap()
+    fn _node(&mut self, id: ID) -> Result<Loan<ID, DataNode>, PVMError> {
+        self.node_cache.lend(&id).ok_or(PVMError::UnknownNode(id))
+    }
+
+    fn _actor(&mut self, id: ID) -> Result<Loan<ID, DataNode>, PVMError> {
+        let node = self._node(id)?;
+        PVM::_expect(&node, Actor)?;
+        Ok(node)
     }
 
-    fn _rel(&mut self, id: ID) -> Loan<ID, Rel> {
-        self.rel_cache.lend(&id).unwrap()
+    fn _expect(node: &DataNode, expected: PVMDataType) -> Result<(), PVMError> {
+        if *node.pvm_ty() == expected {
+            Ok(())
+        } else {
+            Err(PVMError::WrongNodeType {
+                id: node.get_db_id(),
+                expected,
+                found: *node.pvm_ty(),
+            })
+        }
+    }
+
+    fn _rel(&mut self, id: ID) -> Result<Loan<ID, Rel>, PVMError> {
+        self.rel_cache.lend(&id).ok_or(PVMError::UnknownRel(id))
     }
 
     fn _decl_rel<T: RelGenerable + Enumerable<Target = Rel>, S: Fn(ID) -> T::Init>(
@@ -126,14 +281,19 @@ impl PVM {
         dst: ID,
         init: S,
     ) -> ID {
-        let triple = (stringify!(T), src, dst);
-        if self.rel_src_dst_cache.contains_key(&triple) {
-            self.rel_src_dst_cache[&triple]
+        let pair = (src, dst);
+        if self.rel_src_dst_cache.contains_key(&pair) {
+            self.rel_src_dst_cache[&pair]
         } else {
             let id = self._nextid();
             let rel = T::new(id, src, dst, init(self.ctx())).enumerate();
+            let kind = match rel {
+                Rel::Inf(ref i) => format!("{:?}", i.pvm_op),
+                Rel::Named(_) => "Named".to_string(),
+            };
+            self.counters.add("pvm.rels", &kind, 1);
             self.db.create_rel(&rel);
-            self.rel_src_dst_cache.insert(triple, id);
+            self.rel_src_dst_cache.insert(pair, id);
             self.rel_cache.insert(id, rel);
             id
         }
@@ -147,8 +307,17 @@ impl PVM {
         })
     }
 
-    fn _named(&mut self, src: impl HasID, dst: &NameNode) -> ID {
-        self._decl_rel::<Named, _>(src.get_db_id(), dst.get_db_id(), |ctx| NamedInit {
+    fn _inf_nbytes(&mut self, id: ID, bytes: i64) -> Result<(), PVMError> {
+        let mut r = self._rel(id)?;
+        if let Rel::Inf(ref mut i) = *r {
+            i.byte_count += bytes;
+        }
+        self.db.update_rel(&*r);
+        Ok(())
+    }
+
+    fn _named(&mut self, src: impl HasID, dst: ID) -> ID {
+        self._decl_rel::<Named, _>(src.get_db_id(), dst, |ctx| NamedInit {
             start: ctx,
             end: ID::new(0),
         })
@@ -156,12 +325,22 @@ impl PVM {
 
     pub fn register_data_type(&mut self, ty: &'static ConcreteType) {
         self.type_cache.insert(ty);
+        for n in self.pending.remove(ty.name).unwrap_or_default() {
+            let node = DataNode::new(n.pvm_ty, ty, n.id, n.uuid, n.ctx, Some(n.meta));
+            self.node_cache.insert(n.id, node);
+        }
+        if self.restored.remove(ty.name) {
+            return;
+        }
         let id = self._nextid();
         self.db.create_node(SchemaNode::from_data(id, ty));
     }
 
     pub fn register_ctx_type(&mut self, ty: &'static ContextType) {
         self.ctx_type_cache.insert(ty);
+        if self.restored.remove(ty.name) {
+            return;
+        }
         let id = self._nextid();
         self.db.create_node(SchemaNode::from_ctx(id, ty));
     }
@@ -172,16 +351,27 @@ impl PVM {
         ty: &'static ConcreteType,
         uuid: Uuid,
         init: Option<MetaStore>,
-    ) -> ID {
-        assert!(self.type_cache.contains(&ty));
+    ) -> Result<ID, PVMError> {
+        if !self.type_cache.contains(&ty) {
+            return Err(PVMError::UnregisteredType(ty.name));
+        }
+        if !pvm_ty.compatible_concrete(ty) {
+            let id = self.uuid_cache.get(&uuid).cloned();
+            return Err(PVMError::WrongNodeType {
+                id: id.unwrap_or_else(|| ID::new(0)),
+                expected: ty.pvm_ty,
+                found: pvm_ty,
+            });
+        }
         let id = self._nextid();
         let node = DataNode::new(pvm_ty, ty, id, uuid, self.ctx(), init);
         if let Some(nid) = self.uuid_cache.insert(uuid, id) {
             self.node_cache.remove(&nid);
         }
         self.db.create_node(&node);
+        self.counters.add("pvm.nodes", &pvm_ty.to_string(), 1);
         self.node_cache.insert(id, node);
-        id
+        Ok(id)
     }
 
     pub fn declare(
@@ -189,44 +379,56 @@ impl PVM {
         ty: &'static ConcreteType,
         uuid: Uuid,
         init: Option<HashMap<&'static str, String>>,
-    ) -> ID {
+    ) -> Result<ID, PVMError> {
         if !self.uuid_cache.contains_key(&uuid) {
             let init = match init {
-                Some(v) => Some(MetaStore::from_map(v, self.ctx(), ty)),
+                Some(v) => {
+                    if let Some(k) = v.keys().find(|k| !ty.props.contains_key(*k)) {
+                        return Err(PVMError::UnknownProperty {
+                            ty: ty.name,
+                            prop: k.to_string(),
+                        });
+                    }
+                    Some(MetaStore::from_map(v, self.ctx(), ty))
+                }
                 None => None,
             };
             self.add(ty.pvm_ty, ty, uuid, init)
         } else {
-            self.uuid_cache[&uuid]
+            Ok(self.uuid_cache[&uuid])
         }
     }
 
-    fn _version(&mut self, src: &DataNode, choice: Either<Uuid, PVMDataType>) -> ID {
+    fn _version(
+        &mut self,
+        src: &DataNode,
+        choice: Either<Uuid, PVMDataType>,
+    ) -> Result<ID, PVMError> {
         let ctx = self.ctx();
         let dst = match choice {
             Either::Left(uuid) => {
-                let dst_id = self.declare(src.ty(), uuid, None);
-                let mut dst = self._node(dst_id);
+                let dst_id = self.declare(src.ty(), uuid, None)?;
+                let mut dst = self._node(dst_id)?;
                 dst.meta.merge(&src.meta.snapshot(ctx));
                 self.db.update_node(&*dst);
                 dst_id
             }
             Either::Right(pvm_ty) => {
-                self.add(pvm_ty, src.ty(), src.uuid(), Some(src.meta.snapshot(ctx)))
+                self.add(pvm_ty, src.ty(), src.uuid(), Some(src.meta.snapshot(ctx)))?
             }
         };
         self._inf(src, dst, PVMOps::Version);
-        dst
+        Ok(dst)
     }
 
-    pub fn derive(&mut self, src: ID, dst: Uuid) -> ID {
-        let src = self._node(src);
+    pub fn derive(&mut self, src: ID, dst: Uuid) -> Result<ID, PVMError> {
+        let src = self._node(src)?;
         self._version(&src, Either::Left(dst))
     }
 
-    pub fn source(&mut self, act: ID, ent: ID) -> ID {
-        assert_eq!(self._node(act).pvm_ty(), &Actor);
-        self._inf(ent, act, PVMOps::Source)
+    pub fn source(&mut self, act: ID, ent: ID) -> Result<ID, PVMError> {
+        self._actor(act)?;
+        Ok(self._inf(ent, act, PVMOps::Source))
     }
 
     pub fn source_nbytes<T: Into<i64>>(
@@ -234,45 +436,41 @@ impl PVM {
         act: ID,
         ent: ID,
         bytes: T,
-    ) -> ID {
-        assert_eq!(self._node(act).pvm_ty(), &Actor);
-        let id = self.source(act, ent);
-        let mut r = self._rel(id);
-        Inf::denumerate_mut(&mut r).byte_count += bytes.into();
-        self.db.update_rel(&*r);
-        id
+    ) -> Result<ID, PVMError> {
+        let id = self.source(act, ent)?;
+        self._inf_nbytes(id, bytes.into())?;
+        Ok(id)
     }
 
-    pub fn sink(&mut self, act: ID, ent: ID) -> ID {
-        let ent = self._node(ent);
-        assert_eq!(self._node(act).pvm_ty(), &Actor);
+    pub fn sink(&mut self, act: ID, ent: ID) -> Result<ID, PVMError> {
+        let ent = self._node(ent)?;
+        self._actor(act)?;
         match ent.pvm_ty() {
             Store => {
-                let f = self._version(&ent, Either::Right(Store));
-                self._inf(act, f, PVMOps::Sink)
+                let f = self._version(&ent, Either::Right(Store))?;
+                Ok(self._inf(act, f, PVMOps::Sink))
             }
-            _ => self._inf(act, &*ent, PVMOps::Sink),
+            _ => Ok(self._inf(act, &*ent, PVMOps::Sink)),
         }
     }
 
-    pub fn sinkstart(&mut self, act: ID, ent: ID) -> ID {
-        let act = self._node(act);
-        let ent = self._node(ent);
-        assert_eq!(act.pvm_ty(), &Actor);
+    pub fn sinkstart(&mut self, act: ID, ent: ID) -> Result<ID, PVMError> {
+        let act = self._actor(act)?;
+        let ent = self._node(ent)?;
         match ent.pvm_ty() {
             Store => {
-                let es = self._version(&ent, Either::Right(EditSession));
+                let es = self._version(&ent, Either::Right(EditSession))?;
                 self.open_cache.insert(ent.uuid(), hashset!(act.uuid()));
-                self._inf(&*act, es, PVMOps::Sink)
+                Ok(self._inf(&*act, es, PVMOps::Sink))
             }
             EditSession => {
                 self.open_cache
                     .get_mut(&ent.uuid())
-                    .unwrap()
+                    .ok_or_else(|| PVMError::SessionNotOpen(ent.uuid()))?
                     .insert(act.uuid());
-                self._inf(&*act, &*ent, PVMOps::Sink)
+                Ok(self._inf(&*act, &*ent, PVMOps::Sink))
             }
-            _ => self._inf(&*act, &*ent, PVMOps::Sink),
+            _ => Ok(self._inf(&*act, &*ent, PVMOps::Sink)),
         }
     }
 
@@ -281,72 +479,389 @@ impl PVM {
         act: ID,
         ent: ID,
         bytes: T,
-    ) -> ID {
-        assert_eq!(self._node(act).pvm_ty(), &Actor);
-        let id = self.sinkstart(act, ent);
-        let mut r = self._rel(id);
-        Inf::denumerate_mut(&mut r).byte_count += bytes.into();
-        self.db.update_rel(&*r);
-        id
+    ) -> Result<ID, PVMError> {
+        let id = self.sinkstart(act, ent)?;
+        self._inf_nbytes(id, bytes.into())?;
+        Ok(id)
     }
 
-    pub fn sinkend(&mut self, act: ID, ent: ID) {
-        let ent = self._node(ent);
-        let act = self._node(act);
-        assert_eq!(act.pvm_ty(), &Actor);
+    pub fn sinkend(&mut self, act: ID, ent: ID) -> Result<(), PVMError> {
+        let ent = self._node(ent)?;
+        let act = self._actor(act)?;
         if let EditSession = ent.pvm_ty() {
-            self.open_cache
-                .get_mut(&ent.uuid())
-                .unwrap()
-                .remove(&act.uuid());
-            if self.open_cache[&ent.uuid()].is_empty() {
-                self._version(&ent, Either::Right(Store));
+            let empty = {
+                let sess = self
+                    .open_cache
+                    .get_mut(&ent.uuid())
+                    .ok_or_else(|| PVMError::SessionNotOpen(ent.uuid()))?;
+                sess.remove(&act.uuid());
+                sess.is_empty()
+            };
+            if empty {
+                self._version(&ent, Either::Right(Store))?;
             }
         }
+        Ok(())
     }
 
-    fn decl_name(&mut self, name: Name) -> Loan<Name, NameNode> {
-        if !self.name_cache.contains_key(&name) {
-            let n = NameNode::generate(self._nextid(), name.clone());
-            self.db.create_node(&n);
-            self.name_cache.insert(name.clone(), n);
+    fn decl_name(&mut self, name: Name) -> ID {
+        if let Some(n) = self.name_cache.lend(&name) {
+            return n.get_db_id();
         }
-        self.name_cache.lend(&name).unwrap()
+        let n = NameNode::generate(self._nextid(), name.clone());
+        let id = n.get_db_id();
+        self.db.create_node(&n);
+        self.name_cache.insert(name.clone(), n);
+        self.names.push(name);
+        id
     }
 
-    pub fn name(&mut self, obj: ID, name: Name) -> ID {
-        let n_node = self.decl_name(name);
-        self._named(obj, &n_node)
+    pub fn name(&mut self, obj: ID, name: Name) -> Result<ID, PVMError> {
+        let n_id = self.decl_name(name);
+        Ok(self._named(obj, n_id))
     }
 
-    pub fn unname(&mut self, obj: ID, name: Name) -> ID {
-        let id = self.name(obj, name);
-        let mut rel = self._rel(id);
+    pub fn unname(&mut self, obj: ID, name: Name) -> Result<ID, PVMError> {
+        let id = self.name(obj, name)?;
+        let mut rel = self._rel(id)?;
         if let Rel::Named(ref mut n_rel) = *rel {
             n_rel.end = self.ctx();
             self.db.update_rel(&*rel);
         }
-        id
+        Ok(id)
     }
 
-    pub fn meta<T: ToString + ?Sized>(&mut self, ent: ID, key: &'static str, val: &T) {
-        let mut ent = self._node(ent);
-        if !ent.ty().props.contains_key(key) {
-            panic!("Setting unknown property on concrete type: {:?} does not have a property named {}.", ent.ty(), key);
-        }
-        let heritable = ent.ty().props[key];
+    pub fn meta<T: ToString + ?Sized>(
+        &mut self,
+        ent: ID,
+        key: &'static str,
+        val: &T,
+    ) -> Result<(), PVMError> {
+        let mut ent = self._node(ent)?;
+        let heritable = match ent.ty().props.get(key) {
+            Some(h) => *h,
+            None => {
+                return Err(PVMError::UnknownProperty {
+                    ty: ent.ty().name,
+                    prop: key.to_string(),
+                })
+            }
+        };
         ent.meta.update(key, val, self.ctx(), heritable);
         self.db.update_node(&*ent);
+        Ok(())
     }
 
-    pub fn connect(&mut self, first: ID, second: ID, dir: ConnectDir) {
-        assert_eq!(self._node(first).pvm_ty(), &Conduit);
-        assert_eq!(self._node(second).pvm_ty(), &Conduit);
+    pub fn connect(&mut self, first: ID, second: ID, dir: ConnectDir) -> Result<(), PVMError> {
+        PVM::_expect(&*self._node(first)?, Conduit)?;
+        PVM::_expect(&*self._node(second)?, Conduit)?;
         self._inf(first, second, PVMOps::Connect);
         if let ConnectDir::BiDirectional = dir {
             self._inf(second, first, PVMOps::Connect);
         }
+        Ok(())
     }
 
-    pub fn shutdown(self) {}
+    fn _conduit_uuid(&mut self, id: ID) -> Result<Uuid, PVMError> {
+        let node = self._node(id)?;
+        PVM::_expect(&node, Conduit)?;
+        Ok(node.uuid())
+    }
+
+    fn _connect_peer(&mut self, sock: ID, peer: Option<Uuid>) -> Result<(), PVMError> {
+        let peer = peer.and_then(|p| self.uuid_cache.get(&p).cloned());
+        if let Some(peer) = peer {
+            if peer != sock {
+                self.connect(sock, peer, ConnectDir::BiDirectional)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn endpoints(
+        &mut self,
+        sock: ID,
+        host: Uuid,
+        local: Endpoint,
+        foreign: Endpoint,
+    ) -> Result<(), PVMError> {
+        let uuid = self._conduit_uuid(sock)?;
+        self.name(sock, Name::Net(local.0.clone(), local.1))?;
+        self.name(sock, Name::Net(foreign.0.clone(), foreign.1))?;
+        let peer = self.conn_tracker.flow(uuid, host, local, foreign);
+        self._connect_peer(sock, peer)
+    }
+
+    pub fn bind_endpoint(&mut self, sock: ID, host: Uuid, local: Endpoint) -> Result<(), PVMError> {
+        let uuid = self._conduit_uuid(sock)?;
+        self.conn_tracker.bind(uuid, host, local);
+        Ok(())
+    }
+
+    pub fn connect_endpoint(
+        &mut self,
+        sock: ID,
+        host: Uuid,
+        remote: Endpoint,
+    ) -> Result<(), PVMError> {
+        let uuid = self._conduit_uuid(sock)?;
+        self.conn_tracker.connect(uuid, host, remote);
+        Ok(())
+    }
+
+    pub fn accept_endpoint(&mut self, listener: ID, sock: ID, host: Uuid) -> Result<(), PVMError> {
+        let luuid = self._conduit_uuid(listener)?;
+        let peer = self.conn_tracker.accept(luuid, host);
+        self._connect_peer(sock, peer)
+    }
+
+    pub fn input(&self) -> usize {
+        self.input
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, input: usize, offset: usize) {
+        self.input = input;
+        self.offset = offset;
+    }
+
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        let mut cp = Checkpoint::new(self.offset, self.id_counter.load(Ordering::Relaxed) as u64);
+        cp.input = self.input;
+        cp.types = self.type_cache.iter().map(|t| t.name.to_string()).collect();
+        cp.ctx_types = self
+            .ctx_type_cache
+            .iter()
+            .map(|t| t.name.to_string())
+            .collect();
+        cp.types.extend(self.restored.iter().cloned());
+        if let CtxStore::Node(id) = self.cur_ctx {
+            cp.ctx = id;
+        }
+        cp.uuids = self.uuid_cache.iter().map(|(u, i)| (*u, *i)).collect();
+        for (_, id) in &cp.uuids {
+            if let Some(n) = self.node_cache.lend(id) {
+                cp.nodes.push(NodeState {
+                    pvm_ty: *n.pvm_ty(),
+                    ty: n.ty().name.to_string(),
+                    id: *id,
+                    uuid: n.uuid(),
+                    ctx: n.ctx(),
+                    meta: n.meta.clone(),
+                });
+            }
+        }
+        for nodes in self.pending.values() {
+            cp.nodes.extend(nodes.iter().cloned());
+        }
+        cp.rel_src_dst = self
+            .rel_src_dst_cache
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        for (_, id) in &cp.rel_src_dst {
+            if let Some(r) = self.rel_cache.lend(id) {
+                cp.rels.push(r.clone());
+            }
+        }
+        cp.open = self
+            .open_cache
+            .iter()
+            .map(|(k, v)| (*k, v.iter().cloned().collect()))
+            .collect();
+        for name in &self.names {
+            if let Some(n) = self.name_cache.lend(name) {
+                cp.names.push(n.clone());
+            }
+        }
+        cp.pids = self.pid_cache.iter().map(|(k, v)| (*k, *v)).collect();
+        cp.conns = self.conn_tracker.save();
+        cp
+    }
+
+    pub fn restore(&mut self, cp: Checkpoint) -> Result<usize, &'static str> {
+        if self.id_counter.load(Ordering::Relaxed) != 1 {
+            return Err("Checkpoints can only be restored into a fresh pipeline");
+        }
+        self.id_counter
+            .store(cp.next_id as usize, Ordering::Relaxed);
+        self.restored = cp.types.into_iter().chain(cp.ctx_types).collect();
+        if cp.ctx.inner() != 0 {
+            self.cur_ctx = CtxStore::Node(cp.ctx);
+        }
+        self.uuid_cache = cp.uuids.into_iter().collect();
+        for n in cp.nodes {
+            self.pending
+                .entry(n.ty.clone())
+                .or_insert_with(Vec::new)
+                .push(n);
+        }
+        for r in cp.rels {
+            self.rel_cache.insert(r.get_db_id(), r);
+        }
+        self.rel_src_dst_cache = cp.rel_src_dst.into_iter().collect();
+        self.open_cache = cp
+            .open
+            .into_iter()
+            .map(|(k, v)| (k, v.into_iter().collect()))
+            .collect();
+        for n in cp.names {
+            let name = match n {
+                NameNode::Path(_, ref p) => Name::Path(p.clone()),
+                NameNode::Net(_, ref a, p) => Name::Net(a.clone(), p),
+            };
+            self.names.push(name.clone());
+            self.name_cache.insert(name, n);
+        }
+        self.pid_cache = cp.pids.into_iter().collect();
+        self.conn_tracker = ConnTracker::restore(cp.conns);
+        self.input = cp.input;
+        self.offset = cp.offset;
+        Ok(cp.offset)
+    }
+
+    pub fn sync_views(&mut self, done: mpsc::SyncSender<usize>) {
+        self.db.sync(done);
+    }
+
+    pub fn shutdown(mut self) {
+        self.flush_metrics();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use data::node_types::ConcreteType;
+    use views::{queue, QueueReceiver};
+
+    lazy_static! {
+        static ref PROC: ConcreteType = ConcreteType {
+            pvm_ty: Actor,
+            name: "process",
+            props: hashmap!("pid" => false),
+        };
+        static ref FILE: ConcreteType = ConcreteType {
+            pvm_ty: Store,
+            name: "file",
+            props: hashmap!("mode" => true),
+        };
+        static ref SOCK: ConcreteType = ConcreteType {
+            pvm_ty: Conduit,
+            name: "socket",
+            props: hashmap!(),
+        };
+        static ref CTX: ContextType = ContextType {
+            name: "ctx",
+            props: vec!["time"],
+        };
+    }
+
+    fn uuid(n: u8) -> Uuid {
+        Uuid::from_bytes(&[n; 16]).unwrap()
+    }
+
+    fn pvm() -> (PVM, QueueReceiver<DBTr>) {
+        let (send, recv) = queue(0x1000);
+        let mut pvm = PVM::new(send);
+        pvm.register_data_type(&PROC);
+        pvm.register_data_type(&FILE);
+        pvm.register_data_type(&SOCK);
+        pvm.register_ctx_type(&CTX);
+        (pvm, recv)
+    }
+
+    #[test]
+    fn wrong_node_type() {
+        let (mut pvm, _recv) = pvm();
+        let file = pvm.declare(&FILE, uuid(1), None).unwrap();
+        let sock = pvm.declare(&SOCK, uuid(2), None).unwrap();
+        match pvm.source(file, sock) {
+            Err(PVMError::WrongNodeType {
+                id,
+                expected: Actor,
+                found: Store,
+            }) => assert_eq!(id, file),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(pvm.connect(file, sock, ConnectDir::Mono).is_err());
+        match pvm.add(Conduit, &FILE, uuid(3), None) {
+            Err(PVMError::WrongNodeType { .. }) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+        let next = pvm.declare(&FILE, uuid(3), None).unwrap();
+        assert_eq!(next.inner(), sock.inner() + 1);
+    }
+
+    #[test]
+    fn unknown_property() {
+        let (mut pvm, _recv) = pvm();
+        let file = pvm.declare(&FILE, uuid(1), None).unwrap();
+        match pvm.meta(file, "colour", "red") {
+            Err(PVMError::UnknownProperty { ty: "file", ref prop }) if prop == "colour" => {}
+            r => panic!("unexpected {:?}", r),
+        }
+        let init = hashmap!("colour" => "red".to_string());
+        match pvm.declare(&FILE, uuid(2), Some(init)) {
+            Err(PVMError::UnknownProperty { .. }) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(!pvm.known(&uuid(2)));
+        match pvm.new_ctx(&CTX, hashmap!("event" => "x".to_string())) {
+            Err(PVMError::UnknownProperty { ty: "ctx", .. }) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn unknown_node_and_rel() {
+        let (mut pvm, _recv) = pvm();
+        let proc_ = pvm.declare(&PROC, uuid(1), None).unwrap();
+        match pvm.sink(proc_, ID::new(999)) {
+            Err(PVMError::UnknownNode(id)) => assert_eq!(id, ID::new(999)),
+            r => panic!("unexpected {:?}", r),
+        }
+        pvm.release(&uuid(1));
+        match pvm.meta(proc_, "pid", &1) {
+            Err(PVMError::UnknownNode(id)) => assert_eq!(id, proc_),
+            r => panic!("unexpected {:?}", r),
+        }
+        match pvm._inf_nbytes(ID::new(999), 1) {
+            Err(PVMError::UnknownRel(id)) => assert_eq!(id, ID::new(999)),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn unregistered_type() {
+        let (send, _recv) = queue(0x100);
+        let mut pvm = PVM::new(send);
+        match pvm.declare(&FILE, uuid(1), None) {
+            Err(PVMError::UnregisteredType("file")) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+        match pvm.new_ctx(&CTX, HashMap::new()) {
+            Err(PVMError::UnregisteredType("ctx")) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn session_not_open() {
+        let (mut pvm, _recv) = pvm();
+        let proc_ = pvm.declare(&PROC, uuid(1), None).unwrap();
+        let es = pvm.add(EditSession, &FILE, uuid(2), None).unwrap();
+        match pvm.sinkend(proc_, es) {
+            Err(PVMError::SessionNotOpen(u)) => assert_eq!(u, uuid(2)),
+            r => panic!("unexpected {:?}", r),
+        }
+        match pvm.sinkstart(proc_, es) {
+            Err(PVMError::SessionNotOpen(u)) => assert_eq!(u, uuid(2)),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
 }
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Result as FMTResult},
+    sync::Arc,
+};
+
+use super::{job::Progress, pvm::PVMError};
+
+const MAX_SAMPLES: usize = 10;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IngestErrorKind {
+    Io,
+    Json,
+    Syntax,
+    MissingField,
+    Pvm,
+}
+
+impl Display for IngestErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> FMTResult {
+        write!(
+            f,
+            "{}",
+            match self {
+                IngestErrorKind::Io => "File Reading",
+                IngestErrorKind::Json => "JSON Parsing",
+                IngestErrorKind::Syntax => "Record Parsing",
+                IngestErrorKind::MissingField => "Missing Field",
+                IngestErrorKind::Pvm => "PVM Parsing",
+            }
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct IngestError {
+    pub kind: IngestErrorKind,
+    pub line: usize,
+    pub msg: String,
+    pub record: Option<String>,
+}
+
+impl IngestError {
+    pub fn from_pvm(line: usize, err: &PVMError, record: String) -> Self {
+        let kind = match err {
+            PVMError::MissingField { .. } => IngestErrorKind::MissingField,
+            _ => IngestErrorKind::Pvm,
+        };
+        IngestError {
+            kind,
+            line,
+            msg: err.to_string(),
+            record: Some(record),
+        }
+    }
+}
+
+impl Display for IngestError {
+    fn fmt(&self, f: &mut Formatter) -> FMTResult {
+        writeln!(f, "Line: {}", self.line)?;
+        write!(f, "{} error: {}", self.kind, self.msg)?;
+        if let Some(ref rec) = self.record {
+            write!(f, "\n{}", rec)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorPolicy {
+    Skip,
+    AbortAfter(usize),
+    AbortOnFirst,
+}
+
+impl ErrorPolicy {
+    fn should_abort(self, errors: usize) -> bool {
+        match self {
+            ErrorPolicy::Skip => false,
+            ErrorPolicy::AbortAfter(n) => errors >= n,
+            ErrorPolicy::AbortOnFirst => errors >= 1,
+        }
+    }
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        ErrorPolicy::Skip
+    }
+}
+
+pub trait ErrorSink: Send {
+    fn report(&mut self, err: &IngestError);
+
+    fn progress(&self) -> Option<Arc<Progress>> {
+        None
+    }
+}
+
+impl<F: FnMut(&IngestError) + Send> ErrorSink for F {
+    fn report(&mut self, err: &IngestError) {
+        self(err)
+    }
+}
+
+pub struct StderrSink;
+
+impl ErrorSink for StderrSink {
+    fn report(&mut self, err: &IngestError) {
+        eprintln!("{}", err);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ErrorClass {
+    pub count: usize,
+    pub samples: Vec<String>,
+}
+
+impl ErrorClass {
+    fn add(&mut self, err: &IngestError) {
+        self.count += 1;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(format!("line {}: {}", err.line, err.msg));
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IngestReport {
+    pub lines: usize,
+    pub records: usize,
+    // Input records consumed, in the format's framing, which is where a
+    // resumed ingest of the same input starts.
+    pub offset: usize,
+    pub io: ErrorClass,
+    pub json: ErrorClass,
+    pub syntax: ErrorClass,
+    pub missing_field: ErrorClass,
+    pub pvm: ErrorClass,
+    pub unhandled: HashMap<String, usize>,
+    pub aborted: bool,
+}
+
+impl IngestReport {
+    pub fn errors(&self) -> usize {
+        self.io.count
+            + self.json.count
+            + self.syntax.count
+            + self.missing_field.count
+            + self.pvm.count
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.errors() == 0 && !self.aborted
+    }
+
+    fn class_mut(&mut self, kind: IngestErrorKind) -> &mut ErrorClass {
+        match kind {
+            IngestErrorKind::Io => &mut self.io,
+            IngestErrorKind::Json => &mut self.json,
+            IngestErrorKind::Syntax => &mut self.syntax,
+            IngestErrorKind::MissingField => &mut self.missing_field,
+            IngestErrorKind::Pvm => &mut self.pvm,
+        }
+    }
+}
+
+impl Display for IngestReport {
+    fn fmt(&self, f: &mut Formatter) -> FMTResult {
+        writeln!(f, "Lines Read: {}", self.lines)?;
+        writeln!(f, "Records Parsed: {}", self.records)?;
+        for (name, class) in &[
+            ("I/O", &self.io),
+            ("JSON", &self.json),
+            ("Record", &self.syntax),
+            ("Missing Field", &self.missing_field),
+            ("PVM", &self.pvm),
+        ] {
+            writeln!(f, "{} Errors: {}", name, class.count)?;
+            for s in &class.samples {
+                writeln!(f, "    {}", s)?;
+            }
+        }
+        writeln!(f, "Missing Events:")?;
+        for (evt, n) in &self.unhandled {
+            writeln!(f, "    {} ({})", evt, n)?;
+        }
+        if self.aborted {
+            writeln!(f, "Ingest aborted due to errors")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ErrorTracker<'a> {
+    policy: ErrorPolicy,
+    sink: &'a mut ErrorSink,
+    progress: Option<Arc<Progress>>,
+    pub report: IngestReport,
+}
+
+impl<'a> ErrorTracker<'a> {
+    pub fn new(policy: ErrorPolicy, sink: &'a mut ErrorSink) -> Self {
+        ErrorTracker {
+            policy,
+            progress: sink.progress(),
+            sink,
+            report: IngestReport::default(),
+        }
+    }
+
+    pub fn line(&mut self, n: usize) {
+        self.report.lines = n;
+        self.report.offset = n;
+        if let Some(ref p) = self.progress {
+            p.set_lines(n);
+        }
+    }
+
+    pub fn record(&mut self) {
+        self.report.records += 1;
+        if let Some(ref p) = self.progress {
+            p.add_record();
+        }
+    }
+
+    // Formats check this between records, so a cancelled job stops on a
+    // record boundary that a checkpoint can resume from.
+    pub fn cancelled(&self) -> bool {
+        self.progress.as_ref().map_or(false, |p| p.is_cancelled())
+    }
+
+    pub fn error(&mut self, err: IngestError) -> bool {
+        self.sink.report(&err);
+        self.report.class_mut(err.kind).add(&err);
+        if self.policy.should_abort(self.report.errors()) {
+            self.report.aborted = true;
+        }
+        if let Some(ref p) = self.progress {
+            p.set_errors(self.report.errors());
+        }
+        self.report.aborted
+    }
+}
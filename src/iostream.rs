@@ -1,30 +1,63 @@
-use nix::{
-    self,
-    sys::{
-        socket::{getsockname, getsockopt, sockopt, SockAddr, SockType},
-        stat::fstat,
-    },
-};
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use libc;
+use nix::{self, sys::stat::fstat};
 use std::{
     error::Error,
     fs,
-    io::{self, Read},
-    net,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    mem,
+    net::{self, ToSocketAddrs},
     os::unix::{
         self,
         io::{FromRawFd, RawFd},
     },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
 };
 
-pub struct UdpSocketR(pub net::UdpSocket);
+use ingest::RS;
+
 pub struct UnixPipe(fs::File);
 
+// Hands out one datagram at a time, starting each with a record separator
+// and ending it with a newline so a record never runs on into the next
+// datagram.
+pub struct Datagrams<S> {
+    sock: S,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+pub trait Datagram {
+    fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+pub enum ListenAddr {
+    Tcp(net::SocketAddr),
+    Udp(net::SocketAddr),
+    Unix(PathBuf),
+    UnixDatagram(PathBuf),
+}
+
+// Whole lines from every connection are funnelled through one channel, so
+// concurrent producers can only interleave between records.
+struct LineChannel {
+    rx: mpsc::Receiver<Vec<u8>>,
+    cur: Cursor<Vec<u8>>,
+}
+
 pub enum IOType {
     File,
     Pipe,
+    Terminal,
     TcpStream,
     UdpSocket,
     UnixStream,
+    UnixDatagram,
     Unknown(String),
 }
 
@@ -36,13 +69,211 @@ pub enum FdClass {
     Other,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Zstd,
+    Xz,
+}
+
+const MAGIC_LEN: usize = 6;
+const MAGIC_BUF: usize = 0x10000;
+const DATAGRAM_MAX: usize = 0x10000;
+const LINE_QUEUE: usize = 1024;
+const LISTEN_POLL_MS: u64 = 200;
+
+impl Compression {
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    fn decoder(self, src: Box<Read + Send>) -> io::Result<Box<Read + Send>> {
+        Ok(match self {
+            Compression::None => src,
+            Compression::Gzip => Box::new(MultiGzDecoder::new(src)),
+            Compression::Bzip2 => Box::new(BzDecoder::new(src)),
+            Compression::Zstd => Box::new(::zstd::stream::read::Decoder::new(src)?),
+            Compression::Xz => Box::new(::xz2::read::XzDecoder::new_multi_decoder(src)),
+        })
+    }
+}
+
+// Holds the raw source until the first read, which sniffs the magic bytes
+// and swaps in a decoder. Deferring this keeps constructors from blocking
+// on sockets and terminals.
+enum Source {
+    Raw(Box<Read + Send>),
+    Ready(Box<Read + Send>),
+    Failed,
+}
+
+impl Source {
+    fn start(src: &mut Box<Read + Send>) -> io::Result<Box<Read + Send>> {
+        // A single large read keeps a whole datagram together.
+        let mut magic = vec![0; MAGIC_BUF];
+        let mut len = 0;
+        while len < MAGIC_LEN {
+            match src.read(&mut magic[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        magic.truncate(len);
+        let comp = Compression::detect(&magic);
+        let src = mem::replace(src, Box::new(io::empty()));
+        comp.decoder(Box::new(Cursor::new(magic).chain(src)))
+    }
+}
+
 pub struct IOStream {
-    src: Box<Read>,
+    src: Source,
+}
+
+impl Datagram for net::UdpSocket {
+    fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf)
+    }
+}
+
+impl Datagram for unix::net::UnixDatagram {
+    fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf)
+    }
+}
+
+impl<S: Datagram> Datagrams<S> {
+    pub fn new(sock: S) -> Self {
+        Datagrams {
+            sock,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+// Timeouts only happen on sockets we bound in listen mode; they surface as
+// Interrupted so line readers retry while a cancelled job can still stop.
+fn poll_timeout(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::ErrorKind::Interrupted.into(),
+        _ => e,
+    }
+}
+
+impl<S: Datagram> Read for Datagrams<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            self.buf.resize(DATAGRAM_MAX + 1, 0);
+            let res = self.sock.recv_datagram(&mut self.buf[1..]);
+            let n = *res.as_ref().unwrap_or(&0);
+            self.pos = 0;
+            if n == 0 {
+                self.buf.clear();
+            } else {
+                self.buf[0] = RS;
+                self.buf.truncate(n + 1);
+                if self.buf[n] != b'\n' {
+                    self.buf.push(b'\n');
+                }
+            }
+            res.map_err(poll_timeout)?;
+        }
+        let n = (&self.buf[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl LineChannel {
+    fn new() -> (LineChannel, mpsc::SyncSender<Vec<u8>>) {
+        let (tx, rx) = mpsc::sync_channel(LINE_QUEUE);
+        let chan = LineChannel {
+            rx,
+            cur: Cursor::new(Vec::new()),
+        };
+        (chan, tx)
+    }
 }
 
-impl Read for UdpSocketR {
+impl Read for LineChannel {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.recv(buf)
+        loop {
+            let n = self.cur.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.rx.recv_timeout(Duration::from_millis(LISTEN_POLL_MS)) {
+                Ok(line) => self.cur = Cursor::new(line),
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::Interrupted.into()),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+    }
+}
+
+fn forward_lines<R: Read>(conn: R, tx: &mpsc::SyncSender<Vec<u8>>) {
+    let mut conn = BufReader::new(conn);
+    loop {
+        let mut line = Vec::new();
+        match conn.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {
+                if line.last() != Some(&b'\n') {
+                    line.push(b'\n');
+                }
+                if tx.send(line).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn accept_lines<C, I>(conns: I, tx: mpsc::SyncSender<Vec<u8>>)
+where
+    C: Read + Send + 'static,
+    I: Iterator<Item = io::Result<C>>,
+{
+    for conn in conns.flatten() {
+        let tx = tx.clone();
+        thread::spawn(move || forward_lines(conn, &tx));
+    }
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ListenAddr, String> {
+        let inet = |addr: &str| {
+            addr.to_socket_addrs()
+                .map_err(|e| format!("Invalid address {}: {}", addr, e))?
+                .next()
+                .ok_or_else(|| format!("Address {} did not resolve", addr))
+        };
+        match s.find("://").map(|i| (&s[..i], &s[i + 3..])) {
+            Some(("tcp", addr)) => Ok(ListenAddr::Tcp(inet(addr)?)),
+            Some(("udp", addr)) => Ok(ListenAddr::Udp(inet(addr)?)),
+            Some(("unix", path)) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            Some(("unixgram", path)) => Ok(ListenAddr::UnixDatagram(PathBuf::from(path))),
+            _ => Err(format!(
+                "Invalid listen address {}, expected tcp://, udp://, unix:// or unixgram://",
+                s
+            )),
+        }
     }
 }
 
@@ -65,25 +296,98 @@ impl FromRawFd for IOStream {
             Err(e) => IOType::Unknown(e),
         };
         let fd_obj = match iotype {
-            IOType::File => Box::new(fs::File::from_raw_fd(fd)) as Box<Read>,
-            IOType::Pipe => Box::new(UnixPipe::from_raw_fd(fd)) as Box<Read>,
-            IOType::TcpStream => Box::new(net::TcpStream::from_raw_fd(fd)) as Box<Read>,
-            IOType::UdpSocket => Box::new(UdpSocketR(net::UdpSocket::from_raw_fd(fd))) as Box<Read>,
-            IOType::UnixStream => Box::new(unix::net::UnixStream::from_raw_fd(fd)) as Box<Read>,
-            IOType::Unknown(e) => {
-                panic!(
-                    "Unsupported input stream. You have passed a fd type that is not supported by libopus: {}",
-                    e
-                )
+            IOType::File | IOType::Terminal => Box::new(fs::File::from_raw_fd(fd)) as Box<Read + Send>,
+            IOType::Pipe => Box::new(UnixPipe::from_raw_fd(fd)) as Box<Read + Send>,
+            IOType::TcpStream => Box::new(net::TcpStream::from_raw_fd(fd)) as Box<Read + Send>,
+            IOType::UdpSocket => Box::new(Datagrams::new(net::UdpSocket::from_raw_fd(fd))) as Box<Read + Send>,
+            IOType::UnixStream => Box::new(unix::net::UnixStream::from_raw_fd(fd)) as Box<Read + Send>,
+            IOType::UnixDatagram => {
+                Box::new(Datagrams::new(unix::net::UnixDatagram::from_raw_fd(fd))) as Box<Read + Send>
+            }
+            // Anything else that read(2) works on is read as a plain file,
+            // failures then surface as IO errors during the ingest.
+            IOType::Unknown(_) => Box::new(fs::File::from_raw_fd(fd)) as Box<Read + Send>,
+        };
+        IOStream::from_boxed(fd_obj)
+    }
+}
+
+impl IOStream {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<IOStream> {
+        Ok(IOStream::from_read(fs::File::open(path)?))
+    }
+
+    pub fn stdin() -> IOStream {
+        unsafe { IOStream::from_raw_fd(0) }
+    }
+
+    pub fn from_read<R: Read + Send + 'static>(src: R) -> IOStream {
+        IOStream::from_boxed(Box::new(src))
+    }
+
+    pub fn from_read_raw<R: Read + Send + 'static>(src: R) -> IOStream {
+        IOStream {
+            src: Source::Ready(Box::new(src)),
+        }
+    }
+
+    // Binds the address and merges every producer that sends to it. The
+    // stream never reaches EOF, the ingest runs until its job is cancelled.
+    // Input is not decompressed since producers share one stream.
+    pub fn listen(addr: &ListenAddr) -> io::Result<IOStream> {
+        let src: Box<Read + Send> = match *addr {
+            ListenAddr::Udp(ref addr) => {
+                let sock = net::UdpSocket::bind(addr)?;
+                sock.set_read_timeout(Some(Duration::from_millis(LISTEN_POLL_MS)))?;
+                Box::new(Datagrams::new(sock))
+            }
+            ListenAddr::UnixDatagram(ref path) => {
+                let sock = unix::net::UnixDatagram::bind(path)?;
+                sock.set_read_timeout(Some(Duration::from_millis(LISTEN_POLL_MS)))?;
+                Box::new(Datagrams::new(sock))
+            }
+            ListenAddr::Tcp(ref addr) => {
+                let listener = net::TcpListener::bind(addr)?;
+                let (chan, tx) = LineChannel::new();
+                thread::spawn(move || accept_lines(listener.incoming(), tx));
+                Box::new(chan)
+            }
+            ListenAddr::Unix(ref path) => {
+                let listener = unix::net::UnixListener::bind(path)?;
+                let (chan, tx) = LineChannel::new();
+                thread::spawn(move || accept_lines(listener.incoming(), tx));
+                Box::new(chan)
             }
         };
-        IOStream { src: fd_obj }
+        Ok(IOStream {
+            src: Source::Ready(src),
+        })
+    }
+
+    fn from_boxed(src: Box<Read + Send>) -> IOStream {
+        IOStream {
+            src: Source::Raw(src),
+        }
     }
 }
 
 impl Read for IOStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        (*self.src).read(buf)
+        let ready = match self.src {
+            Source::Ready(ref mut src) => return src.read(buf),
+            Source::Raw(ref mut src) => Source::start(src),
+            Source::Failed => return Ok(0),
+        };
+        match ready {
+            Ok(src) => {
+                self.src = Source::Ready(src);
+                self.read(buf)
+            }
+            Err(e) => {
+                self.src = Source::Failed;
+                Err(e)
+            }
+        }
     }
 }
 
@@ -100,13 +404,46 @@ fn err_str(err: nix::Error) -> String {
     err.description().to_owned()
 }
 
+// nix's getsockname and SockType getsockopt fail on unnamed sockets such as
+// those from socketpair, so ask libc for the family and type directly.
+fn sock_family(fd: RawFd) -> Result<libc::c_int, String> {
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockname(
+            fd,
+            &mut addr as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    if ret == 0 {
+        Ok(libc::c_int::from(addr.ss_family))
+    } else {
+        Err(io::Error::last_os_error().to_string())
+    }
+}
+
+fn sock_type(fd: RawFd) -> Result<libc::c_int, String> {
+    let mut ty: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut ty as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret == 0 {
+        Ok(ty)
+    } else {
+        Err(io::Error::last_os_error().to_string())
+    }
+}
+
 fn get_fd_type(fd: RawFd) -> Result<IOType, String> {
     let fs = fstat(fd).map_err(err_str)?;
-    println!(
-        "Mode: {}, masked: {}",
-        fs.st_mode,
-        u32::from(fs.st_mode) & S_IFMT
-    );
     let class = match u32::from(fs.st_mode) & S_IFMT {
         S_IFREG => FdClass::File,
         S_IFSOCK => FdClass::Socket,
@@ -117,22 +454,126 @@ fn get_fd_type(fd: RawFd) -> Result<IOType, String> {
     match class {
         FdClass::File => Ok(IOType::File),
         FdClass::Socket => {
-            let saddr = getsockname(fd).map_err(err_str)?;
-            match saddr {
-                SockAddr::Inet(_) => {
-                    let stype = getsockopt(fd, sockopt::SockType).map_err(err_str)?;
-                    match stype {
-                        SockType::Stream => Ok(IOType::TcpStream),
-                        SockType::Datagram => Ok(IOType::UdpSocket),
-                        _ => Err(String::from("unsupported socket type")),
-                    }
-                }
-                SockAddr::Unix(_) => Ok(IOType::UnixStream),
-                _ => Err(String::from("unsupported socket family")),
+            let inet = match sock_family(fd)? {
+                libc::AF_INET | libc::AF_INET6 => true,
+                _ => false,
+            };
+            match (inet, sock_type(fd)?) {
+                (true, libc::SOCK_STREAM) => Ok(IOType::TcpStream),
+                (true, libc::SOCK_DGRAM) => Ok(IOType::UdpSocket),
+                (false, libc::SOCK_DGRAM) => Ok(IOType::UnixDatagram),
+                (false, _) => Ok(IOType::UnixStream),
+                _ => Err(String::from("unsupported socket type")),
             }
         }
         FdClass::Fifo => Ok(IOType::Pipe),
-        FdClass::Terminal => Err(String::from("stdin input not supported")),
+        FdClass::Terminal => Ok(IOType::Terminal),
         _ => Err(String::from("unknown fd type")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{io::Write, os::unix::io::IntoRawFd};
+
+    use ingest::JsonRecords;
+
+    #[test]
+    fn read_any_fd() {
+        let (mut a, b) = unix::net::UnixStream::pair().unwrap();
+        a.write_all(b"stream\n").unwrap();
+        drop(a);
+        let mut s = String::new();
+        unsafe { IOStream::from_raw_fd(b.into_raw_fd()) }
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "stream\n");
+
+        let (a, b) = unix::net::UnixDatagram::pair().unwrap();
+        a.send(b"datagram").unwrap();
+        let mut buf = [0; 16];
+        let n = unsafe { IOStream::from_raw_fd(b.into_raw_fd()) }
+            .read(&mut buf)
+            .unwrap();
+        assert_eq!(&buf[..n], b"\x1edatagram\n");
+
+        s.clear();
+        IOStream::from_read(io::Cursor::new("cursor"))
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "cursor");
+    }
+
+    #[test]
+    fn datagram_framing() {
+        let (a, b) = unix::net::UnixDatagram::pair().unwrap();
+        let big = format!("\"{}\"", "x".repeat(20_000));
+        a.send(b"{\"a\": 1}\n{\"b\": [2,").unwrap();
+        a.send(b"  {\"c\": 3}").unwrap();
+        a.send(big.as_bytes()).unwrap();
+        let stream = unsafe { IOStream::from_raw_fd(b.into_raw_fd()) };
+        let recs: Vec<_> = JsonRecords::new(BufReader::new(stream))
+            .take(4)
+            .map(|r| {
+                let r = r.unwrap();
+                (String::from_utf8(r.data).unwrap(), r.complete)
+            })
+            .collect();
+        assert_eq!(
+            recs,
+            vec![
+                ("{\"a\": 1}".to_string(), true),
+                ("{\"b\": [2,".to_string(), false),
+                ("{\"c\": 3}".to_string(), true),
+                (big, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn listen_unix() {
+        let path = ::std::env::temp_dir().join(format!("opus-listen-{}.sock", ::std::process::id()));
+        let _ = fs::remove_file(&path);
+        let stream = IOStream::listen(&format!("unix://{}", path.display()).parse().unwrap()).unwrap();
+        let mut p1 = unix::net::UnixStream::connect(&path).unwrap();
+        let mut p2 = unix::net::UnixStream::connect(&path).unwrap();
+        p1.write_all(b"a1").unwrap();
+        p2.write_all(b"b1\nb2").unwrap();
+        drop(p2);
+        p1.write_all(b"\n").unwrap();
+        drop(p1);
+        let mut lines: Vec<String> = BufReader::new(stream)
+            .lines()
+            .take(3)
+            .map(|l| l.unwrap())
+            .collect();
+        fs::remove_file(&path).unwrap();
+        lines.sort();
+        assert_eq!(lines, vec!["a1", "b1", "b2"]);
+        assert!("ftp://host".parse::<ListenAddr>().is_err());
+    }
+
+    #[test]
+    fn decompress() {
+        use flate2::{write::GzEncoder, Compression as Level};
+
+        let mut enc = GzEncoder::new(Vec::new(), Level::default());
+        enc.write_all(b"{\"event\": 1}\n").unwrap();
+        let gz = enc.finish().unwrap();
+        assert_eq!(Compression::detect(&gz), Compression::Gzip);
+        let mut s = String::new();
+        IOStream::from_read(io::Cursor::new(gz.clone()))
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "{\"event\": 1}\n");
+
+        let mut raw = Vec::new();
+        IOStream::from_read_raw(io::Cursor::new(gz.clone()))
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw, gz);
+        assert_eq!(Compression::detect(b"ab"), Compression::None);
+    }
+}
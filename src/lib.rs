@@ -6,8 +6,12 @@ pub extern crate pvm_cfg as cfg;
 pub extern crate pvm_data as data;
 pub extern crate pvm_views as views;
 
+#[cfg(feature = "avro")]
+extern crate avro_rs;
+extern crate bzip2;
 extern crate chrono;
 extern crate either;
+extern crate flate2;
 #[macro_use]
 extern crate lazy_static;
 extern crate lending_library;
@@ -22,7 +26,9 @@ extern crate serde_derive;
 extern crate nix;
 extern crate serde_json;
 extern crate uuid;
+extern crate xz2;
 extern crate zip;
+extern crate zstd;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -43,11 +49,14 @@ macro_rules! timeit {
 
 pub use c_api::*;
 
+pub mod archive;
 pub mod c_api;
 pub mod engine;
+pub mod graph;
 pub mod ingest;
 pub mod invbloom;
 pub mod iostream;
+pub mod metrics;
 pub mod neo4j_glue;
 pub mod query;
 pub mod trace;
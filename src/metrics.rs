@@ -0,0 +1,94 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use serde_json;
+
+pub type MetricsSnapshot = BTreeMap<String, BTreeMap<String, u64>>;
+
+#[derive(Debug, Default)]
+pub struct Counters(HashMap<&'static str, HashMap<String, u64>>);
+
+impl Counters {
+    pub fn new() -> Self {
+        Counters::default()
+    }
+
+    pub fn add(&mut self, stage: &'static str, key: &str, n: u64) {
+        let stage = self.0.entry(stage).or_insert_with(HashMap::new);
+        if let Some(v) = stage.get_mut(key) {
+            *v += n;
+            return;
+        }
+        stage.insert(key.to_string(), n);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Metrics(Arc<Mutex<MetricsSnapshot>>);
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn add(&self, stage: &str, key: &str, n: u64) {
+        let mut m = self.0.lock().unwrap();
+        let s = m.entry(stage.to_string()).or_insert_with(BTreeMap::new);
+        *s.entry(key.to_string()).or_insert(0) += n;
+    }
+
+    pub fn set(&self, stage: &str, key: &str, v: u64) {
+        let mut m = self.0.lock().unwrap();
+        let s = m.entry(stage.to_string()).or_insert_with(BTreeMap::new);
+        s.insert(key.to_string(), v);
+    }
+
+    pub fn flush(&self, counters: &mut Counters) {
+        let mut m = self.0.lock().unwrap();
+        for (stage, keys) in counters.0.drain() {
+            let s = m.entry(stage.to_string()).or_insert_with(BTreeMap::new);
+            for (k, n) in keys {
+                *s.entry(k).or_insert(0) += n;
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.snapshot()).unwrap()
+    }
+
+    pub fn dump<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{}", self.to_json())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush_counters() {
+        let m = Metrics::new();
+        let mut c = Counters::new();
+        c.add("events", "read", 2);
+        c.add("events", "read", 1);
+        c.add("nodes", "Actor", 1);
+        m.flush(&mut c);
+        m.flush(&mut c);
+        m.set("cache", "node_cache", 4);
+        m.add("events", "read", 1);
+        let snap = m.snapshot();
+        assert_eq!(snap["events"]["read"], 4);
+        assert_eq!(snap["nodes"]["Actor"], 1);
+        assert_eq!(snap["cache"]["node_cache"], 4);
+        let json: serde_json::Value = serde_json::from_str(&m.to_json()).unwrap();
+        assert_eq!(json["events"]["read"], 4);
+    }
+}
@@ -4,7 +4,7 @@ use std::{
     fs::File,
     io::Write,
     mem,
-    sync::{mpsc::Receiver, Arc},
+    sync::Arc,
     thread,
 };
 
@@ -83,7 +83,7 @@ impl View for CSVView {
         id: usize,
         params: ViewParams,
         _cfg: &Config,
-        stream: Receiver<Arc<DBTr>>,
+        stream: QueueReceiver<Arc<DBTr>>,
     ) -> ViewInst {
         let path = params.get_or_def("path", "./prov_csv.zip");
         let mut out = ZipWriter::new(File::create(path).unwrap());
@@ -109,6 +109,10 @@ impl View for CSVView {
                             .or_insert_with(HashMap::new)
                             .insert(rel.get_db_id(), rel.clone());
                     }
+                    // Nothing is written before the stream ends.
+                    DBTr::Sync(ref done) => {
+                        done.send(id).ok();
+                    }
                 }
             }
 
@@ -1,7 +1,8 @@
 mod csv_view;
 mod neo4j_view;
+mod resume;
 
-pub use self::{csv_view::CSVView, neo4j_view::Neo4JView};
+pub use self::{csv_view::CSVView, neo4j_view::Neo4JView, resume::db_checkpoint};
 
 use std::{borrow::Cow, collections::HashMap, mem};
 
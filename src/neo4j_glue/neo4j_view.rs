@@ -2,13 +2,14 @@ use neo4j::{Neo4jDB, Neo4jOperations, Value};
 
 use std::{
     collections::{hash_map::Entry, HashMap},
-    sync::{mpsc::Receiver, Arc},
+    sync::Arc,
     thread,
 };
 
 use data::ID;
 
 use cfg::Config;
+use metrics::Metrics;
 use neo4j_glue::{ToDBNode, ToDBRel};
 use views::*;
 
@@ -20,6 +21,32 @@ pub struct Neo4JView {
     id: usize,
 }
 
+#[derive(Default)]
+struct Counts {
+    ups: usize,
+    btc: usize,
+    trs: usize,
+    rel_up_base: usize,
+    rel_up_node: usize,
+    rel_up_rel: usize,
+}
+
+impl Counts {
+    fn record(&self, metrics: &Metrics, stage: &str) {
+        for (key, n) in &[
+            ("updates", self.ups),
+            ("batches", self.btc * 4),
+            ("transactions", self.trs),
+            ("rel_updates", self.rel_up_base),
+            ("rel_updates_into_nodes", self.rel_up_base - self.rel_up_node),
+            ("rel_updates_into_updates", self.rel_up_node - self.rel_up_rel),
+            ("rel_updates_executed", self.rel_up_rel),
+        ] {
+            metrics.set(stage, key, *n as u64);
+        }
+    }
+}
+
 impl View for Neo4JView {
     fn new(id: usize) -> Neo4JView {
         Neo4JView { id }
@@ -43,7 +70,7 @@ impl View for Neo4JView {
         id: usize,
         params: ViewParams,
         cfg: &Config,
-        stream: Receiver<Arc<DBTr>>,
+        stream: QueueReceiver<Arc<DBTr>>,
     ) -> ViewInst {
         let mut db = {
             let addr = params.get_or_def("addr", &cfg.db_server);
@@ -51,17 +78,19 @@ impl View for Neo4JView {
             let pass = params.get_or_def("pass", &cfg.db_password);
             Neo4jDB::connect(addr, user, pass).unwrap()
         };
+        let metrics = params
+            .get("metrics")
+            .and_then(|m| m.downcast_ref::<Metrics>())
+            .cloned()
+            .unwrap_or_default();
+        let stage = format!("view.{}.neo4j", id);
+        let merge = cfg.incremental;
         let thr = thread::spawn(move || {
-            let mut nodes = CreateNodes::new();
-            let mut edges = CreateRels::new();
+            let mut nodes = CreateNodes::new(merge);
+            let mut edges = CreateRels::new(merge);
             let mut up_node = UpdateNodes::new();
             let mut up_rel = UpdateRels::new();
-            let mut ups = 0;
-            let mut btc = 0;
-            let mut trs = 0;
-            let mut rel_up_base = 0;
-            let mut rel_up_node = 0;
-            let mut rel_up_rel = 0;
+            let mut c = Counts::default();
 
             let mut tr = db.transaction();
 
@@ -91,43 +120,54 @@ impl View for Neo4JView {
                             id,
                             hashmap!("labels" => labs.into(), "props"  => props.into()),
                         );
-                        ups += 1;
+                        c.ups += 1;
                     }
                     DBTr::CreateRel(ref rel) => {
                         let (id, data) = rel.to_db();
                         edges.add(id, data);
-                        ups += 1;
+                        c.ups += 1;
                     }
                     DBTr::UpdateNode(ref node) => {
                         let (id, _, props) = node.to_db();
                         if let Some(props) = nodes.update(id, props.into()) {
                             if up_node.add(id, props) {
-                                ups += 1;
+                                c.ups += 1;
                             }
                         }
                     }
                     DBTr::UpdateRel(ref rel) => {
-                        rel_up_base += 1;
+                        c.rel_up_base += 1;
                         let (id, data) = rel.to_db();
                         if let Some(data) = edges.update(id, data) {
-                            rel_up_node += 1;
+                            c.rel_up_node += 1;
                             if up_rel.add(id, data) {
-                                ups += 1;
-                                rel_up_rel += 1;
+                                c.ups += 1;
+                                c.rel_up_rel += 1;
                             }
                         }
                     }
+                    DBTr::Sync(ref done) => {
+                        nodes.execute(&mut tr);
+                        edges.execute(&mut tr);
+                        up_node.execute(&mut tr);
+                        up_rel.execute(&mut tr);
+                        tr.commit_and_refresh().unwrap();
+                        c.trs += 1;
+                        done.send(id).ok();
+                        continue;
+                    }
                 }
-                if ups > (btc + 1) * BATCH_SIZE {
+                if c.ups > (c.btc + 1) * BATCH_SIZE {
                     nodes.execute(&mut tr);
                     edges.execute(&mut tr);
                     up_node.execute(&mut tr);
                     up_rel.execute(&mut tr);
-                    btc += 1;
+                    c.btc += 1;
+                    c.record(&metrics, &stage);
                 }
-                if ups > (trs + 1) * TR_SIZE {
+                if c.ups > (c.trs + 1) * TR_SIZE {
                     tr.commit_and_refresh().unwrap();
-                    trs += 1;
+                    c.trs += 1;
                 }
             }
             nodes.execute(&mut tr);
@@ -136,11 +176,8 @@ impl View for Neo4JView {
             up_rel.execute(&mut tr);
             println!("Final Commit");
             tr.commit().unwrap();
-            trs += 1;
-            println!("Neo4J Updates Issued: {}", ups);
-            println!("Neo4J Batches Issued: {}", btc * 4);
-            println!("Neo4J Transactions Issued: {}", trs);
-            println!("Rel Updates: {}, Absorbed into Nodes: {}, Absorbed into other updates: {}, Finally executed: {}", rel_up_base, rel_up_base - rel_up_node, rel_up_node - rel_up_rel, rel_up_rel);
+            c.trs += 1;
+            c.record(&metrics, &stage);
         });
         ViewInst {
             id,
@@ -153,12 +190,14 @@ impl View for Neo4JView {
 
 struct CreateNodes {
     nodes: HashMap<ID, HashMap<&'static str, Value>>,
+    merge: bool,
 }
 
 impl CreateNodes {
-    fn new() -> Self {
+    fn new(merge: bool) -> Self {
         CreateNodes {
             nodes: HashMap::new(),
+            merge,
         }
     }
     fn execute(&mut self, db: &mut impl Neo4jOperations) {
@@ -166,12 +205,16 @@ impl CreateNodes {
         self._execute(db, nodes);
     }
     fn _execute(&mut self, db: &mut impl Neo4jOperations, nodes: Value) {
-        db.run_unchecked(
+        let query = if self.merge {
+            "UNWIND $nodes AS n
+             CALL apoc.merge.node(n.labels, {db_id: n.props.db_id}, n.props) YIELD node
+             RETURN 0"
+        } else {
             "UNWIND $nodes AS n
              CALL apoc.create.node(n.labels, n.props) YIELD node
-             RETURN 0",
-            hashmap!("nodes" => nodes),
-        );
+             RETURN 0"
+        };
+        db.run_unchecked(query, hashmap!("nodes" => nodes));
     }
     fn add(&mut self, id: ID, data: HashMap<&'static str, Value>) {
         self.nodes.insert(id, data);
@@ -189,24 +232,33 @@ impl CreateNodes {
 
 struct CreateRels {
     rels: HashMap<ID, Value>,
+    merge: bool,
 }
 
 impl CreateRels {
-    fn new() -> Self {
+    fn new(merge: bool) -> Self {
         CreateRels {
             rels: HashMap::new(),
+            merge,
         }
     }
     fn execute(&mut self, db: &mut impl Neo4jOperations) {
         let rels: Value = self.rels.drain().map(|(_k, v)| v).collect();
-        db.run_unchecked(
+        let query = if self.merge {
+            "UNWIND $rels AS r
+             MATCH (s:Node {db_id: r.src}),
+                   (d:Node {db_id: r.dst})
+             CALL apoc.merge.relationship(s, r.type, {db_id: r.props.db_id}, r.props, d)
+             YIELD rel
+             RETURN 0"
+        } else {
             "UNWIND $rels AS r
              MATCH (s:Node {db_id: r.src}),
                    (d:Node {db_id: r.dst})
              CALL apoc.create.relationship(s, r.type, r.props, d) YIELD rel
-             RETURN 0",
-            hashmap!("rels" => rels),
-        );
+             RETURN 0"
+        };
+        db.run_unchecked(query, hashmap!("rels" => rels));
     }
     fn add(&mut self, id: ID, data: Value) {
         self.rels.insert(id, data);
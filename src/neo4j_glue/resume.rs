@@ -0,0 +1,265 @@
+use std::{borrow::Cow, collections::HashMap, convert::TryFrom};
+
+use neo4j::{Neo4jDB, Neo4jOperations, Value};
+use serde_json::{self, Value as Json};
+use uuid::Uuid;
+
+use data::{
+    node_types::{NameNode, PVMDataType},
+    rel_types::{Inf, InfInit, Named, NamedInit, PVMOps, Rel},
+    HasDst, HasID, HasSrc, RelGenerable, ID,
+};
+use ingest::{Checkpoint, NodeState};
+use query::low::db_err;
+
+use super::{IntoID, IntoVal};
+
+type ResumeResult<T> = Result<T, Cow<'static, str>>;
+
+const DB_VERSION: i64 = 2;
+
+fn json_id(val: &Json) -> Option<ID> {
+    val.as_i64().map(|i| ID::new(i as u64))
+}
+
+fn pvm_op(s: &str) -> PVMOps {
+    match s {
+        "Sink" => PVMOps::Sink,
+        "Source" => PVMOps::Source,
+        "Connect" => PVMOps::Connect,
+        "Version" => PVMOps::Version,
+        _ => PVMOps::Unknown,
+    }
+}
+
+fn node_state(val: &Json) -> Option<NodeState> {
+    let labels = val["labels"].as_array()?;
+    let pvm_ty = labels
+        .iter()
+        .filter_map(|l| match l.as_str()? {
+            "Actor" => Some(PVMDataType::Actor),
+            "Store" => Some(PVMDataType::Store),
+            "EditSession" => Some(PVMDataType::EditSession),
+            "Conduit" => Some(PVMDataType::Conduit),
+            _ => None,
+        })
+        .next()?;
+    let props = &val["props"];
+    Some(NodeState {
+        pvm_ty,
+        ty: props["type"].as_str()?.to_string(),
+        id: json_id(&props["db_id"])?,
+        uuid: Uuid::parse_str(props["uuid"].as_str()?).ok()?,
+        ctx: json_id(&props["ctx"]).unwrap_or_else(|| ID::new(0)),
+        meta: match props["meta_hist"].as_str() {
+            Some(s) => serde_json::from_str(s).ok()?,
+            None => Default::default(),
+        },
+    })
+}
+
+fn name_node(val: &Json) -> Option<NameNode> {
+    let props = &val["props"];
+    let id = json_id(&props["db_id"])?;
+    if let Some(path) = props["path"].as_str() {
+        Some(NameNode::Path(id, path.to_string()))
+    } else {
+        Some(NameNode::Net(
+            id,
+            props["addr"].as_str()?.to_string(),
+            u16::try_from(props["port"].as_u64()?).ok()?,
+        ))
+    }
+}
+
+fn rel(val: &Json) -> Option<Rel> {
+    let props = &val["props"];
+    let id = json_id(&props["db_id"])?;
+    let (src, dst) = (json_id(&val["src"])?, json_id(&val["dst"])?);
+    match val["type"].as_str()? {
+        "INF" => Some(Rel::Inf(Inf::new(
+            id,
+            src,
+            dst,
+            InfInit {
+                pvm_op: pvm_op(props["pvm_op"].as_str()?),
+                ctx: json_id(&props["ctx"])?,
+                byte_count: props["byte_count"].as_i64().unwrap_or(0),
+            },
+        ))),
+        "NAMED" => Some(Rel::Named(Named::new(
+            id,
+            src,
+            dst,
+            NamedInit {
+                start: json_id(&props["start"])?,
+                end: json_id(&props["end"])?,
+            },
+        ))),
+        _ => None,
+    }
+}
+
+// A row that cannot be restored would leave its UUID or name unknown to the
+// resumed PVM, which would then create it a second time.
+fn parse_rows<T>(
+    rows: &[Json],
+    what: &str,
+    parse: fn(&Json) -> Option<T>,
+) -> ResumeResult<Vec<T>> {
+    rows.iter()
+        .map(|row| {
+            parse(row).ok_or_else(|| format!("Cannot restore {} from row {}", what, row).into())
+        })
+        .collect()
+}
+
+fn json_rows(
+    db: &mut Neo4jDB,
+    query: &str,
+    params: HashMap<&str, Value>,
+) -> ResumeResult<Vec<Json>> {
+    Ok(db
+        .run(query, params)
+        .map_err(db_err)?
+        .first()
+        .filter_map(|data| match data {
+            Value::String(s) => serde_json::from_str(&s).ok(),
+            _ => None,
+        })
+        .collect())
+}
+
+fn max_id(db: &mut Neo4jDB, query: &str) -> ResumeResult<u64> {
+    Ok(db
+        .run(query, HashMap::new())
+        .map_err(db_err)?
+        .first()
+        .filter_map(|v| v.into_id())
+        .map(|id| id.inner())
+        .max()
+        .unwrap_or(0))
+}
+
+pub fn db_checkpoint(db: &mut Neo4jDB) -> ResumeResult<Checkpoint> {
+    let versions = db
+        .run("MATCH (i:DBInfo) RETURN i.pvm_version", HashMap::new())
+        .map_err(db_err)?
+        .first();
+    for v in versions {
+        match v {
+            Value::Integer(DB_VERSION) => {}
+            _ => return Err("Database was written by an incompatible version of libPVM".into()),
+        }
+    }
+
+    let next_id = max_id(db, "MATCH (n:Node) RETURN max(n.db_id)")?
+        .max(max_id(db, "MATCH ()-[r]->() RETURN max(r.db_id)")?) + 1;
+    let mut cp = Checkpoint::new(0, next_id);
+
+    for row in db
+        .run("MATCH (s:Schema) RETURN s.name, s.base", HashMap::new())
+        .map_err(db_err)?
+        .rows()
+    {
+        let mut row = row.into_iter();
+        match (row.next(), row.next()) {
+            (Some(Value::String(name)), Some(Value::String(ref base))) if base == "Context" => {
+                cp.ctx_types.push(name)
+            }
+            (Some(Value::String(name)), _) => cp.types.push(name),
+            _ => {}
+        }
+    }
+
+    let rows = json_rows(
+        db,
+        "MATCH (n:Node) WHERE exists(n.uuid)
+         WITH n.uuid AS uuid, max(n.db_id) AS id
+         MATCH (n:Node {db_id: id})
+         RETURN apoc.convert.toJson({labels: labels(n), props: properties(n)})",
+        HashMap::new(),
+    )?;
+    cp.nodes = parse_rows(&rows, "node", node_state)?;
+    cp.uuids = cp.nodes.iter().map(|n| (n.uuid, n.id)).collect();
+
+    let rows = json_rows(
+        db,
+        "MATCH (n:Name)
+         RETURN apoc.convert.toJson({labels: labels(n), props: properties(n)})",
+        HashMap::new(),
+    )?;
+    cp.names = parse_rows(&rows, "name", name_node)?;
+
+    let ids: Value = cp.nodes.iter().map(|n| n.id.into_val()).collect();
+    let rows = json_rows(
+        db,
+        "UNWIND $ids AS id
+         MATCH (s:Node {db_id: id})-[r]->(d:Node)
+         WHERE d.db_id IN $ids OR d:Name
+         RETURN apoc.convert.toJson({type: type(r), src: s.db_id, dst: d.db_id,
+                                     props: properties(r)})",
+        hashmap!("ids" => ids),
+    )?;
+    cp.rels = parse_rows(&rows, "relationship", rel)?;
+    cp.rel_src_dst = cp
+        .rels
+        .iter()
+        .map(|r| match r {
+            Rel::Inf(i) => ((i.get_src(), i.get_dst()), i.get_db_id()),
+            Rel::Named(n) => ((n.get_src(), n.get_dst()), n.get_db_id()),
+        })
+        .collect();
+    Ok(cp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_db_records() {
+        let n = node_state(&serde_json::from_str(
+            r#"{"labels": ["Node", "Actor"],
+                "props": {"db_id": 12, "uuid": "00000000-0000-0000-0000-000000000001",
+                          "type": "process", "ctx": 3, "pid": 7,
+                          "meta_hist": "{\"entries\": {}}"}}"#,
+        ).unwrap()).unwrap();
+        assert_eq!((n.id, n.ctx, n.pvm_ty), (ID::new(12), ID::new(3), PVMDataType::Actor));
+        assert_eq!(n.ty, "process");
+
+        let name = name_node(&serde_json::from_str(
+            r#"{"labels": ["Node", "Name", "Net"],
+                "props": {"db_id": 2, "addr": "10.0.0.1", "port": 80}}"#,
+        ).unwrap());
+        match name {
+            Some(NameNode::Net(id, addr, 80)) => assert_eq!((id, &addr[..]), (ID::new(2), "10.0.0.1")),
+            n => panic!("unexpected name {:?}", n),
+        }
+
+        let r = rel(&serde_json::from_str(
+            r#"{"type": "INF", "src": 1, "dst": 12,
+                "props": {"db_id": 13, "pvm_op": "Version", "ctx": 3, "byte_count": 0}}"#,
+        ).unwrap());
+        match r {
+            Some(Rel::Inf(i)) => assert_eq!((i.get_db_id(), i.get_dst()), (ID::new(13), ID::new(12))),
+            r => panic!("unexpected rel {:?}", r),
+        }
+    }
+
+    #[test]
+    fn reject_bad_rows() {
+        let rows: Vec<Json> = vec![
+            serde_json::from_str(r#"{"labels": ["Node", "Name", "Net"],
+                                     "props": {"db_id": 2, "addr": "10.0.0.1", "port": 80}}"#)
+                .unwrap(),
+            serde_json::from_str(r#"{"labels": ["Node", "Name", "Net"],
+                                     "props": {"db_id": 3, "addr": "10.0.0.1", "port": 70000}}"#)
+                .unwrap(),
+        ];
+        assert_eq!(parse_rows(&rows[..1], "name", name_node).map(|v| v.len()), Ok(1));
+        let err = parse_rows(&rows, "name", name_node).unwrap_err();
+        assert!(err.starts_with("Cannot restore name from row"));
+        assert!(err.contains("70000"));
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use neo4j::{Neo4jDB, Neo4jOperations};
+
+use data::{rel_types::Rel, HasDst, HasSrc, ID};
+use graph::{Direction, Graph, GraphRef};
+use neo4j_glue::{IntoID, IntoVal};
+
+use super::{
+    low::{db_err, parse_time, QueryResult},
+    scope::TimeWindow,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lineage {
+    Ancestors,
+    Descendants,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LineageQuery {
+    pub max_depth: Option<usize>,
+    pub window: TimeWindow,
+}
+
+pub trait LineageSource {
+    fn lineage(&mut self, start: ID, dir: Lineage, q: &LineageQuery) -> QueryResult<Vec<ID>>;
+}
+
+impl LineageSource for Graph {
+    fn lineage(&mut self, start: ID, dir: Lineage, q: &LineageQuery) -> QueryResult<Vec<ID>> {
+        Ok(graph_lineage(self, start, dir, q))
+    }
+}
+
+impl LineageSource for GraphRef {
+    fn lineage(&mut self, start: ID, dir: Lineage, q: &LineageQuery) -> QueryResult<Vec<ID>> {
+        Ok(graph_lineage(&self.read().unwrap(), start, dir, q))
+    }
+}
+
+type Bound = Option<DateTime<Utc>>;
+
+fn precedes(a: Bound, b: Bound) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a <= b,
+        _ => true,
+    }
+}
+
+fn looser(dir: Lineage, new: Bound, old: Bound) -> bool {
+    match (new, old) {
+        (None, Some(_)) => true,
+        (Some(n), Some(o)) => match dir {
+            Lineage::Ancestors => n > o,
+            Lineage::Descendants => n < o,
+        },
+        _ => false,
+    }
+}
+
+// Breadth-first expansion from `start`, where `step` lists the nodes one
+// causal hop away together with the time of the rel reaching them.
+fn expand<F>(start: ID, dir: Lineage, q: &LineageQuery, mut step: F) -> QueryResult<Vec<ID>>
+where
+    F: FnMut(ID) -> QueryResult<Vec<(ID, Bound)>>,
+{
+    let limit = match dir {
+        Lineage::Ancestors => q.window.end,
+        Lineage::Descendants => q.window.start,
+    };
+    let mut best: HashMap<ID, (Bound, usize)> = HashMap::new();
+    let mut ret = Vec::new();
+    let mut queue = VecDeque::new();
+    best.insert(start, (limit, 0));
+    queue.push_back((start, limit, 0));
+    while let Some((id, limit, depth)) = queue.pop_front() {
+        if q.max_depth.map_or(false, |d| depth >= d) {
+            continue;
+        }
+        for (next, time) in step(id)? {
+            let causal = match dir {
+                Lineage::Ancestors => precedes(time, limit),
+                Lineage::Descendants => precedes(limit, time),
+            };
+            if !causal || !q.window.contains(time) {
+                continue;
+            }
+            let (lim, d) = (time.or(limit), depth + 1);
+            let improved = match best.get_mut(&next) {
+                Some(b) => {
+                    let improved = looser(dir, lim, b.0) || d < b.1;
+                    if looser(dir, lim, b.0) {
+                        b.0 = lim;
+                    }
+                    b.1 = b.1.min(d);
+                    improved
+                }
+                None => {
+                    best.insert(next, (lim, d));
+                    ret.push(next);
+                    true
+                }
+            };
+            if improved {
+                queue.push_back((next, lim, d));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+fn graph_lineage(g: &Graph, start: ID, dir: Lineage, q: &LineageQuery) -> Vec<ID> {
+    let adj = match dir {
+        Lineage::Ancestors => Direction::In,
+        Lineage::Descendants => Direction::Out,
+    };
+    let step = |id| {
+        Ok(g.neighbours(id, adj)
+            .into_iter()
+            .filter_map(|(rel, _)| match rel {
+                Rel::Inf(inf) => Some(inf),
+                Rel::Named(_) => None,
+            })
+            .map(|inf| {
+                let next = match dir {
+                    Lineage::Ancestors => inf.get_src(),
+                    Lineage::Descendants => inf.get_dst(),
+                };
+                (next, g.ctx_time(inf.ctx))
+            })
+            .collect())
+    };
+    expand(start, dir, q, step).unwrap_or_default()
+}
+
+// Walks the database one hop per query, so the search is pruned the same
+// way as in memory instead of Cypher enumerating every path.
+impl LineageSource for Neo4jDB {
+    fn lineage(&mut self, start: ID, dir: Lineage, q: &LineageQuery) -> QueryResult<Vec<ID>> {
+        let path = match dir {
+            Lineage::Ancestors => "(a:Node)-[r:INF]->(:Node {db_id: $id})",
+            Lineage::Descendants => "(:Node {db_id: $id})-[r:INF]->(a:Node)",
+        };
+        let query = format!(
+            "MATCH {}
+             RETURN a.db_id, head([(c:Context {{db_id: r.ctx}}) | c.time])
+             ORDER BY a.db_id",
+            path
+        );
+        expand(start, dir, q, |id| {
+            Ok(self
+                .run(&query, hashmap!("id" => id.into_val()))
+                .map_err(db_err)?
+                .rows()
+                .filter_map(|row| {
+                    let mut row = row.into_iter();
+                    let id = row.next()?.into_id()?;
+                    Some((id, row.next().and_then(parse_time)))
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    use data::{
+        node_types::{ConcreteType, ContextType, CtxNode, DataNode, Node, PVMDataType},
+        rel_types::{Inf, InfInit, PVMOps},
+        RelGenerable,
+    };
+    use views::DBTr;
+
+    lazy_static! {
+        static ref FILE: ConcreteType = ConcreteType {
+            pvm_ty: PVMDataType::Store,
+            name: "file",
+            props: HashMap::new(),
+        };
+        static ref CTX: ContextType = ContextType {
+            name: "test_context",
+            props: vec!["time"],
+        };
+    }
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp(secs, 0)
+    }
+
+    // Builds 1 -> 2 -> 3 -> 4 with a version edge 4 -> 5, the rel from n to
+    // n + 1 happening at time n.
+    fn chain() -> Graph {
+        let mut g = Graph::new();
+        for n in 1..6 {
+            g.apply(&DBTr::CreateNode(Node::Data(DataNode::new(
+                PVMDataType::Store,
+                &FILE,
+                ID::new(n),
+                Default::default(),
+                ID::new(0),
+                None,
+            ))));
+            g.apply(&DBTr::CreateNode(Node::Ctx(
+                CtxNode::new(
+                    ID::new(100 + n),
+                    &CTX,
+                    hashmap!("time" => time(n as i64).to_rfc3339()),
+                ).unwrap(),
+            )));
+        }
+        for n in 1..5 {
+            g.apply(&DBTr::CreateRel(Rel::Inf(Inf::new(
+                ID::new(10 + n),
+                ID::new(n),
+                ID::new(n + 1),
+                InfInit {
+                    pvm_op: if n == 4 {
+                        PVMOps::Version
+                    } else {
+                        PVMOps::Source
+                    },
+                    ctx: ID::new(100 + n),
+                    byte_count: 0,
+                },
+            ))));
+        }
+        g
+    }
+
+    fn ids(v: &[u64]) -> Vec<ID> {
+        v.iter().map(|n| ID::new(*n)).collect()
+    }
+
+    #[test]
+    fn ancestry_follows_versions() {
+        let mut g = chain();
+        let q = LineageQuery::default();
+        assert_eq!(g.lineage(ID::new(5), Lineage::Ancestors, &q), Ok(ids(&[4, 3, 2, 1])));
+        assert_eq!(g.lineage(ID::new(1), Lineage::Ancestors, &q), Ok(ids(&[])));
+    }
+
+    #[test]
+    fn descendancy_depth() {
+        let mut g = chain();
+        let q = LineageQuery {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(g.lineage(ID::new(2), Lineage::Descendants, &q), Ok(ids(&[3, 4])));
+    }
+
+    #[test]
+    fn time_bounds() {
+        let mut g = chain();
+        let q = LineageQuery {
+            window: TimeWindow::new(Some(time(2)), Some(time(3))),
+            ..Default::default()
+        };
+        assert_eq!(g.lineage(ID::new(2), Lineage::Descendants, &q), Ok(ids(&[3, 4])));
+        assert_eq!(g.lineage(ID::new(5), Lineage::Ancestors, &q), Ok(ids(&[])));
+    }
+
+    #[test]
+    fn ancestry_is_causal() {
+        let mut g = chain();
+        g.apply(&DBTr::CreateRel(Rel::Inf(Inf::new(
+            ID::new(20),
+            ID::new(5),
+            ID::new(2),
+            InfInit {
+                pvm_op: PVMOps::Source,
+                ctx: ID::new(105),
+                byte_count: 0,
+            },
+        ))));
+        let q = LineageQuery::default();
+        assert_eq!(g.lineage(ID::new(3), Lineage::Ancestors, &q), Ok(ids(&[2, 1])));
+        assert_eq!(g.lineage(ID::new(5), Lineage::Descendants, &q), Ok(ids(&[2])));
+        assert_eq!(g.lineage(ID::new(4), Lineage::Descendants, &q), Ok(ids(&[5, 2])));
+    }
+}
@@ -1,34 +1,181 @@
-//use data::node_types::DataNode;
+use std::{borrow::Cow, collections::HashMap, fmt::Debug};
 
-use neo4j::{Neo4jDB, Neo4jOperations};
+use chrono::{DateTime, Utc};
 
-//use neo4j_glue::{FromDB, IntoVal};
+use data::ID;
 
-//use uuid::Uuid;
+use graph::Direction;
 
-/*pub fn nodes_by_uuid(cypher: &mut Neo4jDB, uuid: Uuid) -> Vec<DataNode> {
-    cypher
+use neo4j::{Neo4jDB, Neo4jOperations, Value};
+
+use neo4j_glue::{IntoID, IntoVal};
+
+use uuid::Uuid;
+
+use super::{
+    record::{NodeRecord, RelRecord},
+    scope::{versions_in, TimeWindow},
+};
+
+pub type QueryResult<T> = Result<T, Cow<'static, str>>;
+
+pub(crate) fn db_err<E: Debug>(e: E) -> Cow<'static, str> {
+    format!("Database query failed: {:?}", e).into()
+}
+
+fn json_rows(
+    cypher: &mut Neo4jDB,
+    query: &str,
+    params: HashMap<&str, Value>,
+) -> QueryResult<Vec<String>> {
+    Ok(cypher
+        .run(query, params)
+        .map_err(db_err)?
+        .first()
+        .filter_map(|data| match data {
+            Value::String(s) => Some(s),
+            _ => None,
+        })
+        .collect())
+}
+
+// Cypher equivalent of scope::rel_in for the relationship `r`, carrying
+// `vars` through. Empty when the window is unbounded.
+fn rel_in(w: &TimeWindow, vars: &str, params: &mut HashMap<&str, Value>) -> String {
+    if w.is_unbounded() {
+        return String::new();
+    }
+    let mut inf = vec!["s IS NOT NULL"];
+    let mut named = Vec::new();
+    if let Some(start) = w.start {
+        params.insert("start", Value::from(start.to_rfc3339()));
+        inf.push("s >= $start");
+        named.push("(e IS NULL OR e >= $start)");
+    }
+    if let Some(end) = w.end {
+        params.insert("end", Value::from(end.to_rfc3339()));
+        inf.push("s <= $end");
+        named.push("(s IS NULL OR s <= $end)");
+    }
+    format!(
+        "WITH {}, head([(c:Context {{db_id: coalesce(r.ctx, r.start)}}) | c.time]) AS s,
+              CASE WHEN r.end IS NULL OR r.end = 0 THEN null
+                   ELSE head([(c:Context {{db_id: r.end}}) | c.time]) END AS e
+         WHERE CASE type(r) WHEN \"INF\" THEN {} ELSE {} END",
+        vars,
+        inf.join(" AND "),
+        named.join(" AND ")
+    )
+}
+
+pub(crate) fn parse_time(val: Value) -> Option<DateTime<Utc>> {
+    match val {
+        Value::String(s) => DateTime::parse_from_rfc3339(&s)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+pub fn ids_by_uuid(cypher: &mut Neo4jDB, uuid: Uuid, w: &TimeWindow) -> QueryResult<Vec<ID>> {
+    let versions = cypher
         .run(
-            "MATCH (n {uuid: {uuid}})
-              RETURN n",
+            "MATCH (n:Node {uuid: $uuid})
+              RETURN n.db_id, head([(c:Context {db_id: n.ctx}) | c.time])
+              ORDER BY n.db_id",
             hashmap!("uuid" => uuid.into_val()),
         )
-        .unwrap()
+        .map_err(db_err)?
+        .rows()
+        .filter_map(|row| {
+            let mut row = row.into_iter();
+            let id = row.next()?.into_id()?;
+            Some((id, row.next().and_then(parse_time)))
+        })
+        .collect();
+    Ok(versions_in(versions, w))
+}
+
+pub fn ids_by_path(cypher: &mut Neo4jDB, path: &str, w: &TimeWindow) -> QueryResult<Vec<ID>> {
+    let mut params = hashmap!("path" => Value::from(path));
+    let window = rel_in(w, "n, r", &mut params);
+    Ok(cypher
+        .run(
+            &format!(
+                "MATCH (n:Node)-[r:NAMED]->(:Path {{path: $path}})
+                  {}
+                  WITH DISTINCT n
+                  RETURN n.db_id
+                  ORDER BY n.db_id",
+                window
+            ),
+            params,
+        )
+        .map_err(db_err)?
         .first()
-        .map(|data| DataNode::from_value(data).unwrap())
-        .collect()
-}*/
+        .filter_map(|data| data.into_id())
+        .collect())
+}
+
+pub fn nodes_by_ids(cypher: &mut Neo4jDB, ids: &[ID]) -> QueryResult<Vec<NodeRecord>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ids: Value = ids.iter().map(|id| id.into_val()).collect();
+    Ok(json_rows(
+        cypher,
+        "UNWIND range(0, size($ids) - 1) AS i
+          MATCH (n:Node {db_id: $ids[i]})
+          WITH i, n
+          ORDER BY i
+          RETURN apoc.convert.toJson({labels: labels(n), props: properties(n)})",
+        hashmap!("ids" => ids),
+    )?.iter()
+        .filter_map(|s| NodeRecord::from_json_str(s))
+        .collect())
+}
+
+pub fn neighbours(
+    cypher: &mut Neo4jDB,
+    id: ID,
+    dir: Direction,
+    w: &TimeWindow,
+) -> QueryResult<Vec<RelRecord>> {
+    let pattern = match dir {
+        Direction::Out => "(n:Node {db_id: $id})-[r]->(m:Node)",
+        Direction::In => "(n:Node {db_id: $id})<-[r]-(m:Node)",
+        Direction::Both => "(n:Node {db_id: $id})-[r]-(m:Node)",
+    };
+    let mut params = hashmap!("id" => id.into_val());
+    let window = rel_in(w, "r, m", &mut params);
+    Ok(json_rows(
+        cypher,
+        &format!(
+            "MATCH {}
+              {}
+              RETURN apoc.convert.toJson({{type: type(r),
+                                           src: startNode(r).db_id,
+                                           dst: endNode(r).db_id,
+                                           props: properties(r),
+                                           node: {{labels: labels(m), props: properties(m)}}}})",
+            pattern, window
+        ),
+        params,
+    )?.iter()
+        .filter_map(|s| RelRecord::from_json_str(s))
+        .collect())
+}
 
-pub fn count_processes(cypher: &mut Neo4jDB) -> i64 {
+pub fn count_processes(cypher: &mut Neo4jDB) -> QueryResult<i64> {
     cypher
         .run(
             "MATCH (n:Actor {type: \"process\"})
               RETURN count(n)",
             hashmap!(),
         )
-        .unwrap()
+        .map_err(db_err)?
         .first()
-        .map(|data| data.into_int().unwrap())
         .next()
-        .unwrap()
+        .and_then(|data| data.into_int())
+        .ok_or_else(|| "Process count query returned no count".into())
 }
@@ -1 +1,4 @@
+pub mod lineage;
 pub mod low;
+pub mod record;
+pub mod scope;
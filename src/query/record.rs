@@ -0,0 +1,243 @@
+use serde_json::{self, Value};
+use uuid::Uuid;
+
+use data::{
+    node_types::{NameNode, Node, SchemaNode},
+    rel_types::Rel,
+    HasDst, HasID, HasSrc, ID,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NodeKind {
+    Data,
+    Context,
+    Name,
+    Schema,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RelKind {
+    Inf,
+    Named,
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeRecord {
+    pub id: ID,
+    pub kind: NodeKind,
+    pub uuid: Option<Uuid>,
+    pub ty: String,
+    pub props: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RelRecord {
+    pub id: ID,
+    pub kind: RelKind,
+    pub src: ID,
+    pub dst: ID,
+    pub props: Vec<(String, String)>,
+    pub node: NodeRecord,
+}
+
+fn prop_str(val: &Value) -> String {
+    match val {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn json_id(val: &Value) -> Option<ID> {
+    val.as_i64().map(|i| ID::new(i as u64))
+}
+
+impl<'a> From<&'a Node> for NodeRecord {
+    fn from(node: &'a Node) -> Self {
+        let (kind, uuid, ty, props) = match node {
+            Node::Data(d) => {
+                let mut props: Vec<(String, String)> = d
+                    .meta
+                    .iter_latest()
+                    .map(|(k, v, _, _)| (k.to_string(), v.to_string()))
+                    .collect();
+                props.push(("ctx".to_string(), d.ctx().inner().to_string()));
+                (NodeKind::Data, Some(d.uuid()), d.ty().name, props)
+            }
+            Node::Ctx(c) => (
+                NodeKind::Context,
+                None,
+                c.ty().name,
+                c.cont
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            ),
+            Node::Name(NameNode::Path(_, path)) => (
+                NodeKind::Name,
+                None,
+                "path",
+                vec![("path".to_string(), path.clone())],
+            ),
+            Node::Name(NameNode::Net(_, addr, port)) => (
+                NodeKind::Name,
+                None,
+                "net",
+                vec![
+                    ("addr".to_string(), addr.clone()),
+                    ("port".to_string(), port.to_string()),
+                ],
+            ),
+            Node::Schema(SchemaNode::Data(_, ty)) => (NodeKind::Schema, None, ty.name, Vec::new()),
+            Node::Schema(SchemaNode::Context(_, ty)) => {
+                (NodeKind::Schema, None, ty.name, Vec::new())
+            }
+        };
+        NodeRecord {
+            id: node.get_db_id(),
+            kind,
+            uuid,
+            ty: ty.to_string(),
+            props,
+        }
+    }
+}
+
+impl NodeRecord {
+    pub fn from_json(val: &Value) -> Option<Self> {
+        let labels: Vec<&str> = val["labels"]
+            .as_array()?
+            .iter()
+            .filter_map(|l| l.as_str())
+            .collect();
+        let props = val["props"].as_object()?;
+        let kind = if labels.contains(&"Context") {
+            NodeKind::Context
+        } else if labels.contains(&"Name") {
+            NodeKind::Name
+        } else if labels.contains(&"Schema") {
+            NodeKind::Schema
+        } else {
+            NodeKind::Data
+        };
+        let ty = match kind {
+            NodeKind::Name if labels.contains(&"Net") => "net".to_string(),
+            NodeKind::Name => "path".to_string(),
+            NodeKind::Schema => props.get("name").map(prop_str).unwrap_or_default(),
+            _ => props.get("type").map(prop_str).unwrap_or_default(),
+        };
+        Some(NodeRecord {
+            id: props.get("db_id").and_then(json_id)?,
+            kind,
+            uuid: props
+                .get("uuid")
+                .and_then(|u| u.as_str())
+                .and_then(|u| Uuid::parse_str(u).ok()),
+            ty,
+            props: props
+                .iter()
+                .filter(|(k, _)| !["db_id", "uuid", "type", "meta_hist"].contains(&&k[..]))
+                .map(|(k, v)| (k.clone(), prop_str(v)))
+                .collect(),
+        })
+    }
+
+    pub fn from_json_str(s: &str) -> Option<Self> {
+        serde_json::from_str(s).ok().and_then(|v| Self::from_json(&v))
+    }
+}
+
+impl RelRecord {
+    pub fn new(rel: &Rel, node: &Node) -> Self {
+        let (kind, props) = match rel {
+            Rel::Inf(i) => (
+                RelKind::Inf,
+                vec![
+                    ("pvm_op".to_string(), format!("{:?}", i.pvm_op)),
+                    ("ctx".to_string(), i.ctx.inner().to_string()),
+                    ("byte_count".to_string(), i.byte_count.to_string()),
+                ],
+            ),
+            Rel::Named(n) => (
+                RelKind::Named,
+                vec![
+                    ("start".to_string(), n.start.inner().to_string()),
+                    ("end".to_string(), n.end.inner().to_string()),
+                ],
+            ),
+        };
+        RelRecord {
+            id: rel.get_db_id(),
+            kind,
+            src: rel.get_src(),
+            dst: rel.get_dst(),
+            props,
+            node: NodeRecord::from(node),
+        }
+    }
+
+    pub fn from_json_str(s: &str) -> Option<Self> {
+        let val: Value = serde_json::from_str(s).ok()?;
+        let props = val["props"].as_object()?;
+        Some(RelRecord {
+            id: props.get("db_id").and_then(json_id)?,
+            kind: if val["type"] == "NAMED" {
+                RelKind::Named
+            } else {
+                RelKind::Inf
+            },
+            src: json_id(&val["src"])?,
+            dst: json_id(&val["dst"])?,
+            props: props
+                .iter()
+                .filter(|(k, _)| *k != "db_id")
+                .map(|(k, v)| (k.clone(), prop_str(v)))
+                .collect(),
+            node: NodeRecord::from_json(&val["node"])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_from_json() {
+        let n = NodeRecord::from_json_str(
+            r#"{"labels": ["Node", "Actor"],
+                "props": {"db_id": 12, "uuid": "00000000-0000-0000-0000-000000000001",
+                          "type": "process", "pid": 7, "cmdline": "sh",
+                          "meta_hist": "{}"}}"#,
+        ).unwrap();
+        assert_eq!(n.id, ID::new(12));
+        assert_eq!(n.kind, NodeKind::Data);
+        assert_eq!(n.ty, "process");
+        assert!(n.uuid.is_some());
+        let mut props = n.props.clone();
+        props.sort();
+        assert_eq!(
+            props,
+            vec![
+                ("cmdline".to_string(), "sh".to_string()),
+                ("pid".to_string(), "7".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rel_from_json() {
+        let r = RelRecord::from_json_str(
+            r#"{"type": "NAMED", "src": 1, "dst": 2,
+                "props": {"db_id": 3, "start": 4, "end": 0},
+                "node": {"labels": ["Node", "Name", "Net"],
+                         "props": {"db_id": 2, "addr": "10.0.0.1", "port": 80}}}"#,
+        ).unwrap();
+        assert_eq!(r.kind, RelKind::Named);
+        assert_eq!((r.src, r.dst), (ID::new(1), ID::new(2)));
+        assert_eq!(r.node.kind, NodeKind::Name);
+        assert_eq!(r.node.ty, "net");
+        assert!(NodeRecord::from_json_str(r#"{"labels": []}"#).is_none());
+    }
+}
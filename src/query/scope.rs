@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use data::{
+    node_types::{Name, Node},
+    rel_types::Rel,
+    HasID, ID,
+};
+use graph::{Direction, Graph};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeWindow {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl TimeWindow {
+    pub fn new(start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Self {
+        TimeWindow { start, end }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    pub fn contains(&self, time: Option<DateTime<Utc>>) -> bool {
+        if self.is_unbounded() {
+            return true;
+        }
+        match time {
+            Some(t) => self.start.map_or(true, |s| t >= s) && self.end.map_or(true, |e| t <= e),
+            None => false,
+        }
+    }
+
+    pub fn overlaps(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        let after_start = match (self.start, to) {
+            (Some(s), Some(t)) => t >= s,
+            _ => true,
+        };
+        let before_end = match (self.end, from) {
+            (Some(e), Some(f)) => f <= e,
+            _ => true,
+        };
+        after_start && before_end
+    }
+}
+
+fn named_until(g: &Graph, end: ID) -> Option<DateTime<Utc>> {
+    if end == ID::new(0) {
+        None
+    } else {
+        g.ctx_time(end)
+    }
+}
+
+pub fn rel_in(g: &Graph, rel: &Rel, w: &TimeWindow) -> bool {
+    match rel {
+        Rel::Inf(i) => w.contains(g.ctx_time(i.ctx)),
+        Rel::Named(n) => w.overlaps(g.ctx_time(n.start), named_until(g, n.end)),
+    }
+}
+
+pub fn neighbours<'a>(
+    g: &'a Graph,
+    id: ID,
+    dir: Direction,
+    w: &TimeWindow,
+) -> Vec<(&'a Rel, &'a Node)> {
+    g.neighbours(id, dir)
+        .into_iter()
+        .filter(|(rel, _)| rel_in(g, rel, w))
+        .collect()
+}
+
+pub fn by_name<'a>(g: &'a Graph, name: &Name, w: &TimeWindow) -> Vec<&'a Node> {
+    let mut ret: Vec<&Node> = Vec::new();
+    if let Some(id) = g.name_node(name) {
+        for (_, node) in neighbours(g, id, Direction::In, w) {
+            if ret.iter().all(|n| n.get_db_id() != node.get_db_id()) {
+                ret.push(node);
+            }
+        }
+    }
+    ret
+}
+
+// Keeps the versions, given in order with their creation times, that were
+// current at some point in the window. Each version lasts until the next one
+// is created.
+pub fn versions_in<T>(versions: Vec<(T, Option<DateTime<Utc>>)>, w: &TimeWindow) -> Vec<T> {
+    let created: Vec<_> = versions.iter().map(|(_, t)| *t).collect();
+    versions
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| w.overlaps(created[*i], created.get(i + 1).cloned().unwrap_or(None)))
+        .map(|(_, (v, _))| v)
+        .collect()
+}
+
+pub fn by_uuid<'a>(g: &'a Graph, uuid: Uuid, w: &TimeWindow) -> Vec<&'a Node> {
+    let versions = g.by_uuid(uuid);
+    if w.is_unbounded() {
+        return versions;
+    }
+    let versions = versions
+        .into_iter()
+        .map(|n| match n {
+            Node::Data(d) => (n, g.ctx_time(d.ctx())),
+            _ => (n, None),
+        })
+        .collect();
+    versions_in(versions, w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    use data::{
+        node_types::{ConcreteType, ContextType, CtxNode, DataNode, PVMDataType},
+        ID,
+    };
+    use views::DBTr;
+
+    lazy_static! {
+        static ref FILE: ConcreteType = ConcreteType {
+            pvm_ty: PVMDataType::Store,
+            name: "file",
+            props: HashMap::new(),
+        };
+        static ref CTX: ContextType = ContextType {
+            name: "test_context",
+            props: vec!["time"],
+        };
+    }
+
+    fn time(secs: i64) -> Option<DateTime<Utc>> {
+        Some(Utc.timestamp(secs, 0))
+    }
+
+    #[test]
+    fn contains() {
+        let w = TimeWindow::new(time(10), time(20));
+        assert!(w.contains(time(10)));
+        assert!(w.contains(time(20)));
+        assert!(!w.contains(time(21)));
+        assert!(!w.contains(None));
+        assert!(TimeWindow::default().contains(None));
+        assert!(TimeWindow::new(None, time(5)).contains(time(1)));
+    }
+
+    #[test]
+    fn overlaps() {
+        let w = TimeWindow::new(time(10), time(20));
+        assert!(w.overlaps(time(5), time(10)));
+        assert!(w.overlaps(time(15), None));
+        assert!(w.overlaps(None, None));
+        assert!(!w.overlaps(time(1), time(9)));
+        assert!(!w.overlaps(time(21), None));
+    }
+
+    // Three versions of one file, version n created at time 10 * n.
+    fn versions(uuid: Uuid) -> Graph {
+        let mut g = Graph::new();
+        for n in 1..4 {
+            g.apply(&DBTr::CreateNode(Node::Ctx(
+                CtxNode::new(
+                    ID::new(100 + n),
+                    &CTX,
+                    hashmap!("time" => Utc.timestamp(10 * n as i64, 0).to_rfc3339()),
+                ).unwrap(),
+            )));
+            g.apply(&DBTr::CreateNode(Node::Data(DataNode::new(
+                PVMDataType::Store,
+                &FILE,
+                ID::new(n),
+                uuid,
+                ID::new(100 + n),
+                None,
+            ))));
+        }
+        g
+    }
+
+    fn version_ids(g: &Graph, uuid: Uuid, w: TimeWindow) -> Vec<u64> {
+        by_uuid(g, uuid, &w)
+            .into_iter()
+            .map(|n| n.get_db_id().inner())
+            .collect()
+    }
+
+    #[test]
+    fn by_uuid_versions() {
+        let uuid = Uuid::from_bytes(&[1; 16]).unwrap();
+        let g = versions(uuid);
+        assert_eq!(version_ids(&g, uuid, TimeWindow::default()), vec![1, 2, 3]);
+        assert_eq!(version_ids(&g, uuid, TimeWindow::new(time(12), time(18))), vec![1]);
+        assert_eq!(version_ids(&g, uuid, TimeWindow::new(time(15), time(20))), vec![1, 2]);
+        assert_eq!(version_ids(&g, uuid, TimeWindow::new(time(25), None)), vec![2, 3]);
+        assert_eq!(version_ids(&g, uuid, TimeWindow::new(time(100), None)), vec![3]);
+        assert_eq!(version_ids(&g, uuid, TimeWindow::new(None, time(5))), Vec::<u64>::new());
+        assert_eq!(
+            version_ids(&g, Uuid::from_bytes(&[2; 16]).unwrap(), TimeWindow::default()),
+            Vec::<u64>::new()
+        );
+    }
+
+    #[test]
+    fn versions_in_keeps_unknown_times() {
+        let w = TimeWindow::new(time(10), time(20));
+        assert_eq!(versions_in(vec![(1, None), (2, time(30))], &w), vec![1]);
+        assert_eq!(versions_in(vec![(1, time(5)), (2, None)], &w), vec![1, 2]);
+    }
+}
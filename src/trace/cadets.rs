@@ -1,5 +1,6 @@
 use chrono::{serde::ts_nanoseconds, DateTime, Utc};
-use std::fmt;
+use serde_json;
+use std::{convert::TryFrom, fmt};
 use uuid::Uuid;
 
 use data::{
@@ -15,7 +16,7 @@ use ingest::{
 use super::MapFmt;
 
 lazy_static! {
-    static ref PROCESS: ConcreteType = ConcreteType {
+    pub static ref PROCESS: ConcreteType = ConcreteType {
         pvm_ty: Actor,
         name: "process",
         props: hashmap!("euid" => true,
@@ -25,22 +26,31 @@ lazy_static! {
                         "rgid" => true,
                         "sgid" => true,
                         "pid" => false,
+                        "ppid" => false,
                         "cmdline" => true,
-                        "login_name" => true),
+                        "login_name" => true,
+                        "exit_status" => false,
+                        "exit_time" => false),
     };
-    static ref FILE: ConcreteType = ConcreteType {
+    static ref THREAD: ConcreteType = ConcreteType {
+        pvm_ty: Actor,
+        name: "thread",
+        props: hashmap!("tid" => false,
+                        "exit_time" => false),
+    };
+    pub static ref FILE: ConcreteType = ConcreteType {
         pvm_ty: Store,
         name: "file",
         props: hashmap!("owner_uid" => true,
                         "owner_gid" => true,
                         "mode" => true),
     };
-    static ref SOCKET: ConcreteType = ConcreteType {
+    pub static ref SOCKET: ConcreteType = ConcreteType {
         pvm_ty: Conduit,
         name: "socket",
         props: hashmap!(),
     };
-    static ref PIPE: ConcreteType = ConcreteType {
+    pub static ref PIPE: ConcreteType = ConcreteType {
         pvm_ty: Conduit,
         name: "pipe",
         props: hashmap!(),
@@ -52,6 +62,13 @@ lazy_static! {
                         "owner_gid" => true,
                         "mode" => true),
     };
+    static ref SHM: ConcreteType = ConcreteType {
+        pvm_ty: Store,
+        name: "shm",
+        props: hashmap!("owner_uid" => true,
+                        "owner_gid" => true,
+                        "mode" => true),
+    };
     static ref CTX: ContextType = ContextType {
         name: "cadets_context",
         props: vec!["time", "event", "host"],
@@ -177,19 +194,19 @@ impl AuditEvent {
         let binuuid = field!(self.arg_objuuid1);
         let binname = clone_field!(self.upath1);
 
-        let bin = pvm.declare(&FILE, binuuid, None);
-        pvm.name(bin, Name::Path(binname));
+        let bin = pvm.declare(&FILE, binuuid, None)?;
+        pvm.name(bin, Name::Path(binname))?;
 
-        pvm.meta(pro, "cmdline", cmdline);
-        pvm.source(pro, bin);
+        pvm.meta(pro, "cmdline", cmdline)?;
+        pvm.source(pro, bin)?;
 
         if let Some(lduuid) = self.arg_objuuid2 {
             let ldname = clone_field!(self.upath2);
 
-            let ld = pvm.declare(&FILE, lduuid, None);
-            pvm.name(ld, Name::Path(ldname));
+            let ld = pvm.declare(&FILE, lduuid, None)?;
+            pvm.name(ld, Name::Path(ldname))?;
 
-            pvm.source(pro, ld);
+            pvm.source(pro, ld)?;
         }
 
         Ok(())
@@ -198,24 +215,72 @@ impl AuditEvent {
     fn posix_fork(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let ret_objuuid1 = field!(self.ret_objuuid1);
 
-        let ch = pvm.derive(pro, ret_objuuid1);
+        let ch = pvm.derive(pro, ret_objuuid1)?;
+        pvm.register_pid(field!(self.host), self.retval, ret_objuuid1);
 
-        pvm.meta(ch, "pid", &self.retval);
-        pvm.source(ch, pro);
+        pvm.meta(ch, "pid", &self.retval)?;
+        pvm.meta(ch, "ppid", &self.pid)?;
+        pvm.source(ch, pro)?;
         Ok(())
     }
 
-    fn posix_exit(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+    fn posix_exit(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let host = field!(self.host);
+        let exit_time = self.time.to_rfc3339();
+        pvm.meta(pro, "exit_status", &self.retval)?;
+        pvm.meta(pro, "exit_time", &exit_time)?;
+        if self.subjthruuid != self.subjprocuuid {
+            let thr = pvm.declare(&THREAD, self.subjthruuid, None)?;
+            pvm.meta(thr, "exit_time", &exit_time)?;
+            pvm.release(&self.subjthruuid);
+        }
+        pvm.release_pid(host, self.pid, &self.subjprocuuid);
         pvm.release(&self.subjprocuuid);
         Ok(())
     }
 
+    fn posix_thr_exit(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let thr = pvm.declare(&THREAD, self.subjthruuid, None)?;
+        pvm.meta(thr, "exit_time", &self.time.to_rfc3339())?;
+        pvm.release(&self.subjthruuid);
+        Ok(())
+    }
+
+    fn process(&self, host: Uuid, pvm: &mut PVM) -> Result<ID, PVMError> {
+        let new = !pvm.known(&self.subjprocuuid);
+        let pro = pvm.declare(
+            &PROCESS,
+            self.subjprocuuid,
+            Some(hashmap!("cmdline" => self.exec.clone(),
+                         "pid" => self.pid.to_string(),
+                         "ppid" => self.ppid.to_string())),
+        )?;
+        if new {
+            pvm.register_pid(host, self.pid, self.subjprocuuid);
+            if let Some(puuid) = pvm.pid_owner(host, self.ppid) {
+                if puuid != self.subjprocuuid {
+                    let par = pvm.declare(&PROCESS, puuid, None)?;
+                    pvm.source(pro, par)?;
+                }
+            }
+        }
+        if self.subjthruuid != self.subjprocuuid {
+            let thr = pvm.declare(
+                &THREAD,
+                self.subjthruuid,
+                Some(hashmap!("tid" => self.tid.to_string())),
+            )?;
+            pvm.source(thr, pro)?;
+        }
+        Ok(pro)
+    }
+
     fn posix_open(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         if let Some(fuuid) = self.ret_objuuid1 {
             let fname = clone_field!(self.upath1);
 
-            let f = pvm.declare(&FILE, fuuid, None);
-            pvm.name(f, Name::Path(fname));
+            let f = pvm.declare(&FILE, fuuid, None)?;
+            pvm.name(f, Name::Path(fname))?;
         }
         Ok(())
     }
@@ -223,91 +288,103 @@ impl AuditEvent {
     fn posix_read(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let fuuid = field!(self.arg_objuuid1);
 
-        let f = pvm.declare(&FILE, fuuid, None);
+        let f = pvm.declare(&FILE, fuuid, None)?;
         if let Some(pth) = self.fdpath.clone() {
             if pth != "<unknown>" {
-                pvm.name(f, Name::Path(pth));
+                pvm.name(f, Name::Path(pth))?;
             }
         }
-        pvm.source_nbytes(pro, f, self.retval);
+        pvm.source_nbytes(pro, f, self.retval)?;
         Ok(())
     }
 
     fn posix_write(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let fuuid = field!(self.arg_objuuid1);
 
-        let f = pvm.declare(&FILE, fuuid, None);
+        let f = pvm.declare(&FILE, fuuid, None)?;
         if let Some(pth) = self.fdpath.clone() {
             if pth != "<unknown>" {
-                pvm.name(f, Name::Path(pth));
+                pvm.name(f, Name::Path(pth))?;
             }
         }
-        pvm.sinkstart_nbytes(pro, f, self.retval);
+        pvm.sinkstart_nbytes(pro, f, self.retval)?;
         Ok(())
     }
 
     fn posix_close(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         if let Some(fuuid) = self.arg_objuuid1 {
-            let f = pvm.declare(&FILE, fuuid, None);
-            pvm.sinkend(pro, f);
+            let f = pvm.declare(&FILE, fuuid, None)?;
+            pvm.sinkend(pro, f)?;
         }
         Ok(())
     }
 
     fn posix_socket(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.ret_objuuid1);
-        pvm.declare(&SOCKET, suuid, None);
+        pvm.declare(&SOCKET, suuid, None)?;
         Ok(())
     }
 
     fn posix_listen(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        pvm.declare(&SOCKET, suuid, None);
+        pvm.declare(&SOCKET, suuid, None)?;
         Ok(())
     }
 
     fn posix_bind(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        let s = pvm.declare(&SOCKET, suuid, None);
-        pvm.name(s, self.sock_name()?);
+        let host = field!(self.host);
+        let s = pvm.declare(&SOCKET, suuid, None)?;
+        let name = self.sock_name()?;
+        if let Name::Net(ref addr, port) = name {
+            pvm.bind_endpoint(s, host, (addr.clone(), port))?;
+        }
+        pvm.name(s, name)?;
         Ok(())
     }
 
     fn posix_accept(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let luuid = field!(self.arg_objuuid1);
         let ruuid = field!(self.ret_objuuid1);
-        pvm.declare(&SOCKET, luuid, None);
-        let r = pvm.declare(&SOCKET, ruuid, None);
-        pvm.name(r, self.sock_name()?);
+        let host = field!(self.host);
+        let l = pvm.declare(&SOCKET, luuid, None)?;
+        let r = pvm.declare(&SOCKET, ruuid, None)?;
+        pvm.name(r, self.sock_name()?)?;
+        pvm.accept_endpoint(l, r, host)?;
         Ok(())
     }
 
     fn posix_connect(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        let s = pvm.declare(&SOCKET, suuid, None);
-        pvm.name(s, self.sock_name()?);
+        let host = field!(self.host);
+        let s = pvm.declare(&SOCKET, suuid, None)?;
+        let name = self.sock_name()?;
+        if let Name::Net(ref addr, port) = name {
+            pvm.connect_endpoint(s, host, (addr.clone(), port))?;
+        }
+        pvm.name(s, name)?;
         Ok(())
     }
 
     fn posix_mmap(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let fuuid = field!(self.arg_objuuid1);
-        let f = pvm.declare(&FILE, fuuid, None);
+        let f = pvm.declare(&FILE, fuuid, None)?;
         if let Some(fdpath) = self.fdpath.clone() {
-            pvm.name(f, Name::Path(fdpath));
+            pvm.name(f, Name::Path(fdpath))?;
         }
         if let Some(ref flags) = self.arg_mem_flags {
             if flags.contains(&String::from("PROT_WRITE")) {
                 if let Some(ref share_flags) = self.arg_sharing_flags {
                     if !share_flags.contains(&String::from("MAP_PRIVATE")) {
-                        pvm.sinkstart(pro, f);
+                        pvm.sinkstart(pro, f)?;
                     }
                 } else {
-                    pvm.sinkstart(pro, f);
+                    pvm.sinkstart(pro, f)?;
                 }
             }
 
             if flags.contains(&String::from("PROT_READ")) {
-                pvm.source(pro, f);
+                pvm.source(pro, f)?;
             }
         }
         Ok(())
@@ -316,66 +393,66 @@ impl AuditEvent {
     fn posix_socketpair(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let ruuid1 = field!(self.ret_objuuid1);
         let ruuid2 = field!(self.ret_objuuid2);
-        let s1 = pvm.declare(&SOCKET, ruuid1, None);
-        let s2 = pvm.declare(&SOCKET, ruuid2, None);
-        pvm.connect(s1, s2, ConnectDir::BiDirectional);
+        let s1 = pvm.declare(&SOCKET, ruuid1, None)?;
+        let s2 = pvm.declare(&SOCKET, ruuid2, None)?;
+        pvm.connect(s1, s2, ConnectDir::BiDirectional)?;
         Ok(())
     }
 
     fn posix_pipe(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let ruuid1 = field!(self.ret_objuuid1);
         let ruuid2 = field!(self.ret_objuuid2);
-        let p1 = pvm.declare(&PIPE, ruuid1, None);
-        let p2 = pvm.declare(&PIPE, ruuid2, None);
-        pvm.connect(p1, p2, ConnectDir::BiDirectional);
+        let p1 = pvm.declare(&PIPE, ruuid1, None)?;
+        let p2 = pvm.declare(&PIPE, ruuid2, None)?;
+        pvm.connect(p1, p2, ConnectDir::BiDirectional)?;
         Ok(())
     }
 
     fn posix_sendmsg(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        let s = pvm.declare(&SOCKET, suuid, None);
+        let s = pvm.declare(&SOCKET, suuid, None)?;
         if let Some(n) = self.opt_sock_name()? {
-            pvm.name(s, n);
+            pvm.name(s, n)?;
         }
-        pvm.sinkstart_nbytes(pro, s, self.retval);
+        pvm.sinkstart_nbytes(pro, s, self.retval)?;
         Ok(())
     }
 
     fn posix_sendto(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        let s = pvm.declare(&SOCKET, suuid, None);
+        let s = pvm.declare(&SOCKET, suuid, None)?;
         if let Some(n) = self.opt_sock_name()? {
-            pvm.name(s, n);
+            pvm.name(s, n)?;
         }
-        pvm.sinkstart_nbytes(pro, s, self.retval);
+        pvm.sinkstart_nbytes(pro, s, self.retval)?;
         Ok(())
     }
 
     fn posix_recvmsg(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        let s = pvm.declare(&SOCKET, suuid, None);
+        let s = pvm.declare(&SOCKET, suuid, None)?;
         if let Some(n) = self.opt_sock_name()? {
-            pvm.name(s, n);
+            pvm.name(s, n)?;
         }
-        pvm.source_nbytes(pro, s, self.retval);
+        pvm.source_nbytes(pro, s, self.retval)?;
         Ok(())
     }
 
     fn posix_recvfrom(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let suuid = field!(self.arg_objuuid1);
-        let s = pvm.declare(&SOCKET, suuid, None);
+        let s = pvm.declare(&SOCKET, suuid, None)?;
         if let Some(n) = self.opt_sock_name()? {
-            pvm.name(s, n);
+            pvm.name(s, n)?;
         }
-        pvm.source_nbytes(pro, s, self.retval);
+        pvm.source_nbytes(pro, s, self.retval)?;
         Ok(())
     }
 
     fn posix_chdir(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let duuid = field!(self.arg_objuuid1);
-        let d = pvm.declare(&FILE, duuid, None);
+        let d = pvm.declare(&FILE, duuid, None)?;
         if let Some(dpath) = self.upath1.clone() {
-            pvm.name(d, Name::Path(dpath));
+            pvm.name(d, Name::Path(dpath))?;
         }
         Ok(())
     }
@@ -384,10 +461,10 @@ impl AuditEvent {
         let fuuid = field!(self.arg_objuuid1);
         let fpath = clone_field!(self.upath1);
         let mode = field!(self.mode);
-        let f = pvm.declare(&FILE, fuuid, None);
-        pvm.meta(f, "mode", &format!("{:o}", mode));
-        pvm.name(f, Name::Path(fpath));
-        pvm.sink(pro, f);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.meta(f, "mode", &format!("{:o}", mode))?;
+        pvm.name(f, Name::Path(fpath))?;
+        pvm.sink(pro, f)?;
         Ok(())
     }
 
@@ -396,20 +473,20 @@ impl AuditEvent {
         let fpath = clone_field!(self.upath1);
         let arg_uid = field!(self.arg_uid);
         let arg_gid = field!(self.arg_gid);
-        let f = pvm.declare(&FILE, fuuid, None);
-        pvm.meta(f, "owner_uid", &arg_uid);
-        pvm.meta(f, "owner_gid", &arg_gid);
-        pvm.name(f, Name::Path(fpath));
-        pvm.sink(pro, f);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.meta(f, "owner_uid", &arg_uid)?;
+        pvm.meta(f, "owner_gid", &arg_gid)?;
+        pvm.name(f, Name::Path(fpath))?;
+        pvm.sink(pro, f)?;
         Ok(())
     }
 
     fn posix_fchmod(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let fuuid = field!(self.arg_objuuid1);
         let mode = field!(self.mode);
-        let f = pvm.declare(&FILE, fuuid, None);
-        pvm.meta(f, "mode", &format!("{:o}", mode));
-        pvm.sinkstart(pro, f);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.meta(f, "mode", &format!("{:o}", mode))?;
+        pvm.sinkstart(pro, f)?;
         Ok(())
     }
 
@@ -417,16 +494,16 @@ impl AuditEvent {
         let fuuid = field!(self.arg_objuuid1);
         let arg_uid = field!(self.arg_uid);
         let arg_gid = field!(self.arg_gid);
-        let f = pvm.declare(&FILE, fuuid, None);
-        pvm.meta(f, "owner_uid", &arg_uid);
-        pvm.meta(f, "owner_gid", &arg_gid);
-        pvm.sinkstart(pro, f);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.meta(f, "owner_uid", &arg_uid)?;
+        pvm.meta(f, "owner_gid", &arg_gid)?;
+        pvm.sinkstart(pro, f)?;
         Ok(())
     }
 
     fn posix_posix_openpt(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let ttyuuid = field!(self.ret_objuuid1);
-        pvm.declare(&PTTY, ttyuuid, None);
+        pvm.declare(&PTTY, ttyuuid, None)?;
         Ok(())
     }
 
@@ -434,9 +511,9 @@ impl AuditEvent {
         let fuuid = field!(self.arg_objuuid1);
         let upath1 = clone_field!(self.upath1);
         let upath2 = clone_field!(self.upath2);
-        let f = pvm.declare(&FILE, fuuid, None);
-        pvm.name(f, Name::Path(upath1));
-        pvm.name(f, Name::Path(upath2));
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.name(f, Name::Path(upath1))?;
+        pvm.name(f, Name::Path(upath2))?;
         Ok(())
     }
 
@@ -444,35 +521,35 @@ impl AuditEvent {
         let src_uuid = field!(self.arg_objuuid1);
         let src = clone_field!(self.upath1);
         let dst = clone_field!(self.upath2);
-        let fsrc = pvm.declare(&FILE, src_uuid, None);
-        pvm.unname(fsrc, Name::Path(src));
+        let fsrc = pvm.declare(&FILE, src_uuid, None)?;
+        pvm.unname(fsrc, Name::Path(src))?;
         if let Some(ovr_uuid) = self.arg_objuuid2 {
-            let fovr = pvm.declare(&FILE, ovr_uuid, None);
-            pvm.unname(fovr, Name::Path(dst.clone()));
+            let fovr = pvm.declare(&FILE, ovr_uuid, None)?;
+            pvm.unname(fovr, Name::Path(dst.clone()))?;
         }
-        pvm.name(fsrc, Name::Path(dst));
+        pvm.name(fsrc, Name::Path(dst))?;
         Ok(())
     }
 
     fn posix_unlink(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let fuuid = field!(self.arg_objuuid1);
         let upath1 = clone_field!(self.upath1);
-        let f = pvm.declare(&FILE, fuuid, None);
-        pvm.unname(f, Name::Path(upath1));
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.unname(f, Name::Path(upath1))?;
         Ok(())
     }
 
     fn posix_setuid(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let uid = ref_field!(self.arg_uid);
-        pvm.meta(pro, "euid", uid);
-        pvm.meta(pro, "ruid", uid);
-        pvm.meta(pro, "suid", uid);
+        pvm.meta(pro, "euid", uid)?;
+        pvm.meta(pro, "ruid", uid)?;
+        pvm.meta(pro, "suid", uid)?;
         Ok(())
     }
 
     fn posix_seteuid(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let euid = ref_field!(self.arg_euid);
-        pvm.meta(pro, "euid", euid);
+        pvm.meta(pro, "euid", euid)?;
         Ok(())
     }
 
@@ -480,10 +557,10 @@ impl AuditEvent {
         let ruid = ref_field!(self.arg_ruid);
         let euid = ref_field!(self.arg_euid);
         if *ruid != -1 {
-            pvm.meta(pro, "ruid", ruid);
+            pvm.meta(pro, "ruid", ruid)?;
         }
         if *euid != -1 {
-            pvm.meta(pro, "euid", euid);
+            pvm.meta(pro, "euid", euid)?;
         }
         Ok(())
     }
@@ -493,28 +570,28 @@ impl AuditEvent {
         let euid = ref_field!(self.arg_euid);
         let suid = ref_field!(self.arg_suid);
         if *ruid != -1 {
-            pvm.meta(pro, "ruid", ruid);
+            pvm.meta(pro, "ruid", ruid)?;
         }
         if *euid != -1 {
-            pvm.meta(pro, "euid", euid);
+            pvm.meta(pro, "euid", euid)?;
         }
         if *suid != -1 {
-            pvm.meta(pro, "suid", suid);
+            pvm.meta(pro, "suid", suid)?;
         }
         Ok(())
     }
 
     fn posix_setgid(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let gid = ref_field!(self.arg_gid);
-        pvm.meta(pro, "egid", gid);
-        pvm.meta(pro, "rgid", gid);
-        pvm.meta(pro, "sgid", gid);
+        pvm.meta(pro, "egid", gid)?;
+        pvm.meta(pro, "rgid", gid)?;
+        pvm.meta(pro, "sgid", gid)?;
         Ok(())
     }
 
     fn posix_setegid(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let egid = ref_field!(self.arg_egid);
-        pvm.meta(pro, "egid", egid);
+        pvm.meta(pro, "egid", egid)?;
         Ok(())
     }
 
@@ -522,10 +599,10 @@ impl AuditEvent {
         let rgid = ref_field!(self.arg_rgid);
         let egid = ref_field!(self.arg_egid);
         if *rgid != -1 {
-            pvm.meta(pro, "rgid", rgid);
+            pvm.meta(pro, "rgid", rgid)?;
         }
         if *egid != -1 {
-            pvm.meta(pro, "egid", egid);
+            pvm.meta(pro, "egid", egid)?;
         }
         Ok(())
     }
@@ -535,38 +612,139 @@ impl AuditEvent {
         let egid = ref_field!(self.arg_egid);
         let sgid = ref_field!(self.arg_sgid);
         if *rgid != -1 {
-            pvm.meta(pro, "rgid", rgid);
+            pvm.meta(pro, "rgid", rgid)?;
         }
         if *egid != -1 {
-            pvm.meta(pro, "egid", egid);
+            pvm.meta(pro, "egid", egid)?;
         }
         if *sgid != -1 {
-            pvm.meta(pro, "sgid", sgid);
+            pvm.meta(pro, "sgid", sgid)?;
         }
         Ok(())
     }
 
     fn posix_setlogin(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
         let login = ref_field!(self.login);
-        pvm.meta(pro, "login_name", login);
+        pvm.meta(pro, "login_name", login)?;
+        Ok(())
+    }
+
+    fn posix_mkdir(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let duuid = field!(self.ret_objuuid1);
+        let dpath = clone_field!(self.upath1);
+        let d = pvm.declare(&FILE, duuid, None)?;
+        pvm.name(d, Name::Path(dpath))?;
+        Ok(())
+    }
+
+    fn posix_symlink(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let luuid = field!(self.ret_objuuid1);
+        let lpath = clone_field!(self.upath2);
+        let l = pvm.declare(&FILE, luuid, None)?;
+        pvm.name(l, Name::Path(lpath))?;
+        pvm.sink(pro, l)?;
+        Ok(())
+    }
+
+    fn posix_truncate(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fuuid = field!(self.arg_objuuid1);
+        let fpath = clone_field!(self.upath1);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.name(f, Name::Path(fpath))?;
+        pvm.sink(pro, f)?;
+        Ok(())
+    }
+
+    fn posix_ftruncate(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fuuid = field!(self.arg_objuuid1);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.sinkstart(pro, f)?;
+        Ok(())
+    }
+
+    fn posix_sendfile(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fuuid = field!(self.arg_objuuid1);
+        let suuid = field!(self.arg_objuuid2);
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        if let Some(pth) = self.fdpath.clone() {
+            if pth != "<unknown>" {
+                pvm.name(f, Name::Path(pth))?;
+            }
+        }
+        let s = pvm.declare(&SOCKET, suuid, None)?;
+        pvm.source(pro, f)?;
+        pvm.sinkstart(pro, s)?;
+        Ok(())
+    }
+
+    fn posix_kill(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(tuuid) = self.arg_objuuid1 {
+            let t = pvm.declare(&PROCESS, tuuid, None)?;
+            pvm.sink(pro, t)?;
+        }
+        Ok(())
+    }
+
+    fn posix_ptrace(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(tuuid) = self.arg_objuuid1 {
+            let t = pvm.declare(&PROCESS, tuuid, None)?;
+            pvm.sink(pro, t)?;
+            pvm.source(pro, t)?;
+        }
+        Ok(())
+    }
+
+    fn posix_shm_open(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let suuid = field!(self.ret_objuuid1);
+        let s = pvm.declare(&SHM, suuid, None)?;
+        if let Some(pth) = self.upath1.clone() {
+            pvm.name(s, Name::Path(pth))?;
+        }
+        Ok(())
+    }
+
+    fn posix_shm_unlink(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let suuid = field!(self.arg_objuuid1);
+        let spath = clone_field!(self.upath1);
+        let s = pvm.declare(&SHM, suuid, None)?;
+        pvm.unname(s, Name::Path(spath))?;
+        Ok(())
+    }
+
+    fn posix_shmat(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let suuid = field!(self.arg_objuuid1);
+        let s = pvm.declare(&SHM, suuid, None)?;
+        pvm.source(pro, s)?;
+        pvm.sinkstart(pro, s)?;
+        Ok(())
+    }
+
+    fn posix_shmdt(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(suuid) = self.arg_objuuid1 {
+            let s = pvm.declare(&SHM, suuid, None)?;
+            pvm.sinkend(pro, s)?;
+        }
+        Ok(())
+    }
+
+    fn posix_shmctl(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(suuid) = self.arg_objuuid1 {
+            pvm.declare(&SHM, suuid, None)?;
+        }
         Ok(())
     }
 
     fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        let host = field!(self.host);
         pvm.new_ctx(
             &CTX,
             hashmap!(
                 "event" => self.event.clone(),
-                "host" => self.host.unwrap().hyphenated().to_string(),
+                "host" => host.hyphenated().to_string(),
                 "time" => self.time.to_rfc3339(),
             ),
-        );
-        let pro = pvm.declare(
-            &PROCESS,
-            self.subjprocuuid,
-            Some(hashmap!("cmdline" => self.exec.clone(),
-                         "pid" => self.pid.to_string())),
-        );
+        )?;
+        let pro = self.process(host, pvm)?;
         match &self.event[..] {
             "audit:event:aue_accept:" => self.posix_accept(pro, pvm),
             "audit:event:aue_bind:" => self.posix_bind(pro, pvm),
@@ -585,15 +763,20 @@ impl AuditEvent {
             "audit:event:aue_link:" => self.posix_link(pro, pvm),
             "audit:event:aue_listen:" => self.posix_listen(pro, pvm),
             "audit:event:aue_mmap:" => self.posix_mmap(pro, pvm),
-            "audit:event:aue_open_rwtc:" | "audit:event:aue_openat_rwtc:" => {
-                self.posix_open(pro, pvm)
-            }
+            "audit:event:aue_mkdir:" | "audit:event:aue_mkdirat:" => self.posix_mkdir(pro, pvm),
             "audit:event:aue_pipe:" => self.posix_pipe(pro, pvm),
+            "audit:event:aue_ptrace:" => self.posix_ptrace(pro, pvm),
+            "audit:event:aue_kill:" => self.posix_kill(pro, pvm),
             "audit:event:aue_posix_openpt:" => self.posix_posix_openpt(pro, pvm),
-            "audit:event:aue_read:" | "audit:event:aue_pread:" => self.posix_read(pro, pvm),
+            "audit:event:aue_read:"
+            | "audit:event:aue_pread:"
+            | "audit:event:aue_readv:"
+            | "audit:event:aue_preadv:" => self.posix_read(pro, pvm),
             "audit:event:aue_recvmsg:" => self.posix_recvmsg(pro, pvm),
             "audit:event:aue_recvfrom:" => self.posix_recvfrom(pro, pvm),
-            "audit:event:aue_rename:" => self.posix_rename(pro, pvm),
+            "audit:event:aue_rename:" | "audit:event:aue_renameat:" => self.posix_rename(pro, pvm),
+            "audit:event:aue_rmdir:" => self.posix_unlink(pro, pvm),
+            "audit:event:aue_sendfile:" => self.posix_sendfile(pro, pvm),
             "audit:event:aue_sendmsg:" => self.posix_sendmsg(pro, pvm),
             "audit:event:aue_sendto:" => self.posix_sendto(pro, pvm),
             "audit:event:aue_setegid:" => self.posix_setegid(pro, pvm),
@@ -605,15 +788,30 @@ impl AuditEvent {
             "audit:event:aue_setresuid:" => self.posix_setresuid(pro, pvm),
             "audit:event:aue_setreuid:" => self.posix_setreuid(pro, pvm),
             "audit:event:aue_setuid:" => self.posix_setuid(pro, pvm),
+            "audit:event:aue_shmat:" => self.posix_shmat(pro, pvm),
+            "audit:event:aue_shmctl:" => self.posix_shmctl(pro, pvm),
+            "audit:event:aue_shmdt:" => self.posix_shmdt(pro, pvm),
+            "audit:event:aue_shmget:" | "audit:event:aue_shmopen:" => self.posix_shm_open(pro, pvm),
+            "audit:event:aue_shmunlink:" => self.posix_shm_unlink(pro, pvm),
             "audit:event:aue_socket:" => self.posix_socket(pro, pvm),
             "audit:event:aue_socketpair:" => self.posix_socketpair(pro, pvm),
-            "audit:event:aue_unlink:" => self.posix_unlink(pro, pvm),
-            "audit:event:aue_write:" | "audit:event:aue_pwrite:" | "audit:event:aue_writev:" => {
-                self.posix_write(pro, pvm)
+            "audit:event:aue_symlink:" | "audit:event:aue_symlinkat:" => {
+                self.posix_symlink(pro, pvm)
             }
-            "audit:event:aue_dup2:" => Ok(()), /* IGNORE */
+            "audit:event:aue_thr_exit:" => self.posix_thr_exit(pro, pvm),
+            "audit:event:aue_truncate:" => self.posix_truncate(pro, pvm),
+            "audit:event:aue_ftruncate:" => self.posix_ftruncate(pro, pvm),
+            "audit:event:aue_unlink:" | "audit:event:aue_unlinkat:" => self.posix_unlink(pro, pvm),
+            "audit:event:aue_write:"
+            | "audit:event:aue_pwrite:"
+            | "audit:event:aue_writev:"
+            | "audit:event:aue_pwritev:" => self.posix_write(pro, pvm),
+            // The new descriptor refers to the same object uuid, and the event
+            // does not say what kind of object that is.
+            "audit:event:aue_dup:" | "audit:event:aue_dup2:" | "audit:event:aue_fcntl:" => Ok(()),
+            evt if evt.starts_with("audit:event:aue_open") => self.posix_open(pro, pvm),
             _ => {
-                pvm.unparsed_events.insert(self.event.clone());
+                pvm.unparsed_event(&self.event);
                 Ok(())
             }
         }
@@ -651,6 +849,36 @@ impl fmt::Display for FBTEvent {
     }
 }
 
+impl FBTEvent {
+    fn port(&self, field: &'static str, port: i32) -> Result<u16, PVMError> {
+        u16::try_from(port).map_err(|_| PVMError::InvalidField {
+            evt: self.event.clone(),
+            field,
+            value: port.to_string(),
+        })
+    }
+
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        pvm.new_ctx(
+            &CTX,
+            hashmap!(
+                "event" => self.event.clone(),
+                "host" => self.host.hyphenated().to_string(),
+                "time" => self.time.to_rfc3339(),
+            ),
+        )?;
+        let lport = self.port("lport", self.lport)?;
+        let fport = self.port("fport", self.fport)?;
+        let s = pvm.declare(&SOCKET, self.so_uuid, None)?;
+        pvm.endpoints(
+            s,
+            self.host,
+            (self.laddr.clone(), lport),
+            (self.faddr.clone(), fport),
+        )
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 pub enum TraceEvent {
@@ -675,20 +903,380 @@ impl fmt::Display for TraceEvent {
     }
 }
 
+#[derive(Deserialize)]
+struct Stamp {
+    time: Option<i64>,
+}
+
 impl Parseable for TraceEvent {
     fn init(pvm: &mut PVM) {
         pvm.register_data_type(&PROCESS);
+        pvm.register_data_type(&THREAD);
         pvm.register_data_type(&FILE);
         pvm.register_data_type(&SOCKET);
         pvm.register_data_type(&PIPE);
         pvm.register_data_type(&PTTY);
+        pvm.register_data_type(&SHM);
         pvm.register_ctx_type(&CTX);
     }
 
     fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
         match self {
             TraceEvent::Audit(box tr) => tr.parse(pvm),
-            TraceEvent::FBT(_) => Ok(()),
+            TraceEvent::FBT(fbt) => fbt.parse(pvm),
+        }
+    }
+
+    fn line_time(line: &[u8]) -> Option<i64> {
+        serde_json::from_slice::<Stamp>(line).ok()?.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use serde_json::{self, Value};
+
+    use data::{
+        node_types::{Node, NameNode},
+        rel_types::{PVMOps, Rel},
+        HasDst, HasID, HasSrc,
+    };
+    use views::{queue, DBTr, QueueReceiver};
+
+    use super::*;
+
+    const PRO: &str = "00000000-0000-0000-0000-000000000001";
+    const OBJ1: &str = "00000000-0000-0000-0000-0000000000a1";
+    const OBJ2: &str = "00000000-0000-0000-0000-0000000000a2";
+    const THR: &str = "00000000-0000-0000-0000-000000000002";
+
+    struct Trace {
+        nodes: Vec<Node>,
+        rels: Vec<Rel>,
+        updated: Vec<Rel>,
+        node_updates: Vec<Node>,
+    }
+
+    impl Trace {
+        fn ids(&self, uuid: &str) -> Vec<ID> {
+            let uuid = Uuid::parse_str(uuid).unwrap();
+            self.nodes
+                .iter()
+                .filter_map(|n| match n {
+                    Node::Data(d) if d.uuid() == uuid => Some(d.get_db_id()),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        fn ty(&self, uuid: &str) -> &'static str {
+            let uuid = Uuid::parse_str(uuid).unwrap();
+            self.nodes
+                .iter()
+                .filter_map(|n| match n {
+                    Node::Data(d) if d.uuid() == uuid => Some(d.ty().name),
+                    _ => None,
+                })
+                .next()
+                .unwrap()
+        }
+
+        fn meta(&self, uuid: &str, key: &str) -> Option<String> {
+            let uuid = Uuid::parse_str(uuid).unwrap();
+            self.node_updates
+                .iter()
+                .rev()
+                .chain(self.nodes.iter().rev())
+                .filter_map(|n| match n {
+                    Node::Data(d) if d.uuid() == uuid => d.meta.cur(key).map(|v| v.to_string()),
+                    _ => None,
+                })
+                .next()
+        }
+
+        fn inf(&self, op: PVMOps, src: &str, dst: &str) -> bool {
+            let (src, dst) = (self.ids(src), self.ids(dst));
+            self.rels.iter().any(|r| match r {
+                Rel::Inf(i) => {
+                    mem::discriminant(&i.pvm_op) == mem::discriminant(&op)
+                        && src.contains(&i.get_src())
+                        && dst.contains(&i.get_dst())
+                }
+                _ => false,
+            })
+        }
+
+        fn name_id(&self, path: &str) -> ID {
+            self.nodes
+                .iter()
+                .filter_map(|n| match n {
+                    Node::Name(NameNode::Path(id, p)) if p == path => Some(*id),
+                    _ => None,
+                })
+                .next()
+                .unwrap()
+        }
+
+        fn named(&self, uuid: &str, path: &str) -> bool {
+            let (ids, name) = (self.ids(uuid), self.name_id(path));
+            self.rels.iter().any(|r| match r {
+                Rel::Named(n) => ids.contains(&n.get_src()) && n.get_dst() == name,
+                _ => false,
+            })
+        }
+
+        fn unnamed(&self, uuid: &str, path: &str) -> bool {
+            let (ids, name) = (self.ids(uuid), self.name_id(path));
+            self.updated.iter().any(|r| match r {
+                Rel::Named(n) => {
+                    ids.contains(&n.get_src()) && n.get_dst() == name && n.end != ID::new(0)
+                }
+                _ => false,
+            })
+        }
+    }
+
+    fn collect(rx: &QueueReceiver<DBTr>) -> Trace {
+        let mut tr = Trace {
+            nodes: Vec::new(),
+            rels: Vec::new(),
+            updated: Vec::new(),
+            node_updates: Vec::new(),
+        };
+        for t in rx.try_iter() {
+            match t {
+                DBTr::CreateNode(n) => tr.nodes.push(n),
+                DBTr::CreateRel(r) => tr.rels.push(r),
+                DBTr::UpdateRel(r) => tr.updated.push(r),
+                DBTr::UpdateNode(n) => tr.node_updates.push(n),
+                DBTr::Sync(_) => {}
+            }
+        }
+        tr
+    }
+
+    fn event(evt: &str, extra: &str) -> AuditEvent {
+        let mut base: Value = serde_json::from_str(&format!(
+            r#"{{"event": "audit:event:{}:", "time": 1000, "pid": 7, "ppid": 1,
+                "tid": 100, "uid": 0, "exec": "test", "retval": 0,
+                "subjprocuuid": "{}", "subjthruuid": "{}",
+                "host": "00000000-0000-0000-0000-000000000003"}}"#,
+            evt, PRO, THR
+        )).unwrap();
+        if let (Value::Object(b), Value::Object(e)) = (&mut base, serde_json::from_str(extra).unwrap())
+        {
+            b.extend(e);
+        }
+        serde_json::from_value(base).unwrap()
+    }
+
+    fn run(evts: &[(&str, &str)]) -> Trace {
+        let (send, recv) = queue(100_000);
+        let mut pvm = PVM::new(send);
+        TraceEvent::init(&mut pvm);
+        for (evt, extra) in evts {
+            event(evt, extra).parse(&mut pvm).unwrap();
+        }
+        assert!(pvm.unparsed_events.is_empty());
+        collect(&recv)
+    }
+
+    #[test]
+    fn openat() {
+        let extra = format!(r#"{{"ret_objuuid1": "{}", "upath1": "/etc/motd"}}"#, OBJ1);
+        let tr = run(&[("aue_openat", &extra)]);
+        assert!(tr.named(OBJ1, "/etc/motd"));
+    }
+
+    #[test]
+    fn mkdir() {
+        let extra = format!(r#"{{"ret_objuuid1": "{}", "upath1": "/tmp/d"}}"#, OBJ1);
+        let tr = run(&[("aue_mkdir", &extra)]);
+        assert_eq!(tr.ty(OBJ1), "file");
+        assert!(tr.named(OBJ1, "/tmp/d"));
+    }
+
+    #[test]
+    fn rmdir() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}", "upath1": "/tmp/d"}}"#, OBJ1);
+        let tr = run(&[("aue_rmdir", &extra)]);
+        assert!(tr.unnamed(OBJ1, "/tmp/d"));
+    }
+
+    #[test]
+    fn symlink() {
+        let extra = format!(
+            r#"{{"ret_objuuid1": "{}", "upath1": "/etc/motd", "upath2": "/tmp/l"}}"#,
+            OBJ1
+        );
+        let tr = run(&[("aue_symlink", &extra)]);
+        assert!(tr.named(OBJ1, "/tmp/l"));
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+    }
+
+    #[test]
+    fn truncate() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}", "upath1": "/tmp/f"}}"#, OBJ1);
+        let tr = run(&[("aue_truncate", &extra)]);
+        assert!(tr.named(OBJ1, "/tmp/f"));
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+        assert_eq!(tr.ids(OBJ1).len(), 2);
+    }
+
+    #[test]
+    fn ftruncate() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}"}}"#, OBJ1);
+        let tr = run(&[("aue_ftruncate", &extra)]);
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+    }
+
+    #[test]
+    fn sendfile() {
+        let extra = format!(
+            r#"{{"arg_objuuid1": "{}", "arg_objuuid2": "{}", "fdpath": "/srv/index.html"}}"#,
+            OBJ1, OBJ2
+        );
+        let tr = run(&[("aue_sendfile", &extra)]);
+        assert!(tr.named(OBJ1, "/srv/index.html"));
+        assert_eq!(tr.ty(OBJ2), "socket");
+        assert!(tr.inf(PVMOps::Source, OBJ1, PRO));
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ2));
+    }
+
+    #[test]
+    fn readv() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}", "fdpath": "/tmp/f"}}"#, OBJ1);
+        let tr = run(&[("aue_readv", &extra)]);
+        assert!(tr.inf(PVMOps::Source, OBJ1, PRO));
+        assert!(tr.named(OBJ1, "/tmp/f"));
+    }
+
+    #[test]
+    fn kill() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}"}}"#, OBJ1);
+        let tr = run(&[("aue_kill", &extra)]);
+        assert_eq!(tr.ty(OBJ1), "process");
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+    }
+
+    #[test]
+    fn ptrace() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}"}}"#, OBJ1);
+        let tr = run(&[("aue_ptrace", &extra)]);
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+        assert!(tr.inf(PVMOps::Source, OBJ1, PRO));
+    }
+
+    #[test]
+    fn shm_open_unlink() {
+        let open = format!(r#"{{"ret_objuuid1": "{}", "upath1": "/shm0"}}"#, OBJ1);
+        let unlink = format!(r#"{{"arg_objuuid1": "{}", "upath1": "/shm0"}}"#, OBJ1);
+        let tr = run(&[("aue_shmopen", &open), ("aue_shmunlink", &unlink)]);
+        assert_eq!(tr.ty(OBJ1), "shm");
+        assert!(tr.named(OBJ1, "/shm0"));
+        assert!(tr.unnamed(OBJ1, "/shm0"));
+    }
+
+    #[test]
+    fn shmat_shmdt() {
+        let get = format!(r#"{{"ret_objuuid1": "{}"}}"#, OBJ1);
+        let at = format!(r#"{{"arg_objuuid1": "{}"}}"#, OBJ1);
+        let tr = run(&[
+            ("aue_shmget", &get),
+            ("aue_shmat", &at),
+            ("aue_shmctl", &at),
+            ("aue_shmdt", &at),
+        ]);
+        assert!(tr.inf(PVMOps::Source, OBJ1, PRO));
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+        assert_eq!(tr.ids(OBJ1).len(), 3);
+    }
+
+    #[test]
+    fn dup_fcntl() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}"}}"#, OBJ1);
+        let connect = format!(
+            r#"{{"arg_objuuid1": "{}", "address": "10.0.0.1", "port": 80}}"#,
+            OBJ1
+        );
+        let tr = run(&[
+            ("aue_dup", &extra),
+            ("aue_dup2", &extra),
+            ("aue_fcntl", &extra),
+            ("aue_connect", &connect),
+            ("aue_write", &extra),
+        ]);
+        assert_eq!(tr.ty(OBJ1), "socket");
+        assert_eq!(tr.ids(OBJ1).len(), 1);
+        assert!(tr.inf(PVMOps::Sink, PRO, OBJ1));
+    }
+
+    #[test]
+    fn thread_actor() {
+        let extra = format!(r#"{{"arg_objuuid1": "{}"}}"#, OBJ1);
+        let tr = run(&[("aue_read", &extra)]);
+        assert_eq!(tr.ty(THR), "thread");
+        assert_eq!(tr.meta(THR, "tid"), Some("100".to_string()));
+        assert!(tr.inf(PVMOps::Source, PRO, THR));
+        assert!(tr.inf(PVMOps::Source, OBJ1, PRO));
+    }
+
+    #[test]
+    fn exit_status() {
+        let tr = run(&[("aue_exit", r#"{"retval": 3, "time": 5000000000}"#)]);
+        assert_eq!(tr.meta(PRO, "exit_status"), Some("3".to_string()));
+        assert_eq!(
+            tr.meta(PRO, "exit_time"),
+            Some("1970-01-01T00:00:05+00:00".to_string())
+        );
+        assert!(tr.meta(THR, "exit_time").is_some());
+    }
+
+    #[test]
+    fn ppid_links_parent() {
+        let child = format!(
+            r#"{{"pid": 8, "ppid": 7, "subjprocuuid": "{}", "subjthruuid": "{}"}}"#,
+            OBJ1, OBJ2
+        );
+        let tr = run(&[("aue_close", "{}"), ("aue_close", &child)]);
+        assert_eq!(tr.meta(OBJ1, "ppid"), Some("7".to_string()));
+        assert!(tr.inf(PVMOps::Source, PRO, OBJ1));
+    }
+
+    #[test]
+    fn fork_registers_child() {
+        let fork = format!(r#"{{"ret_objuuid1": "{}", "retval": 8}}"#, OBJ1);
+        let tr = run(&[("aue_fork", &fork)]);
+        assert_eq!(tr.meta(OBJ1, "pid"), Some("8".to_string()));
+        assert_eq!(tr.meta(OBJ1, "ppid"), Some("7".to_string()));
+        assert!(tr.inf(PVMOps::Version, PRO, OBJ1));
+    }
+
+    #[test]
+    fn fbt_invalid_port() {
+        let (send, recv) = queue(100);
+        let mut pvm = PVM::new(send);
+        TraceEvent::init(&mut pvm);
+        for (lport, fport) in &[(-1, 80), (1234, 70000)] {
+            let fbt: TraceEvent = serde_json::from_str(&format!(
+                r#"{{"event": "fbt:kernel:tcp_connect:", "time": 1000, "so_uuid": "{}",
+                    "host": "00000000-0000-0000-0000-000000000003",
+                    "lport": {}, "fport": {}, "laddr": "10.0.0.1", "faddr": "10.0.0.2"}}"#,
+                OBJ1, lport, fport
+            )).unwrap();
+            match fbt.parse(&mut pvm) {
+                Err(PVMError::InvalidField { .. }) => {}
+                r => panic!("unexpected {:?}", r),
+            }
         }
+        assert!(collect(&recv).ids(OBJ1).is_empty());
+    }
+
+    #[test]
+    fn line_time() {
+        assert_eq!(TraceEvent::line_time(br#"{"event": "x", "time": 5000}"#), Some(5000));
+        assert_eq!(TraceEvent::line_time(b"["), None);
     }
 }
@@ -0,0 +1,863 @@
+use std::{collections::HashMap, convert::TryFrom, fmt};
+
+use chrono::{TimeZone, Utc};
+use serde::{
+    de::{Deserializer, Error},
+    Deserialize,
+};
+use serde_json::{self, Map, Value};
+use uuid::Uuid;
+
+use data::{
+    node_types::{ConcreteType, ContextType, Name},
+    ID,
+};
+
+use ingest::{
+    pvm::{PVMError, PVM},
+    Parseable,
+};
+
+use super::cadets::{FILE, PIPE, PROCESS, SOCKET};
+
+lazy_static! {
+    static ref CTX: ContextType = ContextType {
+        name: "cdm_context",
+        props: vec!["time", "event", "host", "sequence"],
+    };
+}
+
+fn union<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr<T> {
+        Tagged(HashMap<String, T>),
+        Plain(T),
+    }
+    Ok(match Option::<Repr<T>>::deserialize(d)? {
+        Some(Repr::Tagged(m)) => m.into_iter().next().map(|(_, v)| v),
+        Some(Repr::Plain(v)) => Some(v),
+        None => None,
+    })
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseObject {
+    #[serde(default, deserialize_with = "union")]
+    pub host_id: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub properties: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Subject {
+    pub uuid: Uuid,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub cid: i32,
+    #[serde(default, deserialize_with = "union")]
+    pub parent_subject: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub host_id: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub start_timestamp_nanos: Option<i64>,
+    #[serde(default, deserialize_with = "union")]
+    pub cmd_line: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileObject {
+    pub uuid: Uuid,
+    #[serde(default)]
+    pub base_object: BaseObject,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NetFlowObject {
+    pub uuid: Uuid,
+    #[serde(default)]
+    pub base_object: BaseObject,
+    #[serde(default, deserialize_with = "union")]
+    pub local_address: Option<String>,
+    #[serde(default, deserialize_with = "union")]
+    pub local_port: Option<i32>,
+    #[serde(default, deserialize_with = "union")]
+    pub remote_address: Option<String>,
+    #[serde(default, deserialize_with = "union")]
+    pub remote_port: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UnnamedPipeObject {
+    pub uuid: Uuid,
+    #[serde(default)]
+    pub base_object: BaseObject,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub uuid: Uuid,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default, deserialize_with = "union")]
+    pub sequence: Option<i64>,
+    #[serde(default, deserialize_with = "union")]
+    pub subject: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub predicate_object: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub predicate_object_path: Option<String>,
+    #[serde(default, deserialize_with = "union")]
+    pub predicate_object2: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub predicate_object2_path: Option<String>,
+    pub timestamp_nanos: i64,
+    #[serde(default, deserialize_with = "union")]
+    pub size: Option<i64>,
+    #[serde(default, deserialize_with = "union")]
+    pub host_id: Option<Uuid>,
+    #[serde(default, deserialize_with = "union")]
+    pub properties: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug)]
+pub enum Datum {
+    Subject(Subject),
+    FileObject(FileObject),
+    NetFlowObject(NetFlowObject),
+    UnnamedPipeObject(UnnamedPipeObject),
+    Event(Box<Event>),
+    Other(String),
+}
+
+fn datum<'de, D: Deserializer<'de>>(d: D) -> Result<Datum, D::Error> {
+    let m = HashMap::<String, Value>::deserialize(d)?;
+    let (k, v) = m
+        .into_iter()
+        .next()
+        .ok_or_else(|| D::Error::custom("empty datum"))?;
+    let kind = k.rsplit('.').next().unwrap_or(&k);
+    Ok(match kind {
+        "Subject" => Datum::Subject(serde_json::from_value(v).map_err(D::Error::custom)?),
+        "FileObject" => Datum::FileObject(serde_json::from_value(v).map_err(D::Error::custom)?),
+        "NetFlowObject" => {
+            Datum::NetFlowObject(serde_json::from_value(v).map_err(D::Error::custom)?)
+        }
+        "UnnamedPipeObject" => {
+            Datum::UnnamedPipeObject(serde_json::from_value(v).map_err(D::Error::custom)?)
+        }
+        "Event" => Datum::Event(Box::new(
+            serde_json::from_value(v).map_err(D::Error::custom)?,
+        )),
+        _ => Datum::Other(kind.to_string()),
+    })
+}
+
+pub fn datum_kind(obj: &Map<String, Value>) -> &'static str {
+    let ty = obj.get("type").and_then(|t| t.as_str()).unwrap_or("");
+    if obj.contains_key("timestampNanos") && obj.contains_key("subject") {
+        "Event"
+    } else if obj.contains_key("cid") {
+        "Subject"
+    } else if obj.contains_key("localAddress") {
+        "NetFlowObject"
+    } else if obj.contains_key("sourceFileDescriptor") || obj.contains_key("sourceUUID") {
+        "UnnamedPipeObject"
+    } else if ty.starts_with("FILE_OBJECT") {
+        "FileObject"
+    } else if obj.contains_key("hostName") {
+        "Host"
+    } else if obj.contains_key("userId") {
+        "Principal"
+    } else {
+        "Unknown"
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CdmRecord {
+    #[serde(deserialize_with = "datum")]
+    pub datum: Datum,
+}
+
+impl fmt::Display for CdmRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CdmRecord({:?})", self.datum)
+    }
+}
+
+fn new_ctx(
+    pvm: &mut PVM,
+    event: &str,
+    host: Option<Uuid>,
+    time: Option<i64>,
+    seq: Option<i64>,
+) -> Result<(), PVMError> {
+    let mut cont = hashmap!("event" => event.to_string());
+    if let Some(h) = host {
+        cont.insert("host", h.hyphenated().to_string());
+    }
+    if let Some(t) = time {
+        if let Some(ts) = Utc
+            .timestamp_opt(t / 1_000_000_000, (t % 1_000_000_000) as u32)
+            .single()
+        {
+            cont.insert("time", ts.to_rfc3339());
+        }
+    }
+    if let Some(s) = seq {
+        cont.insert("sequence", s.to_string());
+    }
+    pvm.new_ctx(&CTX, cont)
+}
+
+impl Subject {
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        if self.ty != "SUBJECT_PROCESS" {
+            pvm.unparsed_event(&self.ty);
+            return Ok(());
+        }
+        new_ctx(pvm, "Subject", self.host_id, self.start_timestamp_nanos, None)?;
+        if let Some(puuid) = self.parent_subject {
+            let par = pvm.declare(&PROCESS, puuid, None)?;
+            let pro = pvm.derive(par, self.uuid)?;
+            pvm.meta(pro, "pid", &self.cid)?;
+            if let Some(ref cmdline) = self.cmd_line {
+                pvm.meta(pro, "cmdline", cmdline)?;
+            }
+            pvm.source(pro, par)?;
+        } else {
+            let mut init = hashmap!("pid" => self.cid.to_string());
+            if let Some(ref cmdline) = self.cmd_line {
+                init.insert("cmdline", cmdline.clone());
+            }
+            pvm.declare(&PROCESS, self.uuid, Some(init))?;
+        }
+        Ok(())
+    }
+}
+
+impl FileObject {
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        new_ctx(pvm, "FileObject", self.base_object.host_id, None, None)?;
+        let ty: &'static ConcreteType = if self.ty == "FILE_OBJECT_UNIX_SOCKET" {
+            &SOCKET
+        } else {
+            &FILE
+        };
+        let f = pvm.declare(ty, self.uuid, None)?;
+        let path = self
+            .base_object
+            .properties
+            .as_ref()
+            .and_then(|p| p.get("path").or_else(|| p.get("filename")));
+        if let Some(pth) = path {
+            pvm.name(f, Name::Path(pth.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+impl NetFlowObject {
+    fn endpoint(
+        &self,
+        addr: &Option<String>,
+        field: &'static str,
+        port: Option<i32>,
+    ) -> Result<Option<(String, u16)>, PVMError> {
+        match (addr, port) {
+            (Some(a), Some(p)) => match u16::try_from(p) {
+                Ok(p) => Ok(Some((a.clone(), p))),
+                Err(_) => Err(PVMError::InvalidField {
+                    evt: "NetFlowObject".to_string(),
+                    field,
+                    value: p.to_string(),
+                }),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        let host = self.base_object.host_id;
+        let local = self.endpoint(&self.local_address, "localPort", self.local_port)?;
+        let remote = self.endpoint(&self.remote_address, "remotePort", self.remote_port)?;
+        new_ctx(pvm, "NetFlowObject", host, None, None)?;
+        let s = pvm.declare(&SOCKET, self.uuid, None)?;
+        match (host, local, remote) {
+            (Some(h), Some(l), Some(r)) => pvm.endpoints(s, h, l, r)?,
+            (_, _, Some((addr, port))) => {
+                pvm.name(s, Name::Net(addr, port))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl UnnamedPipeObject {
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        new_ctx(pvm, "UnnamedPipeObject", self.base_object.host_id, None, None)?;
+        pvm.declare(&PIPE, self.uuid, None)?;
+        Ok(())
+    }
+}
+
+impl Event {
+    fn missing(&self, field: &'static str) -> PVMError {
+        PVMError::MissingField {
+            evt: self.ty.clone(),
+            field,
+        }
+    }
+
+    fn path(&self) -> Option<&String> {
+        self.predicate_object_path
+            .as_ref()
+            .filter(|pth| *pth != "<unknown>")
+    }
+
+    fn object_type(&self) -> Option<&'static ConcreteType> {
+        match &self.ty[..] {
+            "EVENT_CONNECT" | "EVENT_ACCEPT" | "EVENT_SENDTO" | "EVENT_SENDMSG"
+            | "EVENT_RECVFROM" | "EVENT_RECVMSG" => Some(&SOCKET),
+            "EVENT_EXECUTE" | "EVENT_LINK" | "EVENT_MODIFY_FILE_ATTRIBUTES" | "EVENT_TRUNCATE" => {
+                Some(&FILE)
+            }
+            _ => self.path().map(|_| &*FILE),
+        }
+    }
+
+    // Objects are typed by their own records. An event that refers to an
+    // object not seen yet only declares it when the event or a path tells
+    // what the object is, otherwise the event is counted as unhandled.
+    fn object(&self, pvm: &mut PVM) -> Result<Option<ID>, PVMError> {
+        let ouuid = self
+            .predicate_object
+            .ok_or_else(|| self.missing("predicateObject"))?;
+        let o = match (pvm.lookup(&ouuid), self.object_type()) {
+            (Some(o), _) => o,
+            (None, Some(ty)) => pvm.declare(ty, ouuid, None)?,
+            (None, None) => {
+                pvm.unparsed_event(&format!("{}:unknown_object", self.ty));
+                return Ok(None);
+            }
+        };
+        if let Some(pth) = self.path() {
+            pvm.name(o, Name::Path(pth.clone()))?;
+        }
+        Ok(Some(o))
+    }
+
+    fn event_read(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(o) = self.object(pvm)? {
+            match self.size {
+                Some(n) => pvm.source_nbytes(pro, o, n)?,
+                None => pvm.source(pro, o)?,
+            };
+        }
+        Ok(())
+    }
+
+    fn event_write(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(o) = self.object(pvm)? {
+            match self.size {
+                Some(n) => pvm.sinkstart_nbytes(pro, o, n)?,
+                None => pvm.sinkstart(pro, o)?,
+            };
+        }
+        Ok(())
+    }
+
+    fn event_open(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        self.object(pvm)?;
+        Ok(())
+    }
+
+    fn event_close(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(o) = self.object(pvm)? {
+            pvm.sinkend(pro, o)?;
+        }
+        Ok(())
+    }
+
+    fn event_fork(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let chuuid = self
+            .predicate_object
+            .ok_or_else(|| self.missing("predicateObject"))?;
+        let ch = pvm.derive(pro, chuuid)?;
+        pvm.source(ch, pro)?;
+        Ok(())
+    }
+
+    fn event_exec(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let bin = self.object(pvm)?;
+        if let Some(cmdline) = self.properties.as_ref().and_then(|p| p.get("cmdLine")) {
+            pvm.meta(pro, "cmdline", cmdline)?;
+        }
+        if let Some(bin) = bin {
+            pvm.source(pro, bin)?;
+        }
+        Ok(())
+    }
+
+    fn event_exit(&self, subj: Uuid, pvm: &mut PVM) -> Result<(), PVMError> {
+        pvm.release(&subj);
+        Ok(())
+    }
+
+    fn event_rename(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fuuid = self
+            .predicate_object
+            .ok_or_else(|| self.missing("predicateObject"))?;
+        let src = self
+            .predicate_object_path
+            .clone()
+            .ok_or_else(|| self.missing("predicateObjectPath"))?;
+        let dst = self
+            .predicate_object2_path
+            .clone()
+            .ok_or_else(|| self.missing("predicateObject2Path"))?;
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.unname(f, Name::Path(src))?;
+        pvm.name(f, Name::Path(dst))?;
+        Ok(())
+    }
+
+    fn event_link(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let (Some(f), Some(pth)) = (self.object(pvm)?, &self.predicate_object2_path) {
+            pvm.name(f, Name::Path(pth.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn event_unlink(&self, _pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fuuid = self
+            .predicate_object
+            .ok_or_else(|| self.missing("predicateObject"))?;
+        let pth = self
+            .predicate_object_path
+            .clone()
+            .ok_or_else(|| self.missing("predicateObjectPath"))?;
+        let f = pvm.declare(&FILE, fuuid, None)?;
+        pvm.unname(f, Name::Path(pth))?;
+        Ok(())
+    }
+
+    fn event_modify(&self, pro: ID, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(f) = self.object(pvm)? {
+            pvm.sink(pro, f)?;
+        }
+        Ok(())
+    }
+
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        new_ctx(
+            pvm,
+            &self.ty,
+            self.host_id,
+            Some(self.timestamp_nanos),
+            self.sequence,
+        )?;
+        let subj = match self.subject {
+            Some(s) => s,
+            None => {
+                pvm.unparsed_event(&self.ty);
+                return Ok(());
+            }
+        };
+        let pro = pvm.declare(&PROCESS, subj, None)?;
+        match &self.ty[..] {
+            "EVENT_READ" | "EVENT_RECVFROM" | "EVENT_RECVMSG" => self.event_read(pro, pvm),
+            "EVENT_WRITE" | "EVENT_SENDTO" | "EVENT_SENDMSG" => self.event_write(pro, pvm),
+            "EVENT_OPEN" | "EVENT_CREATE_OBJECT" | "EVENT_CONNECT" | "EVENT_ACCEPT" => {
+                self.event_open(pro, pvm)
+            }
+            "EVENT_CLOSE" => self.event_close(pro, pvm),
+            "EVENT_FORK" | "EVENT_CLONE" => self.event_fork(pro, pvm),
+            "EVENT_EXECUTE" => self.event_exec(pro, pvm),
+            "EVENT_EXIT" => self.event_exit(subj, pvm),
+            "EVENT_RENAME" => self.event_rename(pro, pvm),
+            "EVENT_LINK" => self.event_link(pro, pvm),
+            "EVENT_UNLINK" => self.event_unlink(pro, pvm),
+            "EVENT_MODIFY_FILE_ATTRIBUTES" | "EVENT_TRUNCATE" => self.event_modify(pro, pvm),
+            _ => {
+                pvm.unparsed_event(&self.ty);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatumStamp {
+    timestamp_nanos: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct Stamp {
+    datum: HashMap<String, DatumStamp>,
+}
+
+impl Parseable for CdmRecord {
+    fn init(pvm: &mut PVM) {
+        pvm.register_data_type(&PROCESS);
+        pvm.register_data_type(&FILE);
+        pvm.register_data_type(&SOCKET);
+        pvm.register_data_type(&PIPE);
+        pvm.register_ctx_type(&CTX);
+    }
+
+    fn parse(&self, pvm: &mut PVM) -> Result<(), PVMError> {
+        match self.datum {
+            Datum::Subject(ref s) => s.parse(pvm),
+            Datum::FileObject(ref f) => f.parse(pvm),
+            Datum::NetFlowObject(ref n) => n.parse(pvm),
+            Datum::UnnamedPipeObject(ref p) => p.parse(pvm),
+            Datum::Event(box ref e) => e.parse(pvm),
+            Datum::Other(ref kind) => {
+                pvm.unparsed_event(kind);
+                Ok(())
+            }
+        }
+    }
+
+    fn line_time(line: &[u8]) -> Option<i64> {
+        let stamp = serde_json::from_slice::<Stamp>(line).ok()?;
+        stamp.datum.values().next()?.timestamp_nanos
+    }
+}
+
+#[cfg(feature = "avro")]
+pub mod avro {
+    use std::{io::Read, mem};
+
+    use avro_rs::{types::Value as AvroValue, Reader};
+    use serde_json::{self, Map, Value};
+    use uuid::Uuid;
+
+    use ingest::{
+        pvm::PVM, ErrorPolicy, ErrorSink, ErrorTracker, IngestError, IngestErrorKind,
+        IngestReport, Parseable, TraceFormat,
+    };
+
+    use super::{datum_kind, CdmRecord};
+
+    const AVRO_MAGIC: &[u8] = b"Obj\x01";
+
+    fn to_json(v: AvroValue) -> Value {
+        match v {
+            AvroValue::Null => Value::Null,
+            AvroValue::Boolean(b) => Value::from(b),
+            AvroValue::Int(i) => Value::from(i),
+            AvroValue::Long(l) => Value::from(l),
+            AvroValue::Float(f) => Value::from(f64::from(f)),
+            AvroValue::Double(d) => Value::from(d),
+            AvroValue::Fixed(16, ref b) if Uuid::from_bytes(b).is_ok() => {
+                Value::from(Uuid::from_bytes(b).unwrap().hyphenated().to_string())
+            }
+            AvroValue::Bytes(b) | AvroValue::Fixed(_, b) => {
+                Value::Array(b.into_iter().map(Value::from).collect())
+            }
+            AvroValue::String(s) | AvroValue::Enum(_, s) => Value::String(s),
+            AvroValue::Union(box v) => to_json(v),
+            AvroValue::Array(a) => Value::Array(a.into_iter().map(to_json).collect()),
+            AvroValue::Map(m) => Value::Object(m.into_iter().map(|(k, v)| (k, to_json(v))).collect()),
+            AvroValue::Record(r) => {
+                Value::Object(r.into_iter().map(|(k, v)| (k, to_json(v))).collect())
+            }
+        }
+    }
+
+    fn from_avro(v: AvroValue) -> Result<CdmRecord, serde_json::Error> {
+        let mut rec = to_json(v);
+        if let Some(datum) = rec.get_mut("datum") {
+            let kind = datum.as_object().map_or("Unknown", datum_kind);
+            let mut wrapped = Map::new();
+            wrapped.insert(kind.to_string(), mem::replace(datum, Value::Null));
+            *datum = Value::Object(wrapped);
+        }
+        serde_json::from_value(rec)
+    }
+
+    pub struct CdmAvroFormat;
+
+    impl TraceFormat for CdmAvroFormat {
+        fn name(&self) -> &'static str {
+            "cdm_avro"
+        }
+
+        fn desc(&self) -> &'static str {
+            "TC Common Data Model records in Avro container files."
+        }
+
+        fn probe(&self, sample: &[u8]) -> bool {
+            sample.starts_with(AVRO_MAGIC)
+        }
+
+        // Avro blocks are binary, the line framing cannot skip them.
+        fn resumable(&self) -> bool {
+            false
+        }
+
+        fn ingest(
+            &self,
+            stream: Box<Read>,
+            pvm: &mut PVM,
+            policy: ErrorPolicy,
+            sink: &mut ErrorSink,
+        ) -> IngestReport {
+            let mut tracker = ErrorTracker::new(policy, sink);
+            CdmRecord::init(pvm);
+            let reader = match Reader::new(stream) {
+                Ok(r) => r,
+                Err(e) => {
+                    tracker.error(IngestError {
+                        kind: IngestErrorKind::Io,
+                        line: 0,
+                        msg: e.to_string(),
+                        record: None,
+                    });
+                    return tracker.report;
+                }
+            };
+            for (n, v) in reader.enumerate() {
+                if tracker.cancelled() {
+                    break;
+                }
+                tracker.line(n + 1);
+                let rec = v
+                    .map_err(|e| e.to_string())
+                    .and_then(|v| from_avro(v).map_err(|e| e.to_string()));
+                let err = match rec {
+                    Ok(rec) => {
+                        tracker.record();
+                        match rec.parse(pvm) {
+                            Ok(()) => continue,
+                            Err(e) => IngestError::from_pvm(n + 1, &e, rec.to_string()),
+                        }
+                    }
+                    Err(msg) => IngestError {
+                        kind: IngestErrorKind::Syntax,
+                        line: n + 1,
+                        msg,
+                        record: None,
+                    },
+                };
+                if tracker.error(err) {
+                    break;
+                }
+            }
+            let mut report = tracker.report;
+            report.unhandled.extend(pvm.unparsed_events.drain());
+            report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use data::{
+        node_types::{DataNode, Node},
+        rel_types::{PVMOps, Rel},
+        HasDst, HasID, HasSrc,
+    };
+    use views::{queue, DBTr};
+
+    const PRO: &str = "00000000-0000-0000-0000-000000000001";
+    const FIL: &str = "00000000-0000-0000-0000-0000000000f1";
+    const NET: &str = "00000000-0000-0000-0000-0000000000e1";
+    const PIP: &str = "00000000-0000-0000-0000-0000000000b1";
+    const NEW: &str = "00000000-0000-0000-0000-0000000000c1";
+    const UNK: &str = "00000000-0000-0000-0000-0000000000d1";
+
+    fn event(n: u8, ty: &str, obj: &str, extra: &str) -> String {
+        format!(
+            r#"{{"datum": {{"Event": {{"uuid": "00000000-0000-0000-0000-0000000001{:02x}",
+                "type": "{}", "subject": "{}", "predicateObject": "{}",
+                "timestampNanos": 1000{}}}}}}}"#,
+            n,
+            ty,
+            PRO,
+            obj,
+            extra
+        )
+    }
+
+    fn ingest(records: &[String]) -> (Vec<DataNode>, Vec<Rel>, HashMap<String, usize>) {
+        let (send, recv) = queue(100_000);
+        let mut pvm = PVM::new(send);
+        CdmRecord::init(&mut pvm);
+        for r in records {
+            serde_json::from_str::<CdmRecord>(r)
+                .unwrap()
+                .parse(&mut pvm)
+                .unwrap();
+        }
+        let (mut nodes, mut rels) = (Vec::new(), Vec::new());
+        for t in recv.try_iter() {
+            match t {
+                DBTr::CreateNode(Node::Data(n)) => nodes.push(n),
+                DBTr::CreateRel(Rel::Inf(i)) => rels.push(Rel::Inf(i)),
+                _ => {}
+            }
+        }
+        (nodes, rels, pvm.unparsed_events)
+    }
+
+    #[test]
+    fn parse_tagged_event() {
+        let rec: CdmRecord = serde_json::from_str(
+            r#"{"datum":{"com.bbn.tc.schema.avro.cdm18.Event":{
+                "uuid":"6E8BA1A5-1C2D-4B4E-8D7E-0F3A5B6C7D8E",
+                "sequence":{"long":12},"type":"EVENT_READ",
+                "subject":{"com.bbn.tc.schema.avro.cdm18.UUID":"0B5A1C2D-3E4F-5A6B-7C8D-9E0F1A2B3C4D"},
+                "predicateObject":{"com.bbn.tc.schema.avro.cdm18.UUID":"1C2D3E4F-5A6B-7C8D-9E0F-1A2B3C4D5E6F"},
+                "predicateObjectPath":{"string":"/etc/passwd"},
+                "predicateObject2":null,
+                "timestampNanos":1523627790000000000,
+                "size":{"long":4096}}},
+              "CDMVersion":"18","source":"SOURCE_FREEBSD_DTRACE_CADETS"}"#,
+        ).unwrap();
+        match rec.datum {
+            Datum::Event(box e) => {
+                assert_eq!(e.ty, "EVENT_READ");
+                assert_eq!(e.sequence, Some(12));
+                assert_eq!(e.size, Some(4096));
+                assert_eq!(e.predicate_object_path, Some("/etc/passwd".to_string()));
+                assert_eq!(e.predicate_object2, None);
+                assert!(e.subject.is_some());
+            }
+            d => panic!("unexpected datum {:?}", d),
+        }
+    }
+
+    #[test]
+    fn parse_plain_subject() {
+        let rec: CdmRecord = serde_json::from_str(
+            r#"{"datum":{"Subject":{
+                "uuid":"0B5A1C2D-3E4F-5A6B-7C8D-9E0F1A2B3C4D",
+                "type":"SUBJECT_PROCESS","cid":812,
+                "cmdLine":"sshd -D"}}}"#,
+        ).unwrap();
+        match rec.datum {
+            Datum::Subject(s) => {
+                assert_eq!(s.cid, 812);
+                assert_eq!(s.cmd_line, Some("sshd -D".to_string()));
+                assert_eq!(s.parent_subject, None);
+            }
+            d => panic!("unexpected datum {:?}", d),
+        }
+    }
+
+    #[test]
+    fn unknown_datum() {
+        let rec: CdmRecord = serde_json::from_str(
+            r#"{"datum":{"com.bbn.tc.schema.avro.cdm18.Host":{"hostName":"h"}}}"#,
+        ).unwrap();
+        match rec.datum {
+            Datum::Other(k) => assert_eq!(k, "Host"),
+            d => panic!("unexpected datum {:?}", d),
+        }
+    }
+
+    #[test]
+    fn map_objects() {
+        let (nodes, rels, unparsed) = ingest(&[
+            format!(
+                r#"{{"datum": {{"Subject": {{"uuid": "{}", "type": "SUBJECT_PROCESS",
+                    "cid": 7}}}}}}"#,
+                PRO
+            ),
+            format!(
+                r#"{{"datum": {{"FileObject": {{"uuid": "{}", "type": "FILE_OBJECT_FILE",
+                    "baseObject": {{"properties": {{"map": {{"path": "/etc/passwd"}}}}}}}}}}}}"#,
+                FIL
+            ),
+            format!(
+                r#"{{"datum": {{"NetFlowObject": {{"uuid": "{}", "localAddress": "10.0.0.1",
+                    "localPort": 5000, "remoteAddress": "10.0.0.2", "remotePort": 80}}}}}}"#,
+                NET
+            ),
+            format!(
+                r#"{{"datum": {{"UnnamedPipeObject": {{"uuid": "{}",
+                    "sourceFileDescriptor": 3}}}}}}"#,
+                PIP
+            ),
+            event(1, "EVENT_READ", FIL, r#", "size": 10"#),
+            event(2, "EVENT_WRITE", NET, ""),
+            event(3, "EVENT_WRITE", PIP, ""),
+            event(4, "EVENT_CONNECT", NEW, ""),
+            event(5, "EVENT_SENDTO", NEW, ""),
+            event(6, "EVENT_READ", UNK, ""),
+            event(7, "EVENT_CLOSE", NET, ""),
+        ]);
+        let ty = |uuid: &str| -> Vec<(String, &'static str)> {
+            let uuid = Uuid::parse_str(uuid).unwrap();
+            nodes
+                .iter()
+                .filter(|n| n.uuid() == uuid)
+                .map(|n| (n.pvm_ty().to_string(), n.ty().name))
+                .collect()
+        };
+        assert_eq!(ty(PRO), vec![("Actor".to_string(), "process")]);
+        assert_eq!(ty(FIL), vec![("Store".to_string(), "file")]);
+        assert_eq!(ty(NET), vec![("Conduit".to_string(), "socket")]);
+        assert_eq!(ty(PIP), vec![("Conduit".to_string(), "pipe")]);
+        assert_eq!(ty(NEW), vec![("Conduit".to_string(), "socket")]);
+        assert!(ty(UNK).is_empty());
+        assert_eq!(unparsed.get("EVENT_READ:unknown_object"), Some(&1));
+        let id = |uuid: &str| {
+            let uuid = Uuid::parse_str(uuid).unwrap();
+            nodes.iter().find(|n| n.uuid() == uuid).unwrap().get_db_id()
+        };
+        let inf = |op: PVMOps, src: &str, dst: &str| {
+            rels.iter().any(|r| match r {
+                Rel::Inf(i) => {
+                    format!("{:?}", i.pvm_op) == format!("{:?}", op)
+                        && i.get_src() == id(src)
+                        && i.get_dst() == id(dst)
+                }
+                _ => false,
+            })
+        };
+        assert!(inf(PVMOps::Source, FIL, PRO));
+        assert!(inf(PVMOps::Sink, PRO, NET));
+        assert!(inf(PVMOps::Sink, PRO, PIP));
+        assert!(inf(PVMOps::Sink, PRO, NEW));
+    }
+
+    #[test]
+    fn netflow_invalid_port() {
+        let (send, _recv) = queue(100);
+        let mut pvm = PVM::new(send);
+        CdmRecord::init(&mut pvm);
+        let rec: CdmRecord = serde_json::from_str(&format!(
+            r#"{{"datum": {{"NetFlowObject": {{"uuid": "{}", "localAddress": "10.0.0.1",
+                "localPort": 70000, "remoteAddress": "10.0.0.2", "remotePort": 80}}}}}}"#,
+            NET
+        )).unwrap();
+        match rec.parse(&mut pvm) {
+            Err(PVMError::InvalidField { field, value, .. }) => {
+                assert_eq!((field, &value[..]), ("localPort", "70000"))
+            }
+            r => panic!("unexpected result {:?}", r.map_err(|e| e.to_string())),
+        }
+        assert!(pvm.lookup(&Uuid::parse_str(NET).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,1364 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{BufRead, BufReader, Read},
+    net::{Ipv4Addr, Ipv6Addr},
+    str::{self, FromStr},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::{Uuid, NAMESPACE_DNS};
+
+use data::{
+    node_types::{ConcreteType, ContextType, Name},
+    ID,
+};
+
+use ingest::{
+    format::sample_lines,
+    pvm::{ConnectDir, PVMError, PVM},
+    ErrorPolicy, ErrorSink, ErrorTracker, IngestError, IngestErrorKind, IngestReport, LineTime,
+    TraceFormat,
+};
+
+use super::cadets::{FILE, PIPE, PROCESS, SOCKET};
+
+const PROBE_RECORDS: usize = 4;
+const REORDER_WINDOW: usize = 64;
+
+const AT_FDCWD: i32 = -100;
+const EINPROGRESS: i64 = -115;
+const CLONE_THREAD: u64 = 0x10000;
+const F_DUPFD: u64 = 0;
+const F_DUPFD_CLOEXEC: u64 = 1030;
+const ARCH_X86_64: &str = "c000003e";
+
+lazy_static! {
+    static ref CTX: ContextType = ContextType {
+        name: "linux_audit_context",
+        props: vec!["time", "event", "host", "serial"],
+    };
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.is_empty() || s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn split_fields(s: &str) -> HashMap<String, String> {
+    let mut ret = HashMap::new();
+    let mut rest = s.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = &rest[..eq];
+        rest = &rest[eq + 1..];
+        let end = match rest.chars().next() {
+            Some(q @ '"') | Some(q @ '\'') => rest[1..].find(q).map_or(rest.len(), |n| n + 2),
+            _ => rest.find(' ').unwrap_or(rest.len()),
+        };
+        ret.insert(key.to_string(), rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    ret
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub ty: String,
+    pub time: DateTime<Utc>,
+    pub serial: u64,
+    pub node: Option<String>,
+    fields: HashMap<String, String>,
+    raw: String,
+}
+
+impl Record {
+    pub fn parse(line: &str) -> Option<Record> {
+        // Records read from datagrams start with a record separator.
+        let line = line.trim_start_matches('\x1e');
+        let mut rest = match line.find('\x1d') {
+            Some(n) => &line[..n],
+            None => line,
+        };
+        let mut node = None;
+        if rest.starts_with("node=") {
+            let end = rest.find(' ')?;
+            node = Some(rest[5..end].to_string());
+            rest = rest[end..].trim_start();
+        }
+        if !rest.starts_with("type=") {
+            return None;
+        }
+        let end = rest.find(' ')?;
+        let ty = rest[5..end].to_string();
+        rest = rest[end..].trim_start();
+        if !rest.starts_with("msg=audit(") {
+            return None;
+        }
+        let close = rest.find("):")?;
+        let mut stamp = rest[10..close].splitn(2, ':');
+        let mut ts = stamp.next()?.splitn(2, '.');
+        let serial = stamp.next()?.parse().ok()?;
+        let secs = ts.next()?.parse().ok()?;
+        let millis: u32 = ts.next().unwrap_or("0").parse().ok()?;
+        let time = Utc.timestamp_opt(secs, millis * 1_000_000).single()?;
+        Some(Record {
+            ty,
+            time,
+            serial,
+            node,
+            fields: split_fields(&rest[close + 2..]),
+            raw: line.to_string(),
+        })
+    }
+
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(|v| &v[..])
+    }
+
+    pub fn num<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.raw(key)?.parse().ok()
+    }
+
+    pub fn hex(&self, key: &str) -> Option<u64> {
+        u64::from_str_radix(self.raw(key)?, 16).ok()
+    }
+
+    pub fn text(&self, key: &str) -> Option<String> {
+        let v = self.raw(key)?;
+        if v.len() >= 2 && v.starts_with('"') && v.ends_with('"') {
+            Some(v[1..v.len() - 1].to_string())
+        } else if v == "(null)" {
+            None
+        } else if let Some(b) = decode_hex(v) {
+            Some(String::from_utf8_lossy(&b).into_owned())
+        } else {
+            Some(v.to_string())
+        }
+    }
+
+    fn execve_arg(&self, n: usize) -> Option<String> {
+        if let Some(a) = self.text(&format!("a{}", n)) {
+            return Some(a);
+        }
+        let parts: Vec<String> = (0..)
+            .map(|i| self.text(&format!("a{}[{}]", n, i)))
+            .take_while(|p| p.is_some())
+            .map(|p| p.unwrap())
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.concat())
+        }
+    }
+}
+
+pub fn sockaddr_name(saddr: &str) -> Option<Name> {
+    let b = decode_hex(saddr)?;
+    if b.len() < 4 {
+        return None;
+    }
+    let family = u16::from(b[0]) | u16::from(b[1]) << 8;
+    let port = u16::from(b[2]) << 8 | u16::from(b[3]);
+    match family {
+        1 => {
+            let (abstr, path) = match b[2] {
+                0 => (true, &b[3..]),
+                _ => (false, &b[2..]),
+            };
+            let end = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+            let path = String::from_utf8_lossy(&path[..end]);
+            if abstr {
+                Some(Name::Path(format!("@{}", path)))
+            } else if path.is_empty() {
+                None
+            } else {
+                Some(Name::Path(path.into_owned()))
+            }
+        }
+        2 if b.len() >= 8 => Some(Name::Net(
+            Ipv4Addr::new(b[4], b[5], b[6], b[7]).to_string(),
+            port,
+        )),
+        10 if b.len() >= 24 => {
+            let mut addr = [0; 16];
+            addr.copy_from_slice(&b[8..24]);
+            Some(Name::Net(Ipv6Addr::from(addr).to_string(), port))
+        }
+        _ => None,
+    }
+}
+
+fn x86_64_syscall(nr: u32) -> Option<&'static str> {
+    Some(match nr {
+        0 => "read",
+        1 => "write",
+        2 => "open",
+        3 => "close",
+        17 => "pread64",
+        18 => "pwrite64",
+        19 => "readv",
+        20 => "writev",
+        22 => "pipe",
+        32 => "dup",
+        33 => "dup2",
+        40 => "sendfile",
+        41 => "socket",
+        42 => "connect",
+        43 => "accept",
+        44 => "sendto",
+        45 => "recvfrom",
+        46 => "sendmsg",
+        47 => "recvmsg",
+        49 => "bind",
+        50 => "listen",
+        53 => "socketpair",
+        56 => "clone",
+        57 => "fork",
+        58 => "vfork",
+        59 => "execve",
+        60 => "exit",
+        72 => "fcntl",
+        82 => "rename",
+        83 => "mkdir",
+        84 => "rmdir",
+        85 => "creat",
+        86 => "link",
+        87 => "unlink",
+        88 => "symlink",
+        90 => "chmod",
+        91 => "fchmod",
+        92 => "chown",
+        93 => "fchown",
+        94 => "lchown",
+        105 => "setuid",
+        106 => "setgid",
+        113 => "setreuid",
+        114 => "setregid",
+        117 => "setresuid",
+        119 => "setresgid",
+        231 => "exit_group",
+        257 => "openat",
+        258 => "mkdirat",
+        260 => "fchownat",
+        263 => "unlinkat",
+        264 => "renameat",
+        265 => "linkat",
+        266 => "symlinkat",
+        268 => "fchmodat",
+        288 => "accept4",
+        292 => "dup3",
+        293 => "pipe2",
+        295 => "preadv",
+        296 => "pwritev",
+        316 => "renameat2",
+        322 => "execveat",
+        327 => "preadv2",
+        328 => "pwritev2",
+        _ => return None,
+    })
+}
+
+pub struct AuditEvent {
+    pub serial: u64,
+    pub time: DateTime<Utc>,
+    pub line: usize,
+    pub records: Vec<Record>,
+}
+
+impl AuditEvent {
+    fn record(&self, ty: &str) -> Option<&Record> {
+        self.records.iter().find(|r| r.ty == ty)
+    }
+
+    fn paths(&self) -> Vec<&Record> {
+        let mut paths: Vec<&Record> = self.records.iter().filter(|r| r.ty == "PATH").collect();
+        paths.sort_by_key(|r| r.num::<usize>("item"));
+        paths
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, r) in self.records.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", r.raw)?;
+        }
+        Ok(())
+    }
+}
+
+struct Syscall<'a> {
+    name: &'a str,
+    evt: &'a AuditEvent,
+    rec: &'a Record,
+    host: Uuid,
+    pro: ID,
+    pro_uuid: Uuid,
+}
+
+impl<'a> Syscall<'a> {
+    fn missing(&self, field: &'static str) -> PVMError {
+        PVMError::MissingField {
+            evt: self.name.to_string(),
+            field,
+        }
+    }
+
+    fn arg(&self, n: usize) -> Result<u64, PVMError> {
+        let key = ["a0", "a1", "a2", "a3"][n];
+        self.rec.hex(key).ok_or_else(|| self.missing(key))
+    }
+
+    fn fd_arg(&self, n: usize) -> Result<i32, PVMError> {
+        Ok(self.arg(n)? as u32 as i32)
+    }
+
+    fn exit(&self) -> Result<i64, PVMError> {
+        self.rec.num("exit").ok_or_else(|| self.missing("exit"))
+    }
+
+    fn synth(&self, kind: &str) -> Uuid {
+        synth_uuid(
+            &self.host,
+            kind,
+            &format!("{}:{}", self.pro_uuid, self.evt.serial),
+        )
+    }
+
+    fn base_dir(&self) -> Option<String> {
+        if (self.name.ends_with("at") || self.name.ends_with("at2"))
+            && self.rec.hex("a0").map(|d| d as u32 as i32) != Some(AT_FDCWD)
+        {
+            return None;
+        }
+        self.evt.record("CWD").and_then(|r| r.text("cwd"))
+    }
+
+    fn path(&self, rec: &Record) -> Option<String> {
+        let name = rec.text("name")?;
+        if name.starts_with('/') {
+            Some(name)
+        } else {
+            let base = self.base_dir()?;
+            Some(format!("{}/{}", base.trim_end_matches('/'), name))
+        }
+    }
+
+    fn sockaddr(&self) -> Option<Name> {
+        self.evt
+            .record("SOCKADDR")
+            .and_then(|r| r.raw("saddr"))
+            .and_then(sockaddr_name)
+    }
+}
+
+fn synth_uuid(host: &Uuid, kind: &str, id: &str) -> Uuid {
+    Uuid::new_v5(host, &format!("{}:{}", kind, id))
+}
+
+type FdObj = (&'static ConcreteType, Uuid);
+
+struct AuditState {
+    default_host: Uuid,
+    procs: HashMap<(Uuid, i32), Uuid>,
+    fds: HashMap<Uuid, HashMap<i32, FdObj>>,
+    threads: HashMap<Uuid, usize>,
+}
+
+impl AuditState {
+    fn new(host: &str) -> Self {
+        AuditState {
+            default_host: Uuid::new_v5(&NAMESPACE_DNS, host),
+            procs: HashMap::new(),
+            fds: HashMap::new(),
+            threads: HashMap::new(),
+        }
+    }
+
+    fn fd_obj(
+        &mut self,
+        sc: &Syscall,
+        fd: i32,
+        ty: &'static ConcreteType,
+        pvm: &mut PVM,
+    ) -> Result<ID, PVMError> {
+        let host = sc.host;
+        let pro_uuid = sc.pro_uuid;
+        let (ty, uuid) = *self
+            .fds
+            .entry(pro_uuid)
+            .or_insert_with(HashMap::new)
+            .entry(fd)
+            .or_insert_with(|| (ty, synth_uuid(&host, "fd", &format!("{}:{}", pro_uuid, fd))));
+        pvm.declare(ty, uuid, None)
+    }
+
+    fn new_fd(
+        &mut self,
+        sc: &Syscall,
+        fd: i32,
+        obj: FdObj,
+        pvm: &mut PVM,
+    ) -> Result<ID, PVMError> {
+        self.fds
+            .entry(sc.pro_uuid)
+            .or_insert_with(HashMap::new)
+            .insert(fd, obj);
+        pvm.declare(obj.0, obj.1, None)
+    }
+
+    fn file(&self, sc: &Syscall, rec: &Record, pvm: &mut PVM) -> Result<Option<FdObj>, PVMError> {
+        let (dev, inode) = match (rec.raw("dev"), rec.raw("inode")) {
+            (Some(d), Some(i)) => (d, i),
+            _ => return Ok(None),
+        };
+        let uuid = synth_uuid(&sc.host, "file", &format!("{}:{}", dev, inode));
+        let mut init = HashMap::new();
+        for &(prop, key) in &[("owner_uid", "ouid"), ("owner_gid", "ogid"), ("mode", "mode")] {
+            if let Some(v) = rec.raw(key) {
+                init.insert(prop, v.to_string());
+            }
+        }
+        pvm.declare(&FILE, uuid, Some(init))?;
+        Ok(Some((&FILE, uuid)))
+    }
+
+    fn paths(&self, sc: &Syscall, pvm: &mut PVM) -> Result<Option<FdObj>, PVMError> {
+        let mut last = None;
+        for rec in sc.evt.paths() {
+            let nametype = rec.raw("nametype").unwrap_or("NORMAL");
+            if nametype == "PARENT" || nametype == "UNKNOWN" {
+                continue;
+            }
+            let obj = match self.file(sc, rec, pvm)? {
+                Some(o) => o,
+                None => continue,
+            };
+            let f = pvm.declare(obj.0, obj.1, None)?;
+            if let Some(pth) = sc.path(rec) {
+                if nametype == "DELETE" {
+                    pvm.unname(f, Name::Path(pth))?;
+                } else {
+                    pvm.name(f, Name::Path(pth))?;
+                }
+            }
+            if nametype != "DELETE" {
+                last = Some(obj);
+            }
+        }
+        Ok(last)
+    }
+
+    fn sys_read(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let f = self.fd_obj(sc, sc.fd_arg(0)?, &FILE, pvm)?;
+        if let Some(n) = sc.sockaddr() {
+            pvm.name(f, n)?;
+        }
+        pvm.source_nbytes(sc.pro, f, sc.exit()?)?;
+        Ok(())
+    }
+
+    fn sys_write(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let f = self.fd_obj(sc, sc.fd_arg(0)?, &FILE, pvm)?;
+        if let Some(n) = sc.sockaddr() {
+            pvm.name(f, n)?;
+        }
+        pvm.sinkstart_nbytes(sc.pro, f, sc.exit()?)?;
+        Ok(())
+    }
+
+    fn sys_sendfile(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let out = self.fd_obj(sc, sc.fd_arg(0)?, &FILE, pvm)?;
+        let inp = self.fd_obj(sc, sc.fd_arg(1)?, &FILE, pvm)?;
+        let n = sc.exit()?;
+        pvm.source_nbytes(sc.pro, inp, n)?;
+        pvm.sinkstart_nbytes(sc.pro, out, n)?;
+        Ok(())
+    }
+
+    fn sys_open(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some(obj) = self.paths(sc, pvm)? {
+            self.new_fd(sc, sc.exit()? as i32, obj, pvm)?;
+        }
+        Ok(())
+    }
+
+    fn sys_close(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fd = sc.fd_arg(0)?;
+        let obj = self.fds.get_mut(&sc.pro_uuid).and_then(|t| t.remove(&fd));
+        if let Some((ty, uuid)) = obj {
+            let f = pvm.declare(ty, uuid, None)?;
+            pvm.sinkend(sc.pro, f)?;
+        }
+        Ok(())
+    }
+
+    fn sys_dup(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let old = sc.fd_arg(0)?;
+        let new = sc.exit()? as i32;
+        let obj = self.fds.get(&sc.pro_uuid).and_then(|t| t.get(&old)).cloned();
+        if let Some(obj) = obj {
+            self.new_fd(sc, new, obj, pvm)?;
+        }
+        Ok(())
+    }
+
+    fn sys_fcntl(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        match sc.arg(1)? {
+            F_DUPFD | F_DUPFD_CLOEXEC => self.sys_dup(sc, pvm),
+            _ => Ok(()),
+        }
+    }
+
+    fn sys_pair(
+        &mut self,
+        sc: &Syscall,
+        ty: &'static ConcreteType,
+        pvm: &mut PVM,
+    ) -> Result<(), PVMError> {
+        let pair = sc.evt.record("FD_PAIR").ok_or_else(|| sc.missing("FD_PAIR"))?;
+        let fd0 = pair.num("fd0").ok_or_else(|| sc.missing("fd0"))?;
+        let fd1 = pair.num("fd1").ok_or_else(|| sc.missing("fd1"))?;
+        let p0 = self.new_fd(sc, fd0, (ty, sc.synth(&format!("{}:{}", ty.name, fd0))), pvm)?;
+        let p1 = self.new_fd(sc, fd1, (ty, sc.synth(&format!("{}:{}", ty.name, fd1))), pvm)?;
+        pvm.connect(p0, p1, ConnectDir::BiDirectional)?;
+        Ok(())
+    }
+
+    fn sys_socket(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let fd = sc.exit()? as i32;
+        self.new_fd(sc, fd, (&SOCKET, sc.synth(&format!("socket:{}", fd))), pvm)?;
+        Ok(())
+    }
+
+    fn sys_listen(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        self.fd_obj(sc, sc.fd_arg(0)?, &SOCKET, pvm)?;
+        Ok(())
+    }
+
+    fn sys_bind(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let s = self.fd_obj(sc, sc.fd_arg(0)?, &SOCKET, pvm)?;
+        let name = sc.sockaddr().ok_or_else(|| sc.missing("saddr"))?;
+        if let Name::Net(ref addr, port) = name {
+            pvm.bind_endpoint(s, sc.host, (addr.clone(), port))?;
+        }
+        pvm.name(s, name)?;
+        Ok(())
+    }
+
+    fn sys_connect(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let s = self.fd_obj(sc, sc.fd_arg(0)?, &SOCKET, pvm)?;
+        let name = sc.sockaddr().ok_or_else(|| sc.missing("saddr"))?;
+        if let Name::Net(ref addr, port) = name {
+            pvm.connect_endpoint(s, sc.host, (addr.clone(), port))?;
+        }
+        pvm.name(s, name)?;
+        Ok(())
+    }
+
+    fn sys_accept(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let l = self.fd_obj(sc, sc.fd_arg(0)?, &SOCKET, pvm)?;
+        let fd = sc.exit()? as i32;
+        let r = self.new_fd(sc, fd, (&SOCKET, sc.synth(&format!("socket:{}", fd))), pvm)?;
+        if let Some(n) = sc.sockaddr() {
+            pvm.name(r, n)?;
+        }
+        pvm.accept_endpoint(l, r, sc.host)?;
+        Ok(())
+    }
+
+    fn sys_fork(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        if sc.name == "clone" && sc.arg(0)? & CLONE_THREAD != 0 {
+            *self.threads.entry(sc.pro_uuid).or_insert(0) += 1;
+            return Ok(());
+        }
+        let child = sc.exit()? as i32;
+        let ch_uuid = synth_uuid(
+            &sc.host,
+            "process",
+            &format!("{}:{}", child, sc.evt.serial),
+        );
+        self.procs.insert((sc.host, child), ch_uuid);
+        if let Some(fds) = self.fds.get(&sc.pro_uuid).cloned() {
+            self.fds.insert(ch_uuid, fds);
+        }
+
+        let ch = pvm.derive(sc.pro, ch_uuid)?;
+        pvm.meta(ch, "pid", &child)?;
+        pvm.source(ch, sc.pro)?;
+        Ok(())
+    }
+
+    fn sys_exec(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        for rec in sc.evt.paths() {
+            if rec.raw("nametype").unwrap_or("NORMAL") != "NORMAL" {
+                continue;
+            }
+            if let Some((ty, uuid)) = self.file(sc, rec, pvm)? {
+                let bin = pvm.declare(ty, uuid, None)?;
+                if let Some(pth) = sc.path(rec) {
+                    pvm.name(bin, Name::Path(pth))?;
+                }
+                pvm.source(sc.pro, bin)?;
+            }
+        }
+        if let Some(args) = sc.evt.record("EXECVE") {
+            let argc = args.num("argc").unwrap_or(0);
+            let cmdline: Vec<String> = (0..argc).filter_map(|i| args.execve_arg(i)).collect();
+            pvm.meta(sc.pro, "cmdline", &cmdline.join(" "))?;
+        }
+        Ok(())
+    }
+
+    // Audit records carry the thread group id only, so exit ends the process
+    // once every thread cloned into it has exited.
+    fn sys_exit(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let pid = sc.rec.num("pid").ok_or_else(|| sc.missing("pid"))?;
+        if sc.name == "exit" {
+            if let Some(n) = self.threads.get_mut(&sc.pro_uuid).filter(|n| **n > 0) {
+                *n -= 1;
+                return Ok(());
+            }
+        }
+        self.threads.remove(&sc.pro_uuid);
+        self.procs.remove(&(sc.host, pid));
+        self.fds.remove(&sc.pro_uuid);
+        pvm.release(&sc.pro_uuid);
+        Ok(())
+    }
+
+    fn sys_namespace(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        self.paths(sc, pvm)?;
+        Ok(())
+    }
+
+    fn sys_chmod(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let mode = sc.arg(if sc.name == "fchmodat" { 2 } else { 1 })?;
+        if let Some((ty, uuid)) = self.paths(sc, pvm)? {
+            let f = pvm.declare(ty, uuid, None)?;
+            pvm.meta(f, "mode", &format!("{:o}", mode))?;
+            pvm.sink(sc.pro, f)?;
+        }
+        Ok(())
+    }
+
+    fn sys_fchmod(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let mode = sc.arg(1)?;
+        let f = self.fd_obj(sc, sc.fd_arg(0)?, &FILE, pvm)?;
+        pvm.meta(f, "mode", &format!("{:o}", mode))?;
+        pvm.sinkstart(sc.pro, f)?;
+        Ok(())
+    }
+
+    fn chown_meta(sc: &Syscall, f: ID, first: usize, pvm: &mut PVM) -> Result<(), PVMError> {
+        let uid = sc.arg(first)? as u32 as i32;
+        let gid = sc.arg(first + 1)? as u32 as i32;
+        if uid != -1 {
+            pvm.meta(f, "owner_uid", &uid)?;
+        }
+        if gid != -1 {
+            pvm.meta(f, "owner_gid", &gid)?;
+        }
+        Ok(())
+    }
+
+    fn sys_chown(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        if let Some((ty, uuid)) = self.paths(sc, pvm)? {
+            let f = pvm.declare(ty, uuid, None)?;
+            let first = if sc.name == "fchownat" { 2 } else { 1 };
+            AuditState::chown_meta(sc, f, first, pvm)?;
+            pvm.sink(sc.pro, f)?;
+        }
+        Ok(())
+    }
+
+    fn sys_fchown(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        let f = self.fd_obj(sc, sc.fd_arg(0)?, &FILE, pvm)?;
+        AuditState::chown_meta(sc, f, 1, pvm)?;
+        pvm.sinkstart(sc.pro, f)?;
+        Ok(())
+    }
+
+    fn sys_setid(&mut self, sc: &Syscall, pvm: &mut PVM) -> Result<(), PVMError> {
+        for &(prop, key) in &[
+            ("euid", "euid"),
+            ("ruid", "uid"),
+            ("suid", "suid"),
+            ("egid", "egid"),
+            ("rgid", "gid"),
+            ("sgid", "sgid"),
+        ] {
+            if let Some(v) = sc.rec.raw(key) {
+                pvm.meta(sc.pro, prop, v)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self, evt: &AuditEvent, pvm: &mut PVM) -> Result<(), PVMError> {
+        let rec = match evt.record("SYSCALL") {
+            Some(r) => r,
+            None => {
+                pvm.unparsed_event(&evt.records[0].ty);
+                return Ok(());
+            }
+        };
+        let raw_call = rec.raw("syscall").ok_or(PVMError::MissingField {
+            evt: rec.ty.clone(),
+            field: "syscall",
+        })?;
+        let name = match raw_call.parse::<u32>() {
+            Ok(nr) => match (rec.raw("arch"), x86_64_syscall(nr)) {
+                (Some(ARCH_X86_64), Some(name)) => name,
+                _ => {
+                    pvm.unparsed_event(&format!("syscall:{}", raw_call));
+                    return Ok(());
+                }
+            },
+            Err(_) => raw_call,
+        };
+        let host = match rec.node {
+            Some(ref node) => Uuid::new_v5(&NAMESPACE_DNS, node),
+            None => self.default_host,
+        };
+        let pid: i32 = rec.num("pid").ok_or(PVMError::MissingField {
+            evt: name.to_string(),
+            field: "pid",
+        })?;
+
+        pvm.new_ctx(
+            &CTX,
+            hashmap!(
+                "event" => name.to_string(),
+                "host" => host.hyphenated().to_string(),
+                "time" => evt.time.to_rfc3339(),
+                "serial" => evt.serial.to_string(),
+            ),
+        )?;
+
+        let serial = evt.serial;
+        let pro_uuid = *self
+            .procs
+            .entry((host, pid))
+            .or_insert_with(|| synth_uuid(&host, "process", &format!("{}:{}", pid, serial)));
+        let mut init = hashmap!("pid" => pid.to_string());
+        if let Some(exe) = rec.text("exe") {
+            init.insert("cmdline", exe);
+        }
+        let pro = pvm.declare(&PROCESS, pro_uuid, Some(init))?;
+
+        let sc = Syscall {
+            name,
+            evt,
+            rec,
+            host,
+            pro,
+            pro_uuid,
+        };
+
+        if rec.raw("success") == Some("no") && !(name == "connect" && sc.exit()? == EINPROGRESS)
+        {
+            return Ok(());
+        }
+
+        match name {
+            "read" | "pread64" | "readv" | "preadv" | "preadv2" | "recvfrom" | "recvmsg" => {
+                self.sys_read(&sc, pvm)
+            }
+            "write" | "pwrite64" | "writev" | "pwritev" | "pwritev2" | "sendto" | "sendmsg" => {
+                self.sys_write(&sc, pvm)
+            }
+            "sendfile" => self.sys_sendfile(&sc, pvm),
+            "open" | "openat" | "creat" => self.sys_open(&sc, pvm),
+            "close" => self.sys_close(&sc, pvm),
+            "dup" | "dup2" | "dup3" => self.sys_dup(&sc, pvm),
+            "fcntl" => self.sys_fcntl(&sc, pvm),
+            "pipe" | "pipe2" => self.sys_pair(&sc, &PIPE, pvm),
+            "socketpair" => self.sys_pair(&sc, &SOCKET, pvm),
+            "socket" => self.sys_socket(&sc, pvm),
+            "listen" => self.sys_listen(&sc, pvm),
+            "bind" => self.sys_bind(&sc, pvm),
+            "connect" => self.sys_connect(&sc, pvm),
+            "accept" | "accept4" => self.sys_accept(&sc, pvm),
+            "clone" | "fork" | "vfork" => self.sys_fork(&sc, pvm),
+            "execve" | "execveat" => self.sys_exec(&sc, pvm),
+            "exit" | "exit_group" => self.sys_exit(&sc, pvm),
+            "rename" | "renameat" | "renameat2" | "link" | "linkat" | "symlink" | "symlinkat"
+            | "unlink" | "unlinkat" | "mkdir" | "mkdirat" | "rmdir" => {
+                self.sys_namespace(&sc, pvm)
+            }
+            "chmod" | "fchmodat" => self.sys_chmod(&sc, pvm),
+            "fchmod" => self.sys_fchmod(&sc, pvm),
+            "chown" | "lchown" | "fchownat" => self.sys_chown(&sc, pvm),
+            "fchown" => self.sys_fchown(&sc, pvm),
+            "setuid" | "setgid" | "setreuid" | "setregid" | "setresuid" | "setresgid" => {
+                self.sys_setid(&sc, pvm)
+            }
+            _ => {
+                pvm.unparsed_event(name);
+                Ok(())
+            }
+        }
+    }
+}
+
+pub struct LinuxAuditFormat {
+    host: String,
+}
+
+impl LinuxAuditFormat {
+    pub fn new() -> Self {
+        LinuxAuditFormat::with_host("localhost")
+    }
+
+    pub fn with_host(host: &str) -> Self {
+        LinuxAuditFormat {
+            host: host.to_string(),
+        }
+    }
+}
+
+impl Default for LinuxAuditFormat {
+    fn default() -> Self {
+        LinuxAuditFormat::new()
+    }
+}
+
+fn flush(
+    state: &mut AuditState,
+    evt: &AuditEvent,
+    pvm: &mut PVM,
+    tracker: &mut ErrorTracker,
+) -> bool {
+    tracker.record();
+    match state.parse(evt, pvm) {
+        Ok(()) => false,
+        Err(e) => tracker.error(IngestError::from_pvm(evt.line, &e, evt.to_string())),
+    }
+}
+
+fn line_time(line: &[u8]) -> Option<i64> {
+    let rec = Record::parse(str::from_utf8(line).ok()?)?;
+    Some(rec.time.timestamp_nanos())
+}
+
+impl TraceFormat for LinuxAuditFormat {
+    fn name(&self) -> &'static str {
+        "linux_audit"
+    }
+
+    fn desc(&self) -> &'static str {
+        "Linux auditd raw log records."
+    }
+
+    fn probe(&self, sample: &[u8]) -> bool {
+        let mut recs = sample_lines(sample).take(PROBE_RECORDS).peekable();
+        recs.peek().is_some() && recs.all(|l| {
+            String::from_utf8(l.to_vec())
+                .ok()
+                .and_then(|l| Record::parse(&l))
+                .is_some()
+        })
+    }
+
+    fn ingest(
+        &self,
+        stream: Box<Read>,
+        pvm: &mut PVM,
+        policy: ErrorPolicy,
+        sink: &mut ErrorSink,
+    ) -> IngestReport {
+        let mut tracker = ErrorTracker::new(policy, sink);
+        let mut state = AuditState::new(&self.host);
+        let mut pending: BTreeMap<(u64, Option<String>), AuditEvent> = BTreeMap::new();
+        let mut last: Option<(u64, Option<String>)> = None;
+
+        pvm.register_data_type(&PROCESS);
+        pvm.register_data_type(&FILE);
+        pvm.register_data_type(&SOCKET);
+        pvm.register_data_type(&PIPE);
+        pvm.register_ctx_type(&CTX);
+
+        'outer: for (n, l) in BufReader::new(stream).lines().enumerate() {
+            if tracker.cancelled() {
+                break;
+            }
+            tracker.line(n + 1);
+            let err = match l {
+                Ok(ref l) if l.is_empty() => continue,
+                Ok(l) => match Record::parse(&l) {
+                    Some(rec) => {
+                        let key = (rec.serial, rec.node.clone());
+                        if last.as_ref().map_or(false, |k| *k != key) {
+                            let prev = last.take().unwrap();
+                            let standalone = pending
+                                .get(&prev)
+                                .map_or(false, |e| e.record("SYSCALL").is_none());
+                            if standalone {
+                                let evt = pending.remove(&prev).unwrap();
+                                if flush(&mut state, &evt, pvm, &mut tracker) {
+                                    break 'outer;
+                                }
+                            }
+                        }
+                        if rec.ty == "EOE" {
+                            if let Some(evt) = pending.remove(&key) {
+                                if flush(&mut state, &evt, pvm, &mut tracker) {
+                                    break 'outer;
+                                }
+                            }
+                            continue;
+                        }
+                        last = Some(key.clone());
+                        pending
+                            .entry(key)
+                            .or_insert_with(|| AuditEvent {
+                                serial: rec.serial,
+                                time: rec.time,
+                                line: n + 1,
+                                records: Vec::new(),
+                            }).records
+                            .push(rec);
+                        while pending.len() > REORDER_WINDOW {
+                            let first = pending.keys().next().cloned().unwrap();
+                            let evt = pending.remove(&first).unwrap();
+                            if flush(&mut state, &evt, pvm, &mut tracker) {
+                                break 'outer;
+                            }
+                        }
+                        continue;
+                    }
+                    None => IngestError {
+                        kind: IngestErrorKind::Syntax,
+                        line: n + 1,
+                        msg: "Malformed audit record".to_string(),
+                        record: Some(l),
+                    },
+                },
+                Err(perr) => IngestError {
+                    kind: IngestErrorKind::Io,
+                    line: n + 1,
+                    msg: perr.to_string(),
+                    record: None,
+                },
+            };
+            if tracker.error(err) {
+                break;
+            }
+        }
+        if !tracker.report.aborted {
+            let keys: Vec<_> = pending.keys().cloned().collect();
+            for k in keys {
+                let evt = pending.remove(&k).unwrap();
+                if flush(&mut state, &evt, pvm, &mut tracker) {
+                    break;
+                }
+            }
+        }
+        let mut report = tracker.report;
+        report.unhandled.extend(pvm.unparsed_events.drain());
+        report
+    }
+
+    fn line_time(&self) -> Option<LineTime> {
+        Some(line_time)
+    }
+
+    // Events are assembled by serial and node, and serials restart on every
+    // host, so merged records without a node would be mixed up.
+    fn check_merge(&self, sample: &[u8]) -> Result<(), String> {
+        let bare = sample_lines(sample)
+            .filter_map(|l| str::from_utf8(l).ok().and_then(Record::parse))
+            .any(|r| r.node.is_none());
+        if bare {
+            Err("linux_audit streams can only be merged if their records have node= fields".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    // The processes, fds and threads tracked in AuditState are not part of a
+    // checkpoint, so a resumed trace would give them new identities.
+    fn resumable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use data::{
+        node_types::{DataNode, NameNode, Node},
+        rel_types::{PVMOps, Rel},
+        HasDst, HasID, HasSrc,
+    };
+    use views::{queue, DBTr};
+
+    struct Trace {
+        nodes: Vec<DataNode>,
+        names: Vec<NameNode>,
+        rels: Vec<Rel>,
+        report: IngestReport,
+    }
+
+    impl Trace {
+        fn of(&self, ty: &str) -> Vec<&DataNode> {
+            self.nodes.iter().filter(|n| n.ty().name == ty).collect()
+        }
+
+        fn proc_(&self, pid: &str) -> Vec<Uuid> {
+            let mut procs: Vec<Uuid> = self
+                .of("process")
+                .into_iter()
+                .filter(|n| n.meta.cur("pid") == Some(pid))
+                .map(|n| n.uuid())
+                .collect();
+            procs.dedup();
+            procs
+        }
+
+        fn ids(&self, uuid: Uuid) -> Vec<ID> {
+            self.nodes
+                .iter()
+                .filter(|n| n.uuid() == uuid)
+                .map(|n| n.get_db_id())
+                .collect()
+        }
+
+        fn named(&self, name: &Name) -> Option<Uuid> {
+            let id = self.names.iter().find(|n| match (n, name) {
+                (NameNode::Path(_, p), Name::Path(q)) => p == q,
+                (NameNode::Net(_, a, p), Name::Net(b, q)) => a == b && p == q,
+                _ => false,
+            })?.get_db_id();
+            let src = self.rels.iter().find_map(|r| match r {
+                Rel::Named(n) if n.get_dst() == id => Some(n.get_src()),
+                _ => None,
+            })?;
+            self.nodes.iter().find(|n| n.get_db_id() == src).map(|n| n.uuid())
+        }
+
+        fn path(&self, path: &str) -> Uuid {
+            self.named(&Name::Path(path.to_string())).unwrap()
+        }
+
+        fn inf(&self, op: PVMOps, src: Uuid, dst: Uuid) -> bool {
+            let (src, dst) = (self.ids(src), self.ids(dst));
+            self.rels.iter().any(|r| match r {
+                Rel::Inf(i) => {
+                    format!("{:?}", i.pvm_op) == format!("{:?}", op)
+                        && src.contains(&i.get_src())
+                        && dst.contains(&i.get_dst())
+                }
+                _ => false,
+            })
+        }
+    }
+
+    fn run(lines: &[String]) -> Trace {
+        let (send, recv) = queue(100_000);
+        let mut pvm = PVM::new(send);
+        let input = lines.join("\n").into_bytes();
+        let report = LinuxAuditFormat::new().ingest(
+            Box::new(Cursor::new(input)),
+            &mut pvm,
+            ErrorPolicy::AbortOnFirst,
+            &mut |e: &IngestError| panic!("{}", e),
+        );
+        let mut tr = Trace {
+            nodes: Vec::new(),
+            names: Vec::new(),
+            rels: Vec::new(),
+            report,
+        };
+        for t in recv.try_iter() {
+            match t {
+                DBTr::CreateNode(Node::Data(n)) => tr.nodes.push(n),
+                DBTr::UpdateNode(Node::Data(n)) => {
+                    for m in tr.nodes.iter_mut().filter(|m| m.get_db_id() == n.get_db_id()) {
+                        *m = n.clone();
+                    }
+                }
+                DBTr::CreateNode(Node::Name(n)) => tr.names.push(n),
+                DBTr::CreateRel(r) => tr.rels.push(r),
+                _ => {}
+            }
+        }
+        tr
+    }
+
+    fn rec(ty: &str, serial: u64, fields: &str) -> String {
+        format!("type={} msg=audit(1000.{:03}:{}): {}", ty, serial, serial, fields)
+    }
+
+    fn sys(serial: u64, nr: u32, pid: i32, exit: i64, args: &str) -> String {
+        rec(
+            "SYSCALL",
+            serial,
+            &format!(
+                "arch=c000003e syscall={} success=yes exit={} {} pid={} exe=\"/bin/t\"",
+                nr, exit, args, pid
+            ),
+        )
+    }
+
+    fn eoe(serial: u64) -> String {
+        rec("EOE", serial, "")
+    }
+
+    fn open(serial: u64, pid: i32, fd: i32, name: &str, inode: u32) -> Vec<String> {
+        vec![
+            sys(serial, 257, pid, i64::from(fd), "a0=ffffff9c a1=0 a2=241"),
+            rec("CWD", serial, "cwd=\"/home\""),
+            rec(
+                "PATH",
+                serial,
+                "item=0 name=\"/home\" inode=2 dev=08:01 mode=040755 nametype=PARENT",
+            ),
+            rec(
+                "PATH",
+                serial,
+                &format!(
+                    "item=1 name=\"{}\" inode={} dev=08:01 mode=0100644 ouid=0 ogid=0 \
+                     nametype=CREATE",
+                    name, inode
+                ),
+            ),
+            eoe(serial),
+        ]
+    }
+
+    #[test]
+    fn parse_syscall_record() {
+        let rec = Record::parse(
+            "type=SYSCALL msg=audit(1364481363.243:24287): arch=c000003e syscall=2 \
+             success=no exit=-13 a0=7fffd19c5592 a1=0 pid=3538 comm=\"cat\" exe=\"/bin/cat\"",
+        ).unwrap();
+        assert_eq!(rec.ty, "SYSCALL");
+        assert_eq!(rec.serial, 24287);
+        assert_eq!(rec.time.timestamp_millis(), 1_364_481_363_243);
+        assert_eq!(rec.num::<i64>("exit"), Some(-13));
+        assert_eq!(rec.hex("a0"), Some(0x7fff_d19c_5592));
+        assert_eq!(rec.text("exe"), Some("/bin/cat".to_string()));
+    }
+
+    #[test]
+    fn parse_node_and_hex_text() {
+        let rec = Record::parse(
+            "node=web1 type=EXECVE msg=audit(1.5:7): argc=2 a0=\"ls\" a1=2F746D702F6120622063",
+        ).unwrap();
+        assert_eq!(rec.node, Some("web1".to_string()));
+        assert_eq!(rec.execve_arg(0), Some("ls".to_string()));
+        assert_eq!(rec.execve_arg(1), Some("/tmp/a b c".to_string()));
+        let rec = Record::parse("\x1enode=web1 type=EOE msg=audit(1.5:7): ").unwrap();
+        assert_eq!(rec.node, Some("web1".to_string()));
+    }
+
+    #[test]
+    fn reject_non_audit() {
+        assert!(Record::parse("{\"event\": \"audit:event:aue_read:\"}").is_none());
+        assert!(Record::parse("type=SYSCALL msg=foo").is_none());
+    }
+
+    #[test]
+    fn sockaddr_inet() {
+        assert_eq!(
+            sockaddr_name("02000050C0A800010000000000000000"),
+            Some(Name::Net("192.168.0.1".to_string(), 80))
+        );
+        assert_eq!(
+            sockaddr_name(concat!(
+                "0A000016",
+                "00000000",
+                "00000000000000000000000000000001",
+                "00000000"
+            )),
+            Some(Name::Net("::1".to_string(), 22))
+        );
+    }
+
+    #[test]
+    fn sockaddr_unix() {
+        assert_eq!(
+            sockaddr_name("01002F746D702F736F636B00"),
+            Some(Name::Path("/tmp/sock".to_string()))
+        );
+        assert_eq!(
+            sockaddr_name("010000616263"),
+            Some(Name::Path("@abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn open_write_close() {
+        let mut lines = open(1, 10, 3, "out.txt", 12);
+        lines.extend(vec![
+            sys(2, 1, 10, 5, "a0=3 a1=0 a2=5"),
+            eoe(2),
+            sys(3, 3, 10, 0, "a0=3"),
+            eoe(3),
+        ]);
+        let tr = run(&lines);
+        let f = tr.path("/home/out.txt");
+        let pro = tr.proc_("10")[0];
+        let versions: Vec<String> = tr
+            .nodes
+            .iter()
+            .filter(|n| n.uuid() == f)
+            .map(|n| n.pvm_ty().to_string())
+            .collect();
+        assert_eq!(versions, vec!["Store", "EditSession", "Store"]);
+        assert!(tr.inf(PVMOps::Sink, pro, f));
+        assert_eq!(tr.report.records, 3);
+    }
+
+    #[test]
+    fn assemble_out_of_order_records() {
+        let mut lines = open(1, 10, 3, "a", 12);
+        let path = lines.remove(3);
+        lines.retain(|l| !l.starts_with("type=EOE"));
+        lines.push(sys(2, 3, 10, 0, "a0=3"));
+        lines.push(path);
+        lines.push(rec("USER_LOGIN", 4, "pid=10 uid=0 res=success"));
+        lines.push(sys(5, 0, 10, 1, "a0=0"));
+        let tr = run(&lines);
+        tr.path("/home/a");
+        assert_eq!(tr.report.unhandled.get("USER_LOGIN"), Some(&1));
+        assert_eq!(tr.report.records, 4);
+    }
+
+    #[test]
+    fn reorder_window_flushes_old_events() {
+        let mut lines = open(1, 10, 3, "late", 12);
+        let path = lines.remove(3);
+        lines.retain(|l| !l.starts_with("type=EOE"));
+        for serial in 2..REORDER_WINDOW as u64 + 2 {
+            lines.push(sys(serial, 3, 10, 0, "a0=63"));
+        }
+        lines.push(path);
+        let tr = run(&lines);
+        assert!(tr.named(&Name::Path("/home/late".to_string())).is_none());
+        assert_eq!(tr.report.unhandled.get("PATH"), Some(&1));
+    }
+
+    #[test]
+    fn unknown_fd_is_a_file() {
+        let tr = run(&[sys(1, 0, 10, 8, "a0=7"), eoe(1)]);
+        let pro = tr.proc_("10")[0];
+        let files = tr.of("file");
+        assert_eq!(files.len(), 1);
+        assert!(tr.inf(PVMOps::Source, files[0].uuid(), pro));
+    }
+
+    #[test]
+    fn fork_inherits_fds() {
+        let mut lines = open(1, 10, 3, "shared", 12);
+        lines.extend(vec![
+            sys(2, 56, 10, 11, "a0=1200011"),
+            eoe(2),
+            sys(3, 1, 11, 4, "a0=3"),
+            eoe(3),
+        ]);
+        let tr = run(&lines);
+        let (parent, child) = (tr.proc_("10")[0], tr.proc_("11")[0]);
+        assert!(tr.inf(PVMOps::Version, parent, child));
+        assert!(tr.inf(PVMOps::Sink, child, tr.path("/home/shared")));
+    }
+
+    #[test]
+    fn pipe_and_dup() {
+        let tr = run(&[
+            sys(1, 293, 10, 0, "a0=7ffc a1=0"),
+            rec("FD_PAIR", 1, "fd0=3 fd1=4"),
+            eoe(1),
+            sys(2, 33, 10, 1, "a0=4 a1=1"),
+            eoe(2),
+            sys(3, 1, 10, 6, "a0=1"),
+            eoe(3),
+            sys(4, 0, 10, 6, "a0=3"),
+            eoe(4),
+        ]);
+        let pipes = tr.of("pipe");
+        assert_eq!(pipes.len(), 2);
+        let (p0, p1) = (pipes[0].uuid(), pipes[1].uuid());
+        let pro = tr.proc_("10")[0];
+        assert!(tr.inf(PVMOps::Connect, p0, p1));
+        assert!(tr.inf(PVMOps::Connect, p1, p0));
+        assert!(tr.inf(PVMOps::Sink, pro, p1));
+        assert!(tr.inf(PVMOps::Source, p0, pro));
+        assert!(tr.of("file").is_empty());
+    }
+
+    #[test]
+    fn merge_needs_nodes() {
+        let fmt = LinuxAuditFormat::new();
+        let lines = open(2, 10, 3, "a", 20).join("\n") + "\n";
+        assert!(fmt.check_merge(lines.as_bytes()).is_err());
+        let lines = open(2, 10, 3, "a", 20)
+            .iter()
+            .map(|l| format!("node=a {}", l))
+            .collect::<Vec<_>>()
+            .join("\n") + "\n";
+        assert!(fmt.check_merge(lines.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn connect_accept_across_hosts() {
+        let saddr = "saddr=020000500A0000010000000000000000";
+        let node = |n: &str, l: String| format!("node={} {}", n, l);
+        let tr = run(&[
+            node("b", sys(1, 41, 20, 3, "a0=2 a1=1")),
+            node("b", eoe(1)),
+            node("b", sys(2, 49, 20, 0, "a0=3")),
+            node("b", rec("SOCKADDR", 2, saddr)),
+            node("b", eoe(2)),
+            node("b", sys(3, 50, 20, 0, "a0=3")),
+            node("b", eoe(3)),
+            node("a", sys(1, 41, 10, 3, "a0=2 a1=1")),
+            node("a", eoe(1)),
+            node("a", sys(2, 42, 10, 0, "a0=3")),
+            node("a", rec("SOCKADDR", 2, saddr)),
+            node("a", eoe(2)),
+            node("b", sys(4, 43, 20, 4, "a0=3")),
+            node("b", eoe(4)),
+        ]);
+        let socks: Vec<Uuid> = tr.of("socket").iter().map(|n| n.uuid()).collect();
+        assert_eq!(socks.len(), 3);
+        let (listener, client, conn) = (socks[0], socks[1], socks[2]);
+        assert_eq!(tr.named(&Name::Net("10.0.0.1".to_string(), 80)), Some(listener));
+        assert!(!tr.inf(PVMOps::Connect, client, listener));
+        assert!(tr.inf(PVMOps::Connect, client, conn));
+        assert!(tr.inf(PVMOps::Connect, conn, client));
+    }
+
+    #[test]
+    fn exit_ends_process_after_its_threads() {
+        let tr = run(&[
+            sys(1, 0, 10, 0, "a0=0"),
+            sys(2, 60, 10, 0, "a0=0"),
+            sys(3, 0, 10, 0, "a0=0"),
+            sys(4, 56, 20, 21, "a0=3d0f00"),
+            sys(5, 60, 20, 0, "a0=0"),
+            sys(6, 0, 20, 0, "a0=0"),
+            sys(7, 60, 20, 0, "a0=0"),
+            sys(8, 0, 20, 0, "a0=0"),
+            sys(9, 231, 30, 0, "a0=0"),
+            sys(10, 0, 30, 0, "a0=0"),
+        ]);
+        assert_eq!(tr.proc_("10").len(), 2);
+        assert_eq!(tr.proc_("20").len(), 2);
+        assert_eq!(tr.proc_("30").len(), 2);
+        assert!(tr.report.unhandled.is_empty());
+    }
+}
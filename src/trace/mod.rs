@@ -71,3 +71,5 @@ macro_rules! fields_to_map {
 }
 
 pub mod cadets;
+pub mod cdm;
+pub mod linux_audit;
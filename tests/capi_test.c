@@ -0,0 +1,190 @@
+#include "opus.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                   \
+  do {                                                                \
+    if (!(cond)) {                                                    \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+              #cond);                                                 \
+      exit(1);                                                        \
+    }                                                                 \
+  } while (0)
+
+static const char* TRACE =
+    "{\"event\": \"audit:event:aue_read:\", \"time\": 1000, \"pid\": 7, "
+    "\"ppid\": 1, \"tid\": 100, \"uid\": 0, \"exec\": \"cat\", \"retval\": 4, "
+    "\"subjprocuuid\": \"00000000-0000-0000-0000-000000000001\", "
+    "\"subjthruuid\": \"00000000-0000-0000-0000-000000000001\", "
+    "\"host\": \"00000000-0000-0000-0000-000000000003\", "
+    "\"arg_objuuid1\": \"00000000-0000-0000-0000-0000000000a1\"}\n";
+
+static int trace_fd(void) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  CHECK(write(fds[1], TRACE, strlen(TRACE)) == (ssize_t)strlen(TRACE));
+  close(fds[1]);
+  return fds[0];
+}
+
+static void test_views(OpusHdl* hdl) {
+  View* views;
+  intptr_t num_views = opus_list_view_types(hdl, &views);
+  CHECK(num_views > 0);
+  opus_free_views(views, num_views);
+
+  CHECK(opus_create_view_by_name(hdl, "GraphView", NULL, 0) >= 0);
+
+  ViewInst* insts;
+  intptr_t num_insts = opus_list_view_inst(hdl, &insts);
+  CHECK(num_insts == 1);
+  opus_free_view_insts(insts, num_insts);
+
+  KeyVal* fmts;
+  intptr_t num_fmts = opus_list_formats(hdl, &fmts);
+  CHECK(num_fmts > 0);
+  opus_free_keyvals(fmts, num_fmts);
+}
+
+static void test_queries(OpusHdl* hdl) {
+  GraphNode* nodes;
+  intptr_t num_nodes =
+      opus_find_by_uuid(hdl, "00000000-0000-0000-0000-000000000001", 0, 0,
+                        &nodes);
+  CHECK(num_nodes == 1);
+  CHECK(strcmp(nodes[0].ty, "process") == 0);
+  uint64_t pro = nodes[0].id;
+  opus_free_nodes(nodes, num_nodes);
+
+  GraphRel* rels;
+  intptr_t num_rels = opus_neighbours(hdl, pro, Both, 0, 0, &rels);
+  CHECK(num_rels > 0);
+  opus_free_rels(rels, num_rels);
+  CHECK(opus_neighbours(hdl, pro, 7, 0, 0, &rels) == -EINVALIDARG);
+
+  num_nodes = opus_lineage(hdl, pro, Ancestors, 0, 0, 0, &nodes);
+  CHECK(num_nodes > 0);
+  opus_free_nodes(nodes, num_nodes);
+  CHECK(opus_lineage(hdl, pro, 2, 0, 0, 0, &nodes) == -EINVALIDARG);
+  CHECK(opus_set_error_policy(hdl, 3, 0) == -EINVALIDARG);
+
+  CHECK(opus_find_by_uuid(hdl, "not-a-uuid", 0, 0, &nodes) == -EINVALIDARG);
+}
+
+static void test_async(OpusHdl* hdl) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  IngestHdl* job = opus_ingest_fd_async(hdl, fds[0], "cadets");
+  CHECK(job != NULL);
+
+  IngestProgress prog;
+  CHECK(opus_ingest_progress(job, &prog) == 0);
+  CHECK(!prog.finished);
+
+  QueueDepth* depths;
+  intptr_t num_depths = opus_ingest_queue_depths(job, &depths);
+  CHECK(num_depths == 1);
+  opus_free_queue_depths(depths);
+
+  CHECK(opus_ingest_cancel(job) == 0);
+  close(fds[1]);
+  IngestStats stats;
+  CHECK(opus_ingest_wait(job, &stats) == 0);
+  CHECK(stats.records == 0);
+  CHECK(opus_ingest_progress(job, &prog) == 0);
+  CHECK(prog.finished && prog.cancelled);
+  opus_ingest_free(job);
+
+  CHECK(opus_ingest_progress(NULL, &prog) == -EINVALIDARG);
+}
+
+static void test_listen(OpusHdl* hdl) {
+  IngestHdl* job = opus_ingest_listen(hdl, "udp://127.0.0.1:0", "cadets");
+  CHECK(job != NULL);
+  CHECK(opus_ingest_cancel(job) == 0);
+  IngestStats stats;
+  CHECK(opus_ingest_wait(job, &stats) == 0);
+  CHECK(stats.records == 0);
+  opus_ingest_free(job);
+
+  CHECK(opus_ingest_listen(hdl, "ftp://localhost", NULL) == NULL);
+  CHECK(strstr(opus_last_error(), "Invalid listen address") != NULL);
+}
+
+static void test_merge(OpusHdl* hdl) {
+  int fds[2] = { trace_fd(), trace_fd() };
+  IngestStats stats;
+  CHECK(opus_ingest_fds(hdl, fds, 2, "cadets", &stats) == 0);
+  CHECK(stats.lines == 2 && stats.records == 2);
+  CHECK(opus_ingest_fds(hdl, NULL, 0, NULL, &stats) == -EINVALIDARG);
+}
+
+static void test_metrics(OpusHdl* hdl) {
+  KeyVal* metrics;
+  intptr_t num_metrics = opus_metrics(hdl, &metrics);
+  CHECK(num_metrics > 0);
+  int found = 0;
+  for (intptr_t i = 0; i < num_metrics; i++) {
+    if (strcmp(metrics[i].key, "pvm.nodes.Actor") == 0) {
+      found = 1;
+    }
+  }
+  CHECK(found);
+  opus_free_keyvals(metrics, num_metrics);
+
+  char* json = opus_metrics_json(hdl);
+  CHECK(json != NULL && strstr(json, "\"queue.pvm\"") != NULL);
+  opus_free_string(json);
+}
+
+static void test_checkpoint(OpusHdl* hdl, Config cfg) {
+  const char* path = "capi_test_checkpoint.json";
+  CHECK(opus_checkpoint(hdl, path) == 0);
+  CHECK(opus_restore(hdl, path) == -EUNKNOWN);
+  CHECK(opus_checkpoint(hdl, NULL) == -EINVALIDARG);
+
+  OpusHdl* resumed = opus_init(cfg);
+  CHECK(opus_start_pipeline(resumed) == 0);
+  CHECK(opus_restore(resumed, path) == 1);
+  CHECK(opus_shutdown_pipeline(resumed) == 0);
+  opus_cleanup(resumed);
+  unlink(path);
+}
+
+static void test_errors(OpusHdl* hdl) {
+  View* views;
+  CHECK(opus_list_view_types(NULL, &views) == -EINVALIDARG);
+  CHECK(opus_last_error() != NULL);
+  CHECK(opus_list_view_types(hdl, NULL) == -EINVALIDARG);
+  CHECK(opus_create_view_by_name(hdl, NULL, NULL, 0) == -EINVALIDARG);
+  CHECK(opus_create_view_by_name(hdl, "NoSuchView", NULL, 0) == -ENOVIEWWITHNAME);
+  CHECK(strstr(opus_last_error(), "NoSuchView") != NULL);
+  CHECK(opus_ingest_fd_fmt(hdl, 0, "no-such-format", NULL) == -EUNKNOWNFORMAT);
+  CHECK(opus_graph_view(hdl, 1000) == NULL);
+}
+
+int main(void) {
+  Config cfg = { Auto, "", "", "", true, 0 };
+  OpusHdl* hdl = opus_init(cfg);
+  CHECK(opus_start_pipeline(hdl) == 0);
+
+  test_errors(hdl);
+  test_views(hdl);
+  test_async(hdl);
+  test_listen(hdl);
+  test_merge(hdl);
+
+  CHECK(opus_ingest_fd(hdl, trace_fd()) == 0);
+  test_checkpoint(hdl, cfg);
+  CHECK(opus_shutdown_pipeline(hdl) == 0);
+
+  test_queries(hdl);
+  test_metrics(hdl);
+
+  opus_cleanup(hdl);
+  printf("capi_test: ok\n");
+  return 0;
+}